@@ -11,22 +11,147 @@
 
 use crate::util::clap_handlers;
 
-use libmedusa_zip::crawl as lib_crawl;
+use libmedusa_zip::{crawl as lib_crawl, digest as lib_digest, zip::StreamedEntry, EntryName};
 
 use clap::{
   builder::{TypedValueParser, ValueParserFactory},
-  Args,
+  Args, ValueEnum,
 };
+use displaydoc::Display;
 use regex::{Regex, RegexSet};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{
+  io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
+  sync::mpsc,
+};
 
-use std::{fmt, path::PathBuf};
+use std::{
+  fmt,
+  io::{self, Write},
+  num::NonZeroUsize,
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+  File,
+  Symlink,
+}
+
+impl From<lib_crawl::EntryKind> for EntryKind {
+  fn from(x: lib_crawl::EntryKind) -> Self {
+    match x {
+      lib_crawl::EntryKind::File => Self::File,
+      lib_crawl::EntryKind::Symlink => Self::Symlink,
+    }
+  }
+}
+
+impl From<EntryKind> for lib_crawl::EntryKind {
+  fn from(x: EntryKind) -> Self {
+    match x {
+      EntryKind::File => Self::File,
+      EntryKind::Symlink => Self::Symlink,
+    }
+  }
+}
+
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct EntryMetadata {
+  pub kind: EntryKind,
+  pub size: u64,
+  pub mode: u32,
+  pub mtime: SystemTime,
+}
+
+impl From<lib_crawl::EntryMetadata> for EntryMetadata {
+  fn from(x: lib_crawl::EntryMetadata) -> Self {
+    let lib_crawl::EntryMetadata {
+      kind,
+      size,
+      mode,
+      mtime,
+    } = x;
+    Self {
+      kind: kind.into(),
+      size,
+      mode,
+      mtime,
+    }
+  }
+}
+
+impl From<EntryMetadata> for lib_crawl::EntryMetadata {
+  fn from(x: EntryMetadata) -> Self {
+    let EntryMetadata {
+      kind,
+      size,
+      mode,
+      mtime,
+    } = x;
+    Self {
+      kind: kind.into(),
+      size,
+      mode,
+      mtime,
+    }
+  }
+}
+
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum DigestAlgorithm {
+  /// SHA-256.
+  Sha256,
+  /// BLAKE3.
+  Blake3,
+}
+
+impl From<DigestAlgorithm> for lib_digest::DigestAlgorithm {
+  fn from(x: DigestAlgorithm) -> Self {
+    match x {
+      DigestAlgorithm::Sha256 => Self::Sha256,
+      DigestAlgorithm::Blake3 => Self::Blake3,
+    }
+  }
+}
+
+
+/// Serialized as `<algorithm>:<hex digest>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContentDigest(pub lib_digest::ContentDigest);
+
+impl TryFrom<String> for ContentDigest {
+  type Error = lib_digest::ContentDigestParseError;
+
+  fn try_from(x: String) -> Result<Self, Self::Error> { Ok(Self(x.parse()?)) }
+}
+
+impl From<ContentDigest> for String {
+  fn from(x: ContentDigest) -> Self {
+    let ContentDigest(digest) = x;
+    digest.to_string()
+  }
+}
 
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ResolvedPath {
   pub unresolved_path: PathBuf,
   pub resolved_path: PathBuf,
+  /* Optional, so that crawl results written before metadata was recorded can
+   * still be read. */
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<EntryMetadata>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub digest: Option<ContentDigest>,
 }
 
 impl From<lib_crawl::ResolvedPath> for ResolvedPath {
@@ -34,10 +159,14 @@ impl From<lib_crawl::ResolvedPath> for ResolvedPath {
     let lib_crawl::ResolvedPath {
       unresolved_path,
       resolved_path,
+      metadata,
+      digest,
     } = x;
     Self {
       unresolved_path,
       resolved_path,
+      metadata: metadata.map(EntryMetadata::from),
+      digest: digest.map(ContentDigest),
     }
   }
 }
@@ -47,10 +176,53 @@ impl From<ResolvedPath> for lib_crawl::ResolvedPath {
     let ResolvedPath {
       unresolved_path,
       resolved_path,
+      metadata,
+      digest,
     } = x;
     Self {
       unresolved_path,
       resolved_path,
+      metadata: metadata.map(lib_crawl::EntryMetadata::from),
+      digest: digest.map(|ContentDigest(digest)| digest),
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolvedSymlink {
+  pub unresolved_path: PathBuf,
+  pub target: PathBuf,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<EntryMetadata>,
+}
+
+impl From<lib_crawl::ResolvedSymlink> for ResolvedSymlink {
+  fn from(x: lib_crawl::ResolvedSymlink) -> Self {
+    let lib_crawl::ResolvedSymlink {
+      unresolved_path,
+      target,
+      metadata,
+    } = x;
+    Self {
+      unresolved_path,
+      target,
+      metadata: metadata.map(EntryMetadata::from),
+    }
+  }
+}
+
+impl From<ResolvedSymlink> for lib_crawl::ResolvedSymlink {
+  fn from(x: ResolvedSymlink) -> Self {
+    let ResolvedSymlink {
+      unresolved_path,
+      target,
+      metadata,
+    } = x;
+    Self {
+      unresolved_path,
+      target,
+      metadata: metadata.map(lib_crawl::EntryMetadata::from),
     }
   }
 }
@@ -58,25 +230,397 @@ impl From<ResolvedPath> for lib_crawl::ResolvedPath {
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct CrawlResult {
+  /// If provided, the directory which each relative
+  /// [`ResolvedPath::resolved_path`] is relative to.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub root: Option<PathBuf>,
   pub real_file_paths: Vec<ResolvedPath>,
+  /* Omitted when empty, so that crawls without preserved symlinks produce the
+   * same JSON as before. */
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub symlinks: Vec<ResolvedSymlink>,
+  /// Digest over every entry, if the crawl computed digests. See
+  /// [`lib_crawl::CrawlResult::aggregate_digest()`].
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub aggregate_digest: Option<ContentDigest>,
 }
 
 impl From<lib_crawl::CrawlResult> for CrawlResult {
   fn from(x: lib_crawl::CrawlResult) -> Self {
-    let lib_crawl::CrawlResult { real_file_paths } = x;
+    let lib_crawl::CrawlResult {
+      real_file_paths,
+      symlinks,
+      ..
+    } = x;
     let real_file_paths: Vec<ResolvedPath> =
       real_file_paths.into_iter().map(|rp| rp.into()).collect();
-    Self { real_file_paths }
+    let symlinks: Vec<ResolvedSymlink> = symlinks.into_iter().map(|rs| rs.into()).collect();
+    Self {
+      root: None,
+      real_file_paths,
+      symlinks,
+      aggregate_digest: None,
+    }
   }
 }
 
 impl From<CrawlResult> for lib_crawl::CrawlResult {
   fn from(x: CrawlResult) -> Self {
-    let CrawlResult { real_file_paths } = x;
+    let CrawlResult {
+      root,
+      real_file_paths,
+      symlinks,
+      ..
+    } = x;
     let real_file_paths: Vec<lib_crawl::ResolvedPath> =
       real_file_paths.into_iter().map(|rp| rp.into()).collect();
-    Self { real_file_paths }
+    let symlinks: Vec<lib_crawl::ResolvedSymlink> =
+      symlinks.into_iter().map(|rs| rs.into()).collect();
+    let mut ret = Self {
+      real_file_paths,
+      symlinks,
+      skipped_files: Vec::new(),
+    };
+    if let Some(root) = root {
+      ret.join_root(&root);
+    }
+    ret
+  }
+}
+
+/// Writes a [`CrawlHeader`] line, then one [`CrawlRecord`] line per entry as
+/// each is found, then a [`CrawlRecord::Trailer`] line.
+pub struct NdjsonWriter<W> {
+  out: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+  pub fn new(mut out: W, root: Option<PathBuf>) -> Result<Self, CrawlFormatError> {
+    let header = CrawlHeader {
+      version: CRAWL_FORMAT_VERSION,
+      root,
+    };
+    Self::write_line(&mut out, &header)?;
+    Ok(Self { out })
+  }
+
+  pub fn write_entry(&mut self, entry: lib_crawl::CrawledEntry) -> Result<(), CrawlFormatError> {
+    let Self { out } = self;
+    Self::write_line(out, &CrawlRecord::from(entry))
+  }
+
+  /// Write the trailer, which marks the crawl as complete, and flush the
+  /// output.
+  pub fn finish(self, aggregate_digest: Option<ContentDigest>) -> Result<W, CrawlFormatError> {
+    let Self { mut out } = self;
+    let trailer = CrawlRecord::Trailer(CrawlTrailer { aggregate_digest });
+    Self::write_line(&mut out, &trailer)?;
+    out.flush()?;
+    Ok(out)
+  }
+
+  fn write_line(out: &mut W, value: &impl Serialize) -> Result<(), CrawlFormatError> {
+    serde_json::to_writer(&mut *out, value).map_err(CrawlFormatError::Write)?;
+    out.write_all(b"\n")?;
+    Ok(())
+  }
+}
+
+/// The version of the format written by [`NdjsonWriter`]. This must be
+/// incremented for any change which older versions could not read.
+pub const CRAWL_FORMAT_VERSION: u32 = 1;
+
+/// The first line of the format written by [`NdjsonWriter`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CrawlHeader {
+  pub version: u32,
+  /// See [`CrawlResult::root`].
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub root: Option<PathBuf>,
+}
+
+/// Each line after the [`CrawlHeader`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrawlRecord {
+  File(ResolvedPath),
+  Symlink(ResolvedSymlink),
+  /// Always the last line. A crawl which was cut off before this line is
+  /// rejected.
+  Trailer(CrawlTrailer),
+}
+
+impl From<lib_crawl::CrawledEntry> for CrawlRecord {
+  fn from(x: lib_crawl::CrawledEntry) -> Self {
+    match x {
+      lib_crawl::CrawledEntry::File(resolved_path) => Self::File(resolved_path.into()),
+      lib_crawl::CrawledEntry::Symlink(symlink) => Self::Symlink(symlink.into()),
+    }
+  }
+}
+
+/// Anything only known once the whole crawl has completed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CrawlTrailer {
+  /// See [`CrawlResult::aggregate_digest`].
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub aggregate_digest: Option<ContentDigest>,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum CrawlFormatError {
+  /// i/o error: {0}
+  Io(#[from] io::Error),
+  /// error writing crawl output: {0}
+  Write(#[source] serde_json::Error),
+  /// error parsing crawl json: {0}
+  Json(#[source] serde_json::Error),
+  /// error parsing crawl record at line {0}: {1}
+  Record(usize, #[source] serde_json::Error),
+  /// crawl record at line {0} has an invalid entry name: {1}
+  RecordEntryName(usize, #[source] lib_crawl::MedusaCrawlError),
+  /// crawl record at line {0} follows the trailer
+  RecordAfterTrailer(usize),
+  /// crawl output ended without a trailer, so it may be incomplete
+  MissingTrailer,
+  /// crawl format version {0} is not supported (expected version {1})
+  UnsupportedVersion(u32, u32),
+  /// crawl json has an invalid entry name: {0}
+  EntryName(#[source] lib_crawl::MedusaCrawlError),
+  /// path list entry {0} was not valid utf-8
+  PathListEncoding(usize),
+  /// path list entry {0} has no path after '=': {1:?}
+  PathListEmptyPath(usize, String),
+  /// path list entry {0} has no '=' between its entry name and path: {1:?}
+  PathListMissingSeparator(usize, String),
+  /// path list entry {0} is an entry name without a following path: {1:?}
+  PathListMissingPath(usize, String),
+  /// path list entry {0} has an invalid entry name: {1}
+  PathListEntryName(usize, #[source] lib_crawl::MedusaCrawlError),
+}
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum InputFormat {
+  /// The output of `crawl`, in either of its formats.
+  #[default]
+  Json,
+  /// One path per line. Each path is also used as its entry name, without any
+  /// leading './'. Lines are never split on '=', unlike 'mapped-lines'.
+  Lines,
+  /// Like 'lines', but separated by NUL bytes instead, as from `find -print0`
+  /// or `git ls-files -z`. Entries are never split on '='.
+  Nul,
+  /// One 'name=path' per line, to write the file at 'path' to the entry
+  /// 'name'. Each line is split at its first '=', so the path may contain
+  /// '=' but the entry name may not.
+  MappedLines,
+  /// An entry name followed by its path, each terminated by a NUL byte, so
+  /// that neither needs to be escaped.
+  MappedNul,
+}
+
+impl InputFormat {
+  /// Read each entry from `input`, resolve it against `root` (or else the root
+  /// recorded in the input, if any), and send it to `entries` as soon as it has
+  /// been read.
+  pub async fn read_into(
+    self,
+    mut input: impl AsyncBufRead+Unpin,
+    root: Option<PathBuf>,
+    entries: mpsc::UnboundedSender<StreamedEntry>,
+  ) -> Result<(), CrawlFormatError> {
+    let delimiter: u8 = match self {
+      Self::Json => return Self::read_crawl_into(input, root, entries).await,
+      Self::Lines | Self::MappedLines => b'\n',
+      Self::Nul | Self::MappedNul => b'\0',
+    };
+
+    let send = |entry_number: usize, resolved_path: ResolvedPath| {
+      let entry = Self::resolve_root(
+        lib_crawl::CrawledEntry::File(resolved_path.into()),
+        root.as_deref(),
+      )
+      .map_err(|e| CrawlFormatError::PathListEntryName(entry_number, e))?;
+      /* The receiver is only dropped if zipping has failed, and that error is
+       * reported instead. */
+      let _ = entries.send(entry);
+      Ok::<_, CrawlFormatError>(())
+    };
+    let mut entry: Vec<u8> = Vec::new();
+    let mut entry_number: usize = 0;
+    /* With 'mapped-nul', the entry name most recently read, if its path has
+     * not been read yet. */
+    let mut pending_name: Option<(usize, String)> = None;
+    loop {
+      entry.clear();
+      if input.read_until(delimiter, &mut entry).await? == 0 {
+        break;
+      }
+      entry_number += 1;
+      if entry.last() == Some(&delimiter) {
+        entry.pop();
+      }
+      if delimiter == b'\n' && entry.last() == Some(&b'\r') {
+        entry.pop();
+      }
+      if entry.is_empty() {
+        continue;
+      }
+      let entry = std::str::from_utf8(&entry)
+        .map_err(|_| CrawlFormatError::PathListEncoding(entry_number))?;
+      let (name, path) = match self {
+        Self::Json => unreachable!("json input was already read"),
+        Self::Lines | Self::Nul => (entry.trim_start_matches("./"), entry),
+        Self::MappedLines => Self::split_mapped_line(entry_number, entry)?,
+        Self::MappedNul => match pending_name.take() {
+          None => {
+            pending_name = Some((entry_number, entry.to_string()));
+            continue;
+          },
+          Some((name_number, name)) => {
+            send(name_number, Self::resolve_entry(name_number, &name, entry)?)?;
+            continue;
+          },
+        },
+      };
+      send(entry_number, Self::resolve_entry(entry_number, name, path)?)?;
+    }
+    if let Some((name_number, name)) = pending_name {
+      return Err(CrawlFormatError::PathListMissingPath(name_number, name));
+    }
+    Ok(())
+  }
+
+  /// Read the format written by [`NdjsonWriter`] one line at a time, or else a
+  /// single JSON object as written by `crawl --format json`.
+  async fn read_crawl_into(
+    mut input: impl AsyncBufRead+Unpin,
+    root: Option<PathBuf>,
+    entries: mpsc::UnboundedSender<StreamedEntry>,
+  ) -> Result<(), CrawlFormatError> {
+    let mut line = String::new();
+    input.read_line(&mut line).await?;
+    let CrawlHeader {
+      version,
+      root: header_root,
+    } = match serde_json::from_str::<CrawlHeader>(&line) {
+      Ok(header) => header,
+      Err(_) => {
+        /* The first line was not a header, so this must be the older format,
+         * which we have to read all at once. */
+        input.read_to_string(&mut line).await?;
+        let mut crawl_result: CrawlResult =
+          serde_json::from_str(&line).map_err(CrawlFormatError::Json)?;
+        if root.is_some() {
+          crawl_result.root = root;
+        }
+        let lib_crawl::CrawlResult {
+          real_file_paths,
+          symlinks,
+          ..
+        } = crawl_result.into();
+        let crawled_entries = real_file_paths
+          .into_iter()
+          .map(lib_crawl::CrawledEntry::File)
+          .chain(symlinks.into_iter().map(lib_crawl::CrawledEntry::Symlink));
+        for entry in crawled_entries {
+          let entry = entry
+            .into_streamed_entry()
+            .map_err(CrawlFormatError::EntryName)?;
+          let _ = entries.send(entry);
+        }
+        return Ok(());
+      },
+    };
+    if version != CRAWL_FORMAT_VERSION {
+      return Err(CrawlFormatError::UnsupportedVersion(
+        version,
+        CRAWL_FORMAT_VERSION,
+      ));
+    }
+    let root = root.or(header_root);
+
+    let mut line_number: usize = 1;
+    let mut saw_trailer = false;
+    loop {
+      line.clear();
+      if input.read_line(&mut line).await? == 0 {
+        break;
+      }
+      line_number += 1;
+      if line.trim().is_empty() {
+        continue;
+      }
+      if saw_trailer {
+        return Err(CrawlFormatError::RecordAfterTrailer(line_number));
+      }
+      let entry =
+        match serde_json::from_str(&line).map_err(|e| CrawlFormatError::Record(line_number, e))? {
+          CrawlRecord::File(resolved_path) => lib_crawl::CrawledEntry::File(resolved_path.into()),
+          CrawlRecord::Symlink(symlink) => lib_crawl::CrawledEntry::Symlink(symlink.into()),
+          CrawlRecord::Trailer(_) => {
+            saw_trailer = true;
+            continue;
+          },
+        };
+      let entry = Self::resolve_root(entry, root.as_deref())
+        .map_err(|e| CrawlFormatError::RecordEntryName(line_number, e))?;
+      let _ = entries.send(entry);
+    }
+    if !saw_trailer {
+      return Err(CrawlFormatError::MissingTrailer);
+    }
+    Ok(())
+  }
+
+  fn resolve_root(
+    mut entry: lib_crawl::CrawledEntry,
+    root: Option<&Path>,
+  ) -> Result<StreamedEntry, lib_crawl::MedusaCrawlError> {
+    if let Some(root) = root {
+      entry.join_root(root);
+    }
+    entry.into_streamed_entry()
+  }
+
+  fn split_mapped_line(entry_number: usize, entry: &str) -> Result<(&str, &str), CrawlFormatError> {
+    let (name, path) = entry
+      .split_once('=')
+      .ok_or_else(|| CrawlFormatError::PathListMissingSeparator(entry_number, entry.to_string()))?;
+    if path.is_empty() {
+      return Err(CrawlFormatError::PathListEmptyPath(
+        entry_number,
+        entry.to_string(),
+      ));
+    }
+    Ok((name, path))
   }
+
+  fn resolve_entry(
+    entry_number: usize,
+    name: &str,
+    path: &str,
+  ) -> Result<ResolvedPath, CrawlFormatError> {
+    let name = EntryName::validate(name.to_string())
+      .map_err(|e| CrawlFormatError::PathListEntryName(entry_number, e.into()))?;
+    Ok(ResolvedPath {
+      unresolved_path: PathBuf::from(name.as_str()),
+      resolved_path: PathBuf::from(path),
+      metadata: None,
+      digest: None,
+    })
+  }
+}
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum CrawlFormat {
+  /// A header line with the format version, then one line of JSON per entry
+  /// as soon as it is found, then a trailer line. This can be read
+  /// incrementally.
+  #[default]
+  Ndjson,
+  /// A single JSON object, as written by earlier versions.
+  Json,
 }
 
 
@@ -125,6 +669,221 @@ impl ValueParserFactory for RegexWrapper {
 }
 
 
+#[derive(Clone, Debug)]
+pub struct GlobRuleWrapper(pub lib_crawl::GlobRule);
+
+impl fmt::Display for GlobRuleWrapper {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let Self(g) = self;
+    g.fmt(f)
+  }
+}
+
+#[derive(Clone)]
+pub struct GlobRuleParser;
+
+impl TypedValueParser for GlobRuleParser {
+  type Value = GlobRuleWrapper;
+
+  fn parse_ref(
+    &self,
+    cmd: &clap::Command,
+    arg: Option<&clap::Arg>,
+    value: &std::ffi::OsStr,
+  ) -> Result<Self::Value, clap::Error> {
+    let inner = clap::builder::StringValueParser::new();
+    let val = inner.parse_ref(cmd, arg, value)?;
+
+    let rule = lib_crawl::GlobRule::parse(&val).map_err(|e| {
+      let mut err = clap_handlers::prepare_clap_error(cmd, arg, &val);
+      clap_handlers::process_clap_error(
+        &mut err,
+        e,
+        "Globs use the syntax of a .gitignore file. See https://git-scm.com/docs/gitignore#_pattern_format for more details.",
+      );
+      err
+    })?;
+    Ok(GlobRuleWrapper(rule))
+  }
+}
+
+impl ValueParserFactory for GlobRuleWrapper {
+  type Parser = GlobRuleParser;
+
+  fn value_parser() -> Self::Parser { GlobRuleParser }
+}
+
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum SymlinkBehavior {
+  /// Crawl the file or directory each symlink points to, as if it were located
+  /// at the symlink's path.
+  #[default]
+  Follow,
+  /// Record each symlink itself, so that it is written to the zip as a
+  /// symlink entry.
+  Preserve,
+}
+
+impl From<SymlinkBehavior> for lib_crawl::SymlinkBehavior {
+  fn from(x: SymlinkBehavior) -> Self {
+    match x {
+      SymlinkBehavior::Follow => Self::Follow,
+      SymlinkBehavior::Preserve => Self::Preserve,
+    }
+  }
+}
+
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum SymlinkCyclePolicy {
+  /// Fail the crawl.
+  #[default]
+  Error,
+  /// Silently skip the symlink.
+  Skip,
+  /// Crawl the directory one more time, then skip the symlink when it is
+  /// encountered again within that copy. A chain of symlinks which loops back
+  /// on itself is skipped.
+  FollowOnce,
+}
+
+impl From<SymlinkCyclePolicy> for lib_crawl::SymlinkCyclePolicy {
+  fn from(x: SymlinkCyclePolicy) -> Self {
+    match x {
+      SymlinkCyclePolicy::Error => Self::Error,
+      SymlinkCyclePolicy::Skip => Self::Skip,
+      SymlinkCyclePolicy::FollowOnce => Self::FollowOnce,
+    }
+  }
+}
+
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum SpecialFilePolicy {
+  /// Fail the crawl.
+  #[default]
+  Error,
+  /// Print a warning and leave the file out of the crawl.
+  Skip,
+}
+
+impl From<SpecialFilePolicy> for lib_crawl::SpecialFilePolicy {
+  fn from(x: SpecialFilePolicy) -> Self {
+    match x {
+      SpecialFilePolicy::Error => Self::Error,
+      SpecialFilePolicy::Skip => Self::Skip,
+    }
+  }
+}
+
+/// Print a warning to stderr for each file left out of the crawl with
+/// [`SpecialFilePolicy::Skip`].
+pub fn warn_skipped_files(skipped_files: &[lib_crawl::SkippedFile]) {
+  for lib_crawl::SkippedFile { path, kind } in skipped_files.iter() {
+    eprintln!("warning: skipping {} at {:?}", kind, path);
+  }
+}
+
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum CrawlSource {
+  /// Walk the filesystem.
+  #[default]
+  Filesystem,
+  /// Crawl each file tracked by the git repository containing the working
+  /// directory, including files in submodules. Files which are not checked
+  /// out are skipped.
+  GitIndex,
+}
+
+impl From<CrawlSource> for lib_crawl::CrawlSource {
+  fn from(x: CrawlSource) -> Self {
+    match x {
+      CrawlSource::Filesystem => Self::Filesystem,
+      CrawlSource::GitIndex => Self::GitIndex,
+    }
+  }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct TimestampWrapper(pub SystemTime);
+
+#[derive(Clone)]
+pub struct TimestampParser;
+
+impl TypedValueParser for TimestampParser {
+  type Value = TimestampWrapper;
+
+  fn parse_ref(
+    &self,
+    cmd: &clap::Command,
+    arg: Option<&clap::Arg>,
+    value: &std::ffi::OsStr,
+  ) -> Result<Self::Value, clap::Error> {
+    let inner = clap::builder::StringValueParser::new();
+    let val = inner.parse_ref(cmd, arg, value)?;
+
+    let parsed_offset = OffsetDateTime::parse(&val, &Rfc3339).map_err(|e| {
+      let mut err = clap_handlers::prepare_clap_error(cmd, arg, &val);
+      clap_handlers::process_clap_error(
+        &mut err,
+        e,
+        "Provide a string which can be formatted according to RFC 3339, such as '1985-04-12T23:20:50.52Z'. See https://datatracker.ietf.org/doc/html/rfc3339#section-5.6 for details.",
+      );
+      err
+    })?;
+    Ok(TimestampWrapper(parsed_offset.into()))
+  }
+}
+
+impl ValueParserFactory for TimestampWrapper {
+  type Parser = TimestampParser;
+
+  fn value_parser() -> Self::Parser { TimestampParser }
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct FileFilters {
+  /// If provided, skip any file larger than this many bytes.
+  #[arg(long, default_value = None)]
+  pub max_file_size: Option<u64>,
+  /// If provided, skip any file last modified before this [RFC 3339]
+  /// timestamp, such as '1985-04-12T23:20:50.52Z'.
+  ///
+  /// [RFC 3339]: https://datatracker.ietf.org/doc/html/rfc3339#section-5.6
+  #[arg(long, default_value = None)]
+  pub modified_after: Option<TimestampWrapper>,
+  /// If provided, skip any file last modified at or after this [RFC 3339]
+  /// timestamp.
+  ///
+  /// [RFC 3339]: https://datatracker.ietf.org/doc/html/rfc3339#section-5.6
+  #[arg(long, default_value = None)]
+  pub modified_before: Option<TimestampWrapper>,
+  /// Skip any file or directory on a different filesystem than the directory
+  /// being crawled, like `find -xdev`. Only supported on unix.
+  #[arg(long, default_value_t = false)]
+  pub one_file_system: bool,
+}
+
+impl From<FileFilters> for lib_crawl::FileFilters {
+  fn from(x: FileFilters) -> Self {
+    let FileFilters {
+      max_file_size,
+      modified_after,
+      modified_before,
+      one_file_system,
+    } = x;
+    Self {
+      max_size: max_file_size,
+      modified_after: modified_after.map(|TimestampWrapper(t)| t),
+      modified_before: modified_before.map(|TimestampWrapper(t)| t),
+      same_file_system: one_file_system,
+    }
+  }
+}
+
 #[derive(Clone, Debug, Default, Args)]
 pub struct MedusaCrawl {
   /// File, directory, or symlink paths to traverse.
@@ -136,11 +895,69 @@ pub struct MedusaCrawl {
   /// These patterns will not read through symlinks.
   #[arg(short, long, default_values_t = Vec::<RegexWrapper>::new())]
   pub ignore_patterns: Vec<RegexWrapper>,
+  /// Globs in .gitignore syntax to filter out of any directory or file paths
+  /// encountered when crawling. Later globs take precedence, so a glob
+  /// starting with '!' re-includes paths matched by an earlier glob.
+  ///
+  /// Globs are matched against paths relative to
+  /// [`working_dir`](Self::working_dir).
+  #[arg(long, default_values_t = Vec::<GlobRuleWrapper>::new())]
+  pub ignore_glob: Vec<GlobRuleWrapper>,
+  /// If provided, only crawl files matching these globs in .gitignore syntax.
+  /// Directories are still traversed, and ignored paths stay ignored.
+  #[arg(long, default_values_t = Vec::<GlobRuleWrapper>::new())]
+  pub include_glob: Vec<GlobRuleWrapper>,
+  #[command(flatten)]
+  pub filters: FileFilters,
+  /// Names of files to read additional ignore globs from in each directory
+  /// crawled, such as '.gitignore' or '.medusaignore'.
+  ///
+  /// As with .gitignore files, each file's globs only apply to paths within
+  /// its own directory, and globs from more deeply nested files take
+  /// precedence. Globs from --ignore-glob take precedence over all of them.
+  #[arg(long, default_values_t = Vec::<String>::new())]
+  pub ignore_file_name: Vec<String>,
+  /// Where to find the paths to crawl.
+  ///
+  /// With 'git-index', each of [`paths_to_crawl`](Self::paths_to_crawl) is
+  /// used as a pathspec to select tracked files, and --ignore-file-name
+  /// cannot be provided.
+  #[arg(long, value_enum, default_value_t)]
+  pub source: CrawlSource,
+  /// Maximum number of directories, files, and symlinks which may be open or
+  /// read from at once while crawling. `crawl-zip --streaming` ignores this,
+  /// and counts the crawl against `--max-open-files` instead.
+  ///
+  /// Defaults to the soft limit on open file descriptors, less a few reserved
+  /// for the process, if not provided.
+  #[arg(long, default_value = None)]
+  pub crawl_max_open_files: Option<NonZeroUsize>,
+  /// If provided, compute a digest of each file's contents in parallel, and
+  /// record it in the crawl output along with a digest over every entry.
+  #[arg(long, value_enum, default_value = None)]
+  pub digest: Option<DigestAlgorithm>,
   /// Where [`paths_to_crawl`](Self::paths_to_crawl) is relative to.
   ///
   /// Defaults to the process's current working directory if not provided.
   #[arg(short, long, default_value = None)]
   pub working_dir: Option<PathBuf>,
+  /// How to crawl any symlinks encountered, including those in
+  /// [`paths_to_crawl`](Self::paths_to_crawl).
+  #[arg(long, value_enum, default_value_t)]
+  pub symlinks: SymlinkBehavior,
+  /// What to do upon following a symlink into a directory which is already
+  /// being crawled higher up the same path, or a chain of symlinks which loops
+  /// back on itself.
+  #[arg(long, value_enum, default_value_t)]
+  pub symlink_cycles: SymlinkCyclePolicy,
+  /// If provided, fail the crawl upon reaching any directory nested more than
+  /// this many levels below one of [`paths_to_crawl`](Self::paths_to_crawl).
+  #[arg(long, default_value = None)]
+  pub max_depth: Option<usize>,
+  /// What to do upon encountering a path which is not a regular file,
+  /// directory, or symlink, such as a fifo or unix socket.
+  #[arg(long, value_enum, default_value_t)]
+  pub special_files: SpecialFilePolicy,
 }
 
 impl From<MedusaCrawl> for lib_crawl::MedusaCrawl {
@@ -148,7 +965,18 @@ impl From<MedusaCrawl> for lib_crawl::MedusaCrawl {
     let MedusaCrawl {
       paths_to_crawl,
       ignore_patterns,
+      ignore_glob,
+      include_glob,
+      filters,
+      ignore_file_name,
+      source,
+      crawl_max_open_files,
+      digest,
       working_dir,
+      symlinks,
+      symlink_cycles,
+      max_depth,
+      special_files,
     } = x;
     let ignore_patterns = RegexSet::new(
       ignore_patterns
@@ -156,10 +984,248 @@ impl From<MedusaCrawl> for lib_crawl::MedusaCrawl {
         .map(|RegexWrapper(p)| p.as_str().to_string()),
     )
     .expect("constituent patterns were already validated");
+    let ignore_globs = lib_crawl::GlobRules::new(
+      ignore_glob
+        .into_iter()
+        .map(|GlobRuleWrapper(g)| g)
+        .collect(),
+    );
+    let include_globs = lib_crawl::GlobRules::new(
+      include_glob
+        .into_iter()
+        .map(|GlobRuleWrapper(g)| g)
+        .collect(),
+    );
     Self {
       paths_to_crawl: paths_to_crawl.into_iter().map(PathBuf::from).collect(),
-      ignores: lib_crawl::Ignores::new(ignore_patterns),
+      ignores: lib_crawl::Ignores::new(ignore_patterns, ignore_globs, include_globs),
+      filters: filters.into(),
       cwd: working_dir,
+      symlink_behavior: symlinks.into(),
+      symlink_cycle_policy: symlink_cycles.into(),
+      max_depth,
+      special_file_policy: special_files.into(),
+      ignore_file_names: ignore_file_name,
+      source: source.into(),
+      max_open_files: crawl_max_open_files.unwrap_or_else(libmedusa_zip::default_max_open_files),
+      digest: digest.map(lib_digest::DigestAlgorithm::from),
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  async fn read_pairs(
+    format: InputFormat,
+    input: &[u8],
+  ) -> Result<Vec<(String, String)>, CrawlFormatError> {
+    let (entries_tx, mut entries_rx) = mpsc::unbounded_channel();
+    format.read_into(input, None, entries_tx).await?;
+    let mut pairs = Vec::new();
+    while let Some(entry) = entries_rx.recv().await {
+      pairs.push(match entry {
+        StreamedEntry::File(file) => (
+          file.name.as_str().to_string(),
+          file.source.to_str().unwrap().to_string(),
+        ),
+        StreamedEntry::Symlink(symlink) => (symlink.name.as_str().to_string(), symlink.target),
+      });
+    }
+    Ok(pairs)
+  }
+
+  fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    expected
+      .iter()
+      .map(|(name, path)| (name.to_string(), path.to_string()))
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn path_lists_with_equals_signs() {
+    assert_eq!(
+      read_pairs(InputFormat::Lines, b"./a=b.txt\nc/d.txt\r\n\n")
+        .await
+        .unwrap(),
+      pairs(&[("a=b.txt", "./a=b.txt"), ("c/d.txt", "c/d.txt")])
+    );
+    assert_eq!(
+      read_pairs(InputFormat::Nul, b"./a=b.txt\0c=d/e.txt\0")
+        .await
+        .unwrap(),
+      pairs(&[("a=b.txt", "./a=b.txt"), ("c=d/e.txt", "c=d/e.txt")])
+    );
+    assert_eq!(
+      read_pairs(InputFormat::MappedLines, b"x/y.txt=./a=b.txt\n")
+        .await
+        .unwrap(),
+      pairs(&[("x/y.txt", "./a=b.txt")])
+    );
+    assert_eq!(
+      read_pairs(
+        InputFormat::MappedNul,
+        b"x=y.txt\0./a=b.txt\0z.txt\0z.txt\0"
+      )
+      .await
+      .unwrap(),
+      pairs(&[("x=y.txt", "./a=b.txt"), ("z.txt", "z.txt")])
+    );
+  }
+
+  #[tokio::test]
+  async fn malformed_mapped_path_lists() {
+    assert!(matches!(
+      read_pairs(InputFormat::MappedLines, b"a.txt=a.txt\nb.txt\n").await,
+      Err(CrawlFormatError::PathListMissingSeparator(2, _))
+    ));
+    assert!(matches!(
+      read_pairs(InputFormat::MappedLines, b"a.txt=\n").await,
+      Err(CrawlFormatError::PathListEmptyPath(1, _))
+    ));
+    assert!(matches!(
+      read_pairs(InputFormat::MappedNul, b"a.txt\0a.txt\0b.txt\0").await,
+      Err(CrawlFormatError::PathListMissingPath(3, _))
+    ));
+    assert!(matches!(
+      read_pairs(InputFormat::MappedNul, b"/abs\0a.txt\0").await,
+      Err(CrawlFormatError::PathListEntryName(1, _))
+    ));
+  }
+
+  /// Crawl `paths_to_crawl` within `cwd` and write ndjson relative to `root`,
+  /// as `crawl --root` does.
+  async fn crawl_with_root(
+    cwd: &Path,
+    paths_to_crawl: Vec<PathBuf>,
+    root: &Path,
+  ) -> Result<Vec<u8>, lib_crawl::MedusaCrawlError> {
+    let crawl = lib_crawl::MedusaCrawl {
+      paths_to_crawl,
+      cwd: Some(cwd.to_path_buf()),
+      ..Default::default()
+    };
+    let (entries_tx, mut entries_rx) = mpsc::unbounded_channel();
+    crawl.crawl_streaming(entries_tx).await?;
+    let mut writer = NdjsonWriter::new(Vec::new(), Some(root.to_path_buf())).unwrap();
+    while let Some(mut entry) = entries_rx.recv().await {
+      entry.strip_root(root)?;
+      writer.write_entry(entry).unwrap();
+    }
+    Ok(writer.finish(None).unwrap())
+  }
+
+  #[tokio::test]
+  async fn crawl_root_round_trip() {
+    use libmedusa_zip::{
+      destination::OutputWrapper,
+      zip::{EntryModifications, MedusaZip, PipelineTuning, ZipOutputOptions},
+    };
+    use std::io::{Cursor, Read};
+    use zip::{ZipArchive, ZipWriter};
+
+    let tmp = tempfile::tempdir().unwrap();
+    for (dir, contents) in [("src", "source"), ("dst", "moved")].into_iter() {
+      for name in ["a.txt", "d/b.txt"].into_iter() {
+        let path = tmp.path().join(dir).join("tree").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("{} {}", contents, name)).unwrap();
+      }
+    }
+    let output = crawl_with_root(
+      tmp.path(),
+      vec![PathBuf::from("src/tree")],
+      &tmp.path().join("src"),
+    )
+    .await
+    .unwrap();
+
+    /* Each path is recorded relative to the root, and resolved against the
+     * root provided to `zip` instead. */
+    let dst = tmp.path().join("dst");
+    let (entries_tx, entries_rx) = mpsc::unbounded_channel();
+    InputFormat::Json
+      .read_into(&output[..], Some(dst.clone()), entries_tx)
+      .await
+      .unwrap();
+    let output_zip = MedusaZip::zip_streaming(
+      entries_rx,
+      ZipOutputOptions::default(),
+      EntryModifications::default(),
+      OutputWrapper::wrap(ZipWriter::new(Cursor::new(Vec::new()))),
+      PipelineTuning::default(),
+    )
+    .await
+    .unwrap();
+    let mut archive = ZipArchive::new(output_zip.reclaim().finish().unwrap()).unwrap();
+    for name in ["a.txt", "d/b.txt"].into_iter() {
+      let mut contents = String::new();
+      archive
+        .by_name(&format!("src/tree/{}", name))
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+      assert_eq!(contents, format!("moved {}", name));
+    }
+  }
+
+  #[tokio::test]
+  async fn crawl_outside_root() {
+    let tmp = tempfile::tempdir().unwrap();
+    for dir in ["inside", "outside"].into_iter() {
+      std::fs::create_dir_all(tmp.path().join(dir)).unwrap();
+      std::fs::write(tmp.path().join(dir).join("a.txt"), "").unwrap();
     }
+    let root = tmp.path().join("inside");
+    assert!(matches!(
+      crawl_with_root(tmp.path(), vec![PathBuf::from("outside")], &root).await,
+      Err(lib_crawl::MedusaCrawlError::OutsideRoot(path, outside_root))
+        if path == tmp.path().join("outside/a.txt") && outside_root == root
+    ));
+  }
+
+  #[tokio::test]
+  async fn ndjson_round_trip() {
+    let mut writer = NdjsonWriter::new(Vec::new(), Some(PathBuf::from("/root"))).unwrap();
+    writer
+      .write_entry(lib_crawl::CrawledEntry::File(lib_crawl::ResolvedPath {
+        unresolved_path: PathBuf::from("a.txt"),
+        resolved_path: PathBuf::from("b/a.txt"),
+        metadata: None,
+        digest: None,
+      }))
+      .unwrap();
+    writer
+      .write_entry(lib_crawl::CrawledEntry::Symlink(
+        lib_crawl::ResolvedSymlink {
+          unresolved_path: PathBuf::from("link"),
+          target: PathBuf::from("a.txt"),
+          metadata: None,
+        },
+      ))
+      .unwrap();
+    let output = writer.finish(None).unwrap();
+    assert_eq!(
+      read_pairs(InputFormat::Json, &output).await.unwrap(),
+      pairs(&[("a.txt", "/root/b/a.txt"), ("link", "a.txt")])
+    );
+
+    /* Without the trailer, the crawl may have been cut off. */
+    let last_line = output[..output.len() - 1]
+      .iter()
+      .rposition(|b| *b == b'\n')
+      .unwrap();
+    assert!(matches!(
+      read_pairs(InputFormat::Json, &output[..=last_line]).await,
+      Err(CrawlFormatError::MissingTrailer)
+    ));
+    let mut appended = output.clone();
+    appended.extend_from_slice(&output[output.iter().position(|b| *b == b'\n').unwrap() + 1..]);
+    assert!(matches!(
+      read_pairs(InputFormat::Json, &appended).await,
+      Err(CrawlFormatError::RecordAfterTrailer(5))
+    ));
   }
 }
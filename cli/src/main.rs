@@ -56,10 +56,12 @@ mod util;
 mod cli {
   mod args {
     use crate::{
-      crawl::MedusaCrawl,
+      crawl::{CrawlFormat, InputFormat, MedusaCrawl},
       destination::DestinationBehavior,
-      merge::MedusaMerge,
-      zip::{EntryModifications, ModifiedTimeBehavior, Parallelism, ZipOutputOptions},
+      merge::{MedusaMerge, MergeTuning},
+      zip::{
+        EntryModifications, ModifiedTimeBehavior, Parallelism, PipelineTuning, ZipOutputOptions,
+      },
     };
 
     use clap::{Args, Parser, Subcommand};
@@ -78,23 +80,47 @@ mod cli {
 
     #[derive(Subcommand, Debug)]
     pub enum Command {
-      /// Write a JSON object to stdout which contains all the file paths under
-      /// the top-level `paths`.
+      /// Write JSON to stdout which contains all the file paths under the
+      /// top-level `paths`.
       Crawl {
         #[command(flatten)]
         crawl: MedusaCrawl,
+        /// Record this directory once in the output as `root`, and write each
+        /// `resolved_path` relative to it, so that the output can be consumed
+        /// on another machine with `zip --root`.
+        ///
+        /// A relative path is resolved against `--working-dir`.
+        #[arg(long, default_value = None)]
+        root: Option<PathBuf>,
+        /// How to write the crawl output. `zip` can read either format.
+        #[arg(long, value_enum, default_value_t)]
+        format: CrawlFormat,
       },
-      /// Consume a JSON object from [`Self::Crawl`] over stdin and write those
-      /// files into a zip file at `output`.
+      /// Consume the output from [`Self::Crawl`], or a list of paths, over
+      /// stdin and write those files into a zip file at `output`.
       Zip {
         #[command(flatten)]
         output: Output,
+        /// The format of the file list read from stdin.
+        #[arg(long, value_enum, default_value_t)]
+        input_format: InputFormat,
+        /// Resolve relative `resolved_path`s against this directory, instead
+        /// of any `root` recorded in the input.
+        #[arg(long, default_value = None)]
+        root: Option<PathBuf>,
         #[command(flatten)]
         zip_options: ZipOutputOptions,
         #[command(flatten)]
         modifications: EntryModifications,
         #[arg(long, value_enum, default_value_t)]
         parallelism: Parallelism,
+        /// Start compressing files as soon as they are read from stdin,
+        /// instead of waiting for the input to end. The output is the same,
+        /// but `--parallelism` is ignored.
+        #[arg(long)]
+        streaming: bool,
+        #[command(flatten)]
+        tuning: PipelineTuning,
       },
       /// Merge the content of several zip files into one.
       Merge {
@@ -105,6 +131,8 @@ mod cli {
         mtime_behavior: ModifiedTimeBehavior,
         #[command(flatten)]
         merge: MedusaMerge,
+        #[command(flatten)]
+        tuning: MergeTuning,
       },
       /// Perform a `crawl` and then a `zip` on its output in memory.
       CrawlZip {
@@ -118,20 +146,41 @@ mod cli {
         modifications: EntryModifications,
         #[arg(long, value_enum, default_value_t)]
         parallelism: Parallelism,
+        /// Start compressing files as soon as they are crawled, instead of
+        /// waiting for the crawl to finish. The output is the same, but
+        /// `--parallelism` is ignored.
+        #[arg(long)]
+        streaming: bool,
+        #[command(flatten)]
+        tuning: PipelineTuning,
       },
       /// Perform a `zip` and then a `merge` without releasing the output file
       /// handle.
       ZipMerge {
         #[command(flatten)]
         output: Output,
+        /// The format of the file list read from stdin.
+        #[arg(long, value_enum, default_value_t)]
+        input_format: InputFormat,
+        /// Resolve relative `resolved_path`s against this directory, instead
+        /// of any `root` recorded in the input.
+        #[arg(long, default_value = None)]
+        root: Option<PathBuf>,
         #[command(flatten)]
         zip_options: ZipOutputOptions,
         #[command(flatten)]
         modifications: EntryModifications,
         #[arg(long, value_enum, default_value_t)]
         parallelism: Parallelism,
+        /// Start compressing files as soon as they are read from stdin,
+        /// instead of waiting for the input to end. The output is the same,
+        /// but `--parallelism` is ignored.
+        #[arg(long)]
+        streaming: bool,
         #[command(flatten)]
         merge: MedusaMerge,
+        #[command(flatten)]
+        tuning: PipelineTuning,
       },
       /// Perform `crawl`, then a `zip` on its output in memory, then a `merge`
       /// into the same output file.
@@ -146,8 +195,15 @@ mod cli {
         modifications: EntryModifications,
         #[arg(long, value_enum, default_value_t)]
         parallelism: Parallelism,
+        /// Start compressing files as soon as they are crawled, instead of
+        /// waiting for the crawl to finish. The output is the same, but
+        /// `--parallelism` is ignored.
+        #[arg(long)]
+        streaming: bool,
         #[command(flatten)]
         merge: MedusaMerge,
+        #[command(flatten)]
+        tuning: PipelineTuning,
       },
     }
 
@@ -165,18 +221,27 @@ mod cli {
   mod run {
     use super::{Cli, Command, Output};
 
-    use crate::crawl::CrawlResult;
+    use crate::crawl::{
+      warn_skipped_files, ContentDigest, CrawlFormat, CrawlResult, InputFormat, NdjsonWriter,
+    };
 
     use libmedusa_zip::{
-      crawl::{CrawlResult as LibCrawlResult, MedusaCrawl},
+      crawl::{AggregateDigest, CrawledEntry, MedusaCrawl},
       destination::{DestinationBehavior, OutputWrapper},
       merge::MedusaMerge,
+      zip::{
+        EntryModifications, MedusaZip, Parallelism, PipelineTuning as LibPipelineTuning,
+        StreamedEntry, ZipOutputOptions,
+      },
+      FileSource, SymlinkSource,
     };
 
     use serde_json;
-    use tokio::io::{self, AsyncReadExt};
+    use tokio::{io, sync::mpsc};
     use zip::write::ZipWriter;
 
+    use std::{env, io::BufWriter, path::PathBuf};
+
     impl Output {
       pub async fn initialize(self) -> eyre::Result<ZipWriter<std::fs::File>> {
         let Self {
@@ -193,51 +258,109 @@ mod cli {
         let Self { command } = self;
 
         match command {
-          Command::Crawl { crawl } => {
+          Command::Crawl {
+            crawl,
+            root,
+            format,
+          } => {
             let crawl: MedusaCrawl = crawl.into();
-            let crawl_result = crawl.crawl_paths().await?;
-            let crawl_result: CrawlResult = crawl_result.into();
-            let crawl_json = serde_json::to_string(&crawl_result)?;
-
+            let digest = crawl.digest;
+            let root = match root {
+              Some(root) => {
+                let cwd = crawl.cwd.clone().map(Ok).unwrap_or_else(env::current_dir)?;
+                /* Normalize away any "." components. */
+                Some(cwd.join(root).components().collect::<PathBuf>())
+              },
+              None => None,
+            };
             /* Print json serialization to stdout. */
-            println!("{}", crawl_json);
+            match format {
+              CrawlFormat::Ndjson => {
+                /* Write each entry as soon as it is found. */
+                let (entries_tx, mut entries_rx) = mpsc::unbounded_channel::<CrawledEntry>();
+                let crawl = async move {
+                  crawl
+                    .crawl_streaming(entries_tx)
+                    .await
+                    .map_err(eyre::Report::from)
+                };
+                let write = async move {
+                  let stdout = BufWriter::new(std::io::stdout().lock());
+                  let mut writer = NdjsonWriter::new(stdout, root.clone())?;
+                  let mut aggregate_digest = digest.map(AggregateDigest::new);
+                  while let Some(mut entry) = entries_rx.recv().await {
+                    if let Some(ref mut aggregate_digest) = aggregate_digest {
+                      aggregate_digest.add(&entry)?;
+                    }
+                    if let Some(ref root) = root {
+                      entry.strip_root(root)?;
+                    }
+                    writer.write_entry(entry)?;
+                  }
+                  writer.finish(aggregate_digest.map(|a| ContentDigest(a.finish())))?;
+                  Ok::<_, eyre::Report>(())
+                };
+                let (skipped_files, ()) = tokio::try_join!(crawl, write)?;
+                warn_skipped_files(&skipped_files);
+              },
+              CrawlFormat::Json => {
+                let mut crawl_result = crawl.crawl_paths().await?;
+                warn_skipped_files(&crawl_result.skipped_files);
+                let aggregate_digest = digest
+                  .map(|algorithm| crawl_result.aggregate_digest(algorithm))
+                  .transpose()?;
+                if let Some(ref root) = root {
+                  crawl_result.strip_root(root)?;
+                }
+                let mut crawl_result: CrawlResult = crawl_result.into();
+                crawl_result.root = root;
+                crawl_result.aggregate_digest = aggregate_digest.map(ContentDigest);
+
+                let crawl_json = serde_json::to_string(&crawl_result)?;
+                println!("{}", crawl_json);
+              },
+            }
           },
           Command::Zip {
             output,
+            input_format,
+            root,
             zip_options,
             modifications,
             parallelism,
+            streaming,
+            tuning,
           } => {
             /* Initialize output stream. */
             let output_zip = OutputWrapper::wrap(output.initialize().await?);
 
-            /* Read json serialization from stdin. */
-            let mut input_json: Vec<u8> = Vec::new();
-            io::stdin().read_to_end(&mut input_json).await?;
-            let crawl_result: CrawlResult = serde_json::from_slice(&input_json)?;
-            let crawl_result: LibCrawlResult = crawl_result.into();
-
-            /* Apply options from command line to produce a zip spec. */
-            let crawled_zip = crawl_result.medusa_zip(
+            /* Read each entry from stdin, and zip them. */
+            let _output_file_handle = Self::read_and_zip(
+              input_format,
+              root,
               zip_options.try_into()?,
               modifications.into(),
               parallelism.into(),
-            )?;
-
-            /* Do the parallel zip!!! */
-            let _output_file_handle = crawled_zip.zip(output_zip).await?;
+              streaming,
+              output_zip,
+              tuning.into(),
+            )
+            .await?;
           },
           Command::Merge {
             output,
             mtime_behavior,
             merge,
+            tuning,
           } => {
             /* Initialize output stream. */
             let output_zip = OutputWrapper::wrap(output.initialize().await?);
 
             let merge_spec: MedusaMerge = merge.try_into()?;
             /* Copy over constituent zips into current. */
-            let _output_file_handle = merge_spec.merge(mtime_behavior.into(), output_zip).await?;
+            let _output_file_handle = merge_spec
+              .merge(mtime_behavior.into(), output_zip, tuning.into())
+              .await?;
           },
           Command::CrawlZip {
             crawl,
@@ -245,54 +368,73 @@ mod cli {
             zip_options,
             modifications,
             parallelism,
+            streaming,
+            tuning,
           } => {
             /* Initialize output stream. */
             let output_zip = OutputWrapper::wrap(output.initialize().await?);
 
             let crawl: MedusaCrawl = crawl.into();
-            /* Perform the actual crawl, traversing the filesystem in the process. */
-            let crawl_result = crawl.crawl_paths().await?;
-
-            /* Apply options from command line to produce a zip spec. */
-            let crawled_zip = crawl_result.medusa_zip(
-              zip_options.try_into()?,
-              modifications.into(),
-              parallelism.into(),
-            )?;
-
-            /* Do the parallel zip over the crawled files!!! */
-            let _output_file_handle = crawled_zip.zip(output_zip).await?;
+            if streaming {
+              /* Compress each file as soon as the crawl finds it. */
+              let (_output_file_handle, skipped_files) = crawl
+                .crawl_zip(
+                  zip_options.try_into()?,
+                  modifications.into(),
+                  output_zip,
+                  tuning.into(),
+                )
+                .await?;
+              warn_skipped_files(&skipped_files);
+            } else {
+              /* Perform the actual crawl, traversing the filesystem in the process. */
+              let crawl_result = crawl.crawl_paths().await?;
+              warn_skipped_files(&crawl_result.skipped_files);
+
+              /* Apply options from command line to produce a zip spec. */
+              let crawled_zip = crawl_result.medusa_zip(
+                zip_options.try_into()?,
+                modifications.into(),
+                parallelism.into(),
+              )?;
+
+              /* Do the parallel zip over the crawled files!!! */
+              let _output_file_handle = crawled_zip.zip(output_zip, tuning.into()).await?;
+            }
           },
           Command::ZipMerge {
             output,
+            input_format,
+            root,
             zip_options,
             modifications,
             parallelism,
+            streaming,
             merge,
+            tuning,
           } => {
             /* Initialize output stream. */
             let output_zip = OutputWrapper::wrap(output.initialize().await?);
 
-            /* Read json serialization from stdin. */
-            let mut input_json: Vec<u8> = Vec::new();
-            io::stdin().read_to_end(&mut input_json).await?;
-            let crawl_result: CrawlResult = serde_json::from_slice(&input_json)?;
-            let crawl_result: LibCrawlResult = crawl_result.into();
-
-            /* Apply options from command line to produce a zip spec. */
-            let crawled_zip = crawl_result.medusa_zip(
+            /* Read each entry from stdin, and zip them. */
+            let mtime_behavior = zip_options.mtime_behavior;
+            let tuning: LibPipelineTuning = tuning.into();
+            let output_zip_file_handle = Self::read_and_zip(
+              input_format,
+              root,
               zip_options.try_into()?,
               modifications.into(),
               parallelism.into(),
-            )?;
-
-            /* Do the parallel zip!!! */
-            let output_zip_file_handle = crawled_zip.zip(output_zip).await?;
+              streaming,
+              output_zip,
+              tuning.clone(),
+            )
+            .await?;
 
             let merge_spec: MedusaMerge = merge.try_into()?;
             /* Copy over constituent zips into current. */
             let _output_file_handle = merge_spec
-              .merge(zip_options.mtime_behavior.into(), output_zip_file_handle)
+              .merge(mtime_behavior.into(), output_zip_file_handle, tuning)
               .await?;
           },
           Command::CrawlZipMerge {
@@ -301,34 +443,110 @@ mod cli {
             zip_options,
             modifications,
             parallelism,
+            streaming,
             merge,
+            tuning,
           } => {
             /* Initialize output stream. */
             let output_zip = OutputWrapper::wrap(output.initialize().await?);
 
             let crawl: MedusaCrawl = crawl.into();
-            let crawl_result = crawl.crawl_paths().await?;
-
-            /* Apply options from command line to produce a zip spec. */
-            let crawled_zip = crawl_result.medusa_zip(
-              zip_options.try_into()?,
-              modifications.into(),
-              parallelism.into(),
-            )?;
-
-            /* Do the parallel zip!!! */
-            let output_zip_file_handle = crawled_zip.zip(output_zip).await?;
+            let mtime_behavior = zip_options.mtime_behavior;
+            let tuning: LibPipelineTuning = tuning.into();
+            let output_zip_file_handle = if streaming {
+              /* Compress each file as soon as the crawl finds it. */
+              let (output_zip_file_handle, skipped_files) = crawl
+                .crawl_zip(
+                  zip_options.try_into()?,
+                  modifications.into(),
+                  output_zip,
+                  tuning.clone(),
+                )
+                .await?;
+              warn_skipped_files(&skipped_files);
+              output_zip_file_handle
+            } else {
+              let crawl_result = crawl.crawl_paths().await?;
+              warn_skipped_files(&crawl_result.skipped_files);
+
+              /* Apply options from command line to produce a zip spec. */
+              let crawled_zip = crawl_result.medusa_zip(
+                zip_options.try_into()?,
+                modifications.into(),
+                parallelism.into(),
+              )?;
+
+              /* Do the parallel zip!!! */
+              crawled_zip.zip(output_zip, tuning.clone()).await?
+            };
 
             let merge_spec: MedusaMerge = merge.try_into()?;
             /* Copy over constituent zips into current. */
             let _output_file_handle = merge_spec
-              .merge(zip_options.mtime_behavior.into(), output_zip_file_handle)
+              .merge(mtime_behavior.into(), output_zip_file_handle, tuning)
               .await?;
           },
         }
 
         Ok(())
       }
+
+      /// Read entries from stdin in `input_format`, and zip them with
+      /// `parallelism` once stdin is exhausted. With `streaming`, each file is
+      /// instead compressed as soon as it is read.
+      async fn read_and_zip(
+        input_format: InputFormat,
+        root: Option<PathBuf>,
+        zip_options: ZipOutputOptions,
+        modifications: EntryModifications,
+        parallelism: Parallelism,
+        streaming: bool,
+        output_zip: OutputWrapper<ZipWriter<std::fs::File>>,
+        tuning: LibPipelineTuning,
+      ) -> eyre::Result<OutputWrapper<ZipWriter<std::fs::File>>> {
+        /* The sender is dropped, closing the channel, once stdin is exhausted. */
+        let (entries_tx, mut entries_rx) = mpsc::unbounded_channel::<StreamedEntry>();
+        let read = async move {
+          input_format
+            .read_into(io::BufReader::new(io::stdin()), root, entries_tx)
+            .await?;
+          Ok::<_, eyre::Report>(())
+        };
+
+        if streaming {
+          let zip = async move {
+            let output_zip =
+              MedusaZip::zip_streaming(entries_rx, zip_options, modifications, output_zip, tuning)
+                .await?;
+            Ok::<_, eyre::Report>(output_zip)
+          };
+          let ((), output_zip) = tokio::try_join!(read, zip)?;
+          return Ok(output_zip);
+        }
+
+        let collect = async move {
+          let mut input_files: Vec<FileSource> = Vec::new();
+          let mut input_symlinks: Vec<SymlinkSource> = Vec::new();
+          while let Some(entry) = entries_rx.recv().await {
+            match entry {
+              StreamedEntry::File(file) => input_files.push(file),
+              StreamedEntry::Symlink(symlink) => input_symlinks.push(symlink),
+            }
+          }
+          Ok::<_, eyre::Report>((input_files, input_symlinks))
+        };
+        let ((), (input_files, input_symlinks)) = tokio::try_join!(read, collect)?;
+
+        /* Do the parallel zip!!! */
+        let zip = MedusaZip {
+          input_files,
+          input_symlinks,
+          zip_options,
+          modifications,
+          parallelism,
+        };
+        Ok(zip.zip(output_zip, tuning).await?)
+      }
     }
   }
 }
@@ -9,12 +9,12 @@
 
 //! ???
 
-use libmedusa_zip::{self as lib, merge as lib_merge};
+use libmedusa_zip::{self as lib, merge as lib_merge, zip as lib_zip};
 
 use clap::Args;
 use eyre::{self, WrapErr};
 
-use std::{mem, path::PathBuf};
+use std::{mem, num::NonZeroUsize, path::PathBuf};
 
 
 #[derive(Clone, Debug, Args)]
@@ -73,3 +73,25 @@ impl TryFrom<MedusaMerge> for lib_merge::MedusaMerge {
     Ok(Self { groups: ret })
   }
 }
+
+
+/// The subset of [`crate::zip::PipelineTuning`] which affects a merge.
+#[derive(Clone, Debug, Args)]
+pub struct MergeTuning {
+  /// Number of source zips which may be opened ahead of being merged into the
+  /// output.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().parallel_merge_entries)]
+  pub parallel_merge_entries: NonZeroUsize,
+}
+
+impl From<MergeTuning> for lib_zip::PipelineTuning {
+  fn from(x: MergeTuning) -> Self {
+    let MergeTuning {
+      parallel_merge_entries,
+    } = x;
+    Self {
+      parallel_merge_entries,
+      ..Default::default()
+    }
+  }
+}
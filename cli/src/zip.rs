@@ -13,7 +13,7 @@ use crate::util::clap_handlers;
 
 #[cfg(doc)]
 use libmedusa_zip::merge::MergeGroup;
-use libmedusa_zip::zip as lib_zip;
+use libmedusa_zip::{cache as lib_cache, zip as lib_zip, EntryName, MedusaNameFormatError};
 
 use clap::{
   builder::{TypedValueParser, ValueParserFactory},
@@ -21,9 +21,15 @@ use clap::{
 };
 use displaydoc::Display;
 use eyre::{self, WrapErr};
+use thiserror::Error;
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use zip::DateTime as ZipDateTime;
 
+use std::{
+  num::{self, NonZeroU64, NonZeroUsize},
+  path::PathBuf,
+};
+
 
 #[derive(Copy, Clone, Default, Debug, ValueEnum)]
 pub enum AutomaticModifiedTimeStrategy {
@@ -149,6 +155,10 @@ pub enum CompressionMethod {
   /// deflate-compressed
   #[default]
   Deflated,
+  /// bzip2-compressed
+  Bzip2,
+  /// zstd-compressed
+  Zstd,
 }
 
 impl From<CompressionMethod> for lib_zip::CompressionMethod {
@@ -156,6 +166,8 @@ impl From<CompressionMethod> for lib_zip::CompressionMethod {
     match x {
       CompressionMethod::Stored => Self::Stored,
       CompressionMethod::Deflated => Self::Deflated,
+      CompressionMethod::Bzip2 => Self::Bzip2,
+      CompressionMethod::Zstd => Self::Zstd,
     }
   }
 }
@@ -170,9 +182,6 @@ pub struct CompressionOptions {
   /// regardless of this setting as an optimization.
   #[arg(value_enum, default_value_t, long)]
   pub compression_method: CompressionMethod,
-  /* /// - [`CompressionMethod::Bzip2`]: 0..=9 (default 6)
-   * /// - [`CompressionMethod::Zstd`]: -7..=22 (default 3)
-   */
   /// The degree of computational effort to exert for the
   /// [`Self::compression_method`].
   ///
@@ -181,17 +190,197 @@ pub struct CompressionOptions {
   ///   provided.
   /// - [`CompressionMethod::Deflated`]: 0..=9 (default 6)
   ///   - 0 is also mapped to "default".
+  /// - [`CompressionMethod::Bzip2`]: 1..=9 (default 6)
+  /// - [`CompressionMethod::Zstd`]: -7..=22 (default 3)
+  ///   - 0 is also mapped to "default".
   #[arg(long, default_value = None, requires = "compression_method", verbatim_doc_comment)]
   pub compression_level: Option<i8>,
 }
 
 
-#[derive(Copy, Clone, Default, Debug, Args)]
+/// Errors parsing a `--compression-rule` argument.
+#[derive(Debug, Display, Error)]
+pub enum CompressionRuleParseError {
+  /// rule {0:?} must provide a compression method after the last '='
+  MissingStrategy(String),
+  /// unrecognized compression method {0:?}
+  InvalidMethod(String),
+  /// invalid compression level {0:?}: {1}
+  InvalidLevel(String, num::ParseIntError),
+  /// error parsing compression strategy: {0}
+  Strategy(#[from] lib_zip::ParseCompressionOptionsError),
+  /// size range {0:?} must be "MIN-MAX" (either bound may be omitted)
+  MissingSizeSeparator(String),
+  /// invalid size {0:?}: {1}
+  InvalidSize(String, num::ParseIntError),
+  /// error parsing glob pattern: {0}
+  Glob(#[from] globset::Error),
+  /// error parsing regex pattern: {0}
+  Regex(#[from] regex::Error),
+  /// unrecognized rule condition {0:?} (expected "size:", "glob:", or "regex:")
+  UnknownCondition(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressionRuleWrapper(pub lib_zip::CompressionRule);
+
+impl CompressionRuleWrapper {
+  fn parse_size(size: &str) -> Result<Option<u64>, CompressionRuleParseError> {
+    if size.is_empty() {
+      return Ok(None);
+    }
+    let size: u64 = size
+      .parse()
+      .map_err(|e| CompressionRuleParseError::InvalidSize(size.to_string(), e))?;
+    Ok(Some(size))
+  }
+
+  fn parse_strategy(
+    strategy: &str,
+  ) -> Result<lib_zip::CompressionStrategy, CompressionRuleParseError> {
+    let (method, level) = match strategy.split_once(':') {
+      Some((method, level)) => {
+        let level: i8 = level
+          .parse()
+          .map_err(|e| CompressionRuleParseError::InvalidLevel(level.to_string(), e))?;
+        (method, Some(level))
+      },
+      None => (strategy, None),
+    };
+    let method = CompressionMethod::from_str(method, true)
+      .map_err(|_| CompressionRuleParseError::InvalidMethod(method.to_string()))?;
+    Ok(lib_zip::CompressionStrategy::from_method_and_level(
+      method.into(),
+      level,
+    )?)
+  }
+
+  pub fn parse(rule: &str) -> Result<Self, CompressionRuleParseError> {
+    let (conditions, strategy) = rule
+      .rsplit_once('=')
+      .ok_or_else(|| CompressionRuleParseError::MissingStrategy(rule.to_string()))?;
+    let strategy = Self::parse_strategy(strategy)?;
+
+    /* The size range is parsed first, so that any commas within a name pattern
+     * are left alone. */
+    let (mut min_size, mut max_size) = (None, None);
+    let mut conditions = conditions;
+    if let Some(rest) = conditions.strip_prefix("size:") {
+      let (range, remaining) = rest.split_once(',').unwrap_or((rest, ""));
+      let (min, max) = range
+        .split_once('-')
+        .ok_or_else(|| CompressionRuleParseError::MissingSizeSeparator(range.to_string()))?;
+      min_size = Self::parse_size(min)?;
+      max_size = Self::parse_size(max)?;
+      conditions = remaining;
+    }
+
+    let name_pattern = if conditions.is_empty() {
+      None
+    } else if let Some(glob) = conditions.strip_prefix("glob:") {
+      Some(lib_zip::EntryNamePattern::glob(glob)?)
+    } else if let Some(regex) = conditions.strip_prefix("regex:") {
+      Some(lib_zip::EntryNamePattern::regex(regex)?)
+    } else {
+      return Err(CompressionRuleParseError::UnknownCondition(
+        conditions.to_string(),
+      ));
+    };
+
+    Ok(Self(lib_zip::CompressionRule {
+      name_pattern,
+      min_size,
+      max_size,
+      strategy,
+    }))
+  }
+}
+
+#[derive(Clone)]
+pub struct CompressionRuleParser;
+
+impl TypedValueParser for CompressionRuleParser {
+  type Value = CompressionRuleWrapper;
+
+  fn parse_ref(
+    &self,
+    cmd: &clap::Command,
+    arg: Option<&clap::Arg>,
+    value: &std::ffi::OsStr,
+  ) -> Result<Self::Value, clap::Error> {
+    let inner = clap::builder::StringValueParser::new();
+    let val = inner.parse_ref(cmd, arg, value)?;
+
+    CompressionRuleWrapper::parse(&val).map_err(|e| {
+      let mut err = clap_handlers::prepare_clap_error(cmd, arg, &val);
+      clap_handlers::process_clap_error(
+        &mut err,
+        e,
+        "Provide a rule of the form '[size:MIN-MAX,][glob:PATTERN|regex:PATTERN]=METHOD[:LEVEL]', such as 'glob:*.jar=stored' or 'size:1000000-=zstd:10'.",
+      );
+      err
+    })
+  }
+}
+
+impl ValueParserFactory for CompressionRuleWrapper {
+  type Parser = CompressionRuleParser;
+
+  fn value_parser() -> Self::Parser { CompressionRuleParser }
+}
+
+
+#[derive(Clone, Debug, Args)]
 pub struct ZipOutputOptions {
   #[command(flatten)]
   pub mtime_behavior: ModifiedTimeBehavior,
   #[command(flatten)]
   pub compression_options: CompressionOptions,
+  /// Override the compression method and level for specific file entries.
+  ///
+  /// Rules are of the form
+  /// '[size:MIN-MAX,][glob:PATTERN|regex:PATTERN]=METHOD[:LEVEL]'. Size
+  /// bounds are inclusive and in bytes, and either bound may be omitted. Name
+  /// patterns are matched against the final entry name in the output zip, and
+  /// '*' in a glob will match across '/'. Rules are checked in order, and the
+  /// first matching rule is applied. Files matching no rule use
+  /// [`CompressionOptions`].
+  ///
+  /// After any rules provided here, files of at most 1000 bytes are stored
+  /// uncompressed. To compress them anyway, provide a rule such as
+  /// 'size:-1000=deflated'.
+  #[arg(long, verbatim_doc_comment)]
+  pub compression_rule: Vec<CompressionRuleWrapper>,
+  /// If provided, any compressed file entry which saves less than this
+  /// fraction of its uncompressed size (from 0.0 to 1.0) will be written
+  /// uncompressed instead.
+  ///
+  /// This avoids wasting space (and decompression time) on files which are
+  /// already compressed, such as jpgs or nested zips.
+  #[arg(long, default_value = None)]
+  pub incompressible_min_savings: Option<f64>,
+  /// If provided, any deflated file entry of at least this many bytes will be
+  /// split into blocks which are compressed in parallel (like pigz).
+  ///
+  /// The result is a single ordinary deflate entry, but slightly larger than
+  /// if the file were compressed as a whole.
+  #[arg(long, default_value = None)]
+  pub parallel_deflate_min_size: Option<u64>,
+  /// Size in bytes of each uncompressed block for
+  /// [`Self::parallel_deflate_min_size`].
+  #[arg(long, default_value_t = lib_zip::ParallelDeflate::default().block_size)]
+  pub parallel_deflate_block_size: NonZeroUsize,
+  /// If provided, reuse compressed file entries from this directory when the
+  /// same file contents were previously compressed with the same options, and
+  /// save newly compressed entries to it.
+  ///
+  /// The directory is created if it does not exist.
+  #[arg(long, default_value = None)]
+  pub entry_cache_dir: Option<PathBuf>,
+  /// Once the total size in bytes of [`Self::entry_cache_dir`] exceeds this
+  /// limit, the least recently used entries are deleted.
+  #[arg(long, default_value_t = lib_cache::EntryCache::DEFAULT_MAX_SIZE)]
+  pub entry_cache_max_size: u64,
 }
 
 impl TryFrom<ZipOutputOptions> for lib_zip::ZipOutputOptions {
@@ -205,15 +394,45 @@ impl TryFrom<ZipOutputOptions> for lib_zip::ZipOutputOptions {
           compression_method,
           compression_level,
         },
+      compression_rule,
+      incompressible_min_savings,
+      parallel_deflate_min_size,
+      parallel_deflate_block_size,
+      entry_cache_dir,
+      entry_cache_max_size,
     } = x;
     let compression_method: lib_zip::CompressionMethod = compression_method.into();
     let mtime_behavior: lib_zip::ModifiedTimeBehavior = mtime_behavior.into();
     let compression_options =
       lib_zip::CompressionStrategy::from_method_and_level(compression_method, compression_level)
         .wrap_err("error parsing compression strategy")?;
+    let lib_zip::CompressionPolicy {
+      rules: default_rules,
+    } = lib_zip::CompressionPolicy::default();
+    let compression_policy = lib_zip::CompressionPolicy {
+      rules: compression_rule
+        .into_iter()
+        .map(|CompressionRuleWrapper(rule)| rule)
+        .chain(default_rules)
+        .collect(),
+    };
+    let incompressible_detection = incompressible_min_savings
+      .map(lib_zip::IncompressibleDetection::new)
+      .transpose()
+      .wrap_err("error parsing incompressible detection")?;
+    let parallel_deflate = parallel_deflate_min_size.map(|min_size| lib_zip::ParallelDeflate {
+      min_size,
+      block_size: parallel_deflate_block_size,
+    });
+    let entry_cache =
+      entry_cache_dir.map(|dir| lib_cache::EntryCache::new(dir, entry_cache_max_size));
     Ok(Self {
       mtime_behavior,
       compression_options,
+      compression_policy,
+      incompressible_detection,
+      parallel_deflate,
+      entry_cache,
     })
   }
 }
@@ -238,6 +457,19 @@ pub struct EntryModifications {
   /* FIXME: explain how these work when stacked together! */
   #[arg(long, default_value = None)]
   pub own_prefix: Option<String>,
+  /// Rewrite the name of each file and symlink. Rewrites are applied in order,
+  /// before [`Self::silent_external_prefix`] or [`Self::own_prefix`].
+  ///
+  /// Rewrites are of the form 'strip-prefix:DIR', 'add-prefix:DIR', or
+  /// 'replace:REGEX=REPLACEMENT'. 'strip-prefix' only removes whole leading
+  /// directories, and the replacement may refer to capture groups such as
+  /// '$1'. Any names which are rewritten to the same name are reported as an
+  /// error along with their original names.
+  ///
+  /// `--rewrite strip-prefix:src --rewrite 'replace:^bin/=scripts/'` =>
+  /// `[src/a.py, bin/b] => [a.py, scripts/b]`
+  #[arg(long, verbatim_doc_comment)]
+  pub rewrite: Vec<NameRewriteWrapper>,
 }
 
 impl From<lib_zip::EntryModifications> for EntryModifications {
@@ -245,10 +477,12 @@ impl From<lib_zip::EntryModifications> for EntryModifications {
     let lib_zip::EntryModifications {
       silent_external_prefix,
       own_prefix,
+      rewrites,
     } = x;
     Self {
       silent_external_prefix,
       own_prefix,
+      rewrite: rewrites.into_iter().map(NameRewriteWrapper).collect(),
     }
   }
 }
@@ -258,14 +492,95 @@ impl From<EntryModifications> for lib_zip::EntryModifications {
     let EntryModifications {
       silent_external_prefix,
       own_prefix,
+      rewrite,
     } = x;
     Self {
       silent_external_prefix,
       own_prefix,
+      rewrites: rewrite
+        .into_iter()
+        .map(|NameRewriteWrapper(rewrite)| rewrite)
+        .collect(),
     }
   }
 }
 
+/// Errors parsing a `--rewrite` argument.
+#[derive(Debug, Display, Error)]
+pub enum NameRewriteParseError {
+  /// rewrite {0:?} must provide a replacement after '='
+  MissingReplacement(String),
+  /// invalid prefix: {0}
+  Prefix(#[from] MedusaNameFormatError),
+  /// error parsing regex pattern: {0}
+  Regex(#[from] regex::Error),
+  /// unknown rewrite {0:?} (expected strip-prefix:, add-prefix: or replace:)
+  UnknownRewrite(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct NameRewriteWrapper(pub lib_zip::NameRewrite);
+
+impl NameRewriteWrapper {
+  fn parse_prefix(prefix: &str) -> Result<EntryName, NameRewriteParseError> {
+    Ok(EntryName::validate(
+      prefix.trim_end_matches('/').to_string(),
+    )?)
+  }
+
+  pub fn parse(rewrite: &str) -> Result<Self, NameRewriteParseError> {
+    let rewrite = if let Some(prefix) = rewrite.strip_prefix("strip-prefix:") {
+      lib_zip::NameRewrite::StripPrefix(Self::parse_prefix(prefix)?)
+    } else if let Some(prefix) = rewrite.strip_prefix("add-prefix:") {
+      lib_zip::NameRewrite::AddPrefix(Self::parse_prefix(prefix)?)
+    } else if let Some(rest) = rewrite.strip_prefix("replace:") {
+      let (pattern, replacement) = rest
+        .split_once('=')
+        .ok_or_else(|| NameRewriteParseError::MissingReplacement(rewrite.to_string()))?;
+      lib_zip::NameRewrite::Replace {
+        pattern: regex::Regex::new(pattern)?,
+        replacement: replacement.to_string(),
+      }
+    } else {
+      return Err(NameRewriteParseError::UnknownRewrite(rewrite.to_string()));
+    };
+    Ok(Self(rewrite))
+  }
+}
+
+#[derive(Clone)]
+pub struct NameRewriteParser;
+
+impl TypedValueParser for NameRewriteParser {
+  type Value = NameRewriteWrapper;
+
+  fn parse_ref(
+    &self,
+    cmd: &clap::Command,
+    arg: Option<&clap::Arg>,
+    value: &std::ffi::OsStr,
+  ) -> Result<Self::Value, clap::Error> {
+    let inner = clap::builder::StringValueParser::new();
+    let val = inner.parse_ref(cmd, arg, value)?;
+
+    NameRewriteWrapper::parse(&val).map_err(|e| {
+      let mut err = clap_handlers::prepare_clap_error(cmd, arg, &val);
+      clap_handlers::process_clap_error(
+        &mut err,
+        e,
+        "Provide a rewrite of the form 'strip-prefix:DIR', 'add-prefix:DIR', or 'replace:REGEX=REPLACEMENT', such as 'strip-prefix:site-packages' or 'replace:\\.py\\.in$=.py'.",
+      );
+      err
+    })
+  }
+}
+
+impl ValueParserFactory for NameRewriteWrapper {
+  type Parser = NameRewriteParser;
+
+  fn value_parser() -> Self::Parser { NameRewriteParser }
+}
+
 
 #[derive(Copy, Clone, Default, Debug, ValueEnum)]
 pub enum Parallelism {
@@ -274,6 +589,12 @@ pub enum Parallelism {
   /// Parallelize creation by splitting up the input into chunks.
   #[default]
   ParallelMerge,
+  /// Like parallel-merge, but merge adjacent chunks together in parallel before
+  /// copying the few largest into the output zip.
+  ParallelTreeMerge,
+  /// Compress each file entry in parallel, then write them in order to a
+  /// single temporary zip which is copied into the output zip.
+  PerEntry,
 }
 
 
@@ -282,6 +603,8 @@ impl From<lib_zip::Parallelism> for Parallelism {
     match x {
       lib_zip::Parallelism::Synchronous => Self::Synchronous,
       lib_zip::Parallelism::ParallelMerge => Self::ParallelMerge,
+      lib_zip::Parallelism::ParallelTreeMerge => Self::ParallelTreeMerge,
+      lib_zip::Parallelism::PerEntry => Self::PerEntry,
     }
   }
 }
@@ -291,6 +614,127 @@ impl From<Parallelism> for lib_zip::Parallelism {
     match x {
       Parallelism::Synchronous => Self::Synchronous,
       Parallelism::ParallelMerge => Self::ParallelMerge,
+      Parallelism::ParallelTreeMerge => Self::ParallelTreeMerge,
+      Parallelism::PerEntry => Self::PerEntry,
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, Args)]
+pub struct PipelineTuning {
+  /// Maximum number of entries to write into each intermediate zip when using
+  /// `--parallelism=parallel-merge`.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().intermediate_chunk_size)]
+  pub intermediate_chunk_size: NonZeroUsize,
+  /// Total size in bytes of the source files to write into each intermediate
+  /// zip when using `--parallelism=parallel-merge`.
+  ///
+  /// Files at least this large are given an intermediate zip of their own.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().intermediate_chunk_bytes)]
+  pub intermediate_chunk_bytes: NonZeroU64,
+  /// Number of intermediate zips which may be written concurrently.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().max_parallel_intermediates)]
+  pub max_parallel_intermediates: NonZeroUsize,
+  /// Number of input files which may be opened and compressed ahead of being
+  /// merged into their intermediate zip.
+  #[arg(
+    long,
+    default_value_t = lib_zip::PipelineTuning::default().per_intermediate_file_io_queue_length
+  )]
+  pub per_intermediate_file_io_queue_length: NonZeroUsize,
+  /// Size in bytes above which an intermediate zip is written to disk instead
+  /// of memory.
+  #[arg(
+    long,
+    default_value_t = lib_zip::PipelineTuning::default().intermediate_output_spool_threshold
+  )]
+  pub intermediate_output_spool_threshold: usize,
+  /// Size in bytes above which the compressed contents of a single file entry
+  /// are written to disk instead of memory.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().per_file_spool_threshold)]
+  pub per_file_spool_threshold: usize,
+  /// Number of source zips which may be opened ahead of being merged into the
+  /// output.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().parallel_merge_entries)]
+  pub parallel_merge_entries: NonZeroUsize,
+  /// Number of intermediate zips at which to stop merging pairs together when
+  /// using `--parallelism=parallel-tree-merge`.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().tree_merge_final_width)]
+  pub tree_merge_final_width: NonZeroUsize,
+  /// Directory to create spooled temporary files in.
+  ///
+  /// Defaults to the system temp directory if not provided.
+  #[arg(long, default_value = None)]
+  pub temp_dir: Option<PathBuf>,
+  /// Maximum number of files which may be held open at once while zipping,
+  /// counting source files, the buffers they are compressed into, and
+  /// temporary zips.
+  ///
+  /// Defaults to the soft limit on open file descriptors, less a few reserved
+  /// for the process.
+  #[arg(long, default_value_t = lib_zip::PipelineTuning::default().max_open_files)]
+  pub max_open_files: NonZeroUsize,
+}
+
+impl Default for PipelineTuning {
+  fn default() -> Self { lib_zip::PipelineTuning::default().into() }
+}
+
+impl From<lib_zip::PipelineTuning> for PipelineTuning {
+  fn from(x: lib_zip::PipelineTuning) -> Self {
+    let lib_zip::PipelineTuning {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    } = x;
+    Self {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    }
+  }
+}
+
+impl From<PipelineTuning> for lib_zip::PipelineTuning {
+  fn from(x: PipelineTuning) -> Self {
+    let PipelineTuning {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    } = x;
+    Self {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
     }
   }
 }
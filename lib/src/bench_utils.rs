@@ -61,6 +61,7 @@ pub fn extract_example_zip(target: &Path, extract_dir: &Path) -> Result<Vec<File
         FileSource {
           name,
           source: absolute_path,
+          metadata: None,
         }
       }).collect();
 
@@ -100,21 +101,34 @@ pub fn execute_basic_crawl(extracted_dir: &Path) -> Result<lib_crawl::CrawlResul
       .strip_prefix(extracted_dir)
       .unwrap()
       .to_path_buf();
+    /* Symlinks are followed by the medusa crawl, so record their targets'
+     * metadata. */
+    let metadata = lib_crawl::EntryMetadata::from_metadata(
+      lib_crawl::EntryKind::File,
+      &fs::metadata(entry.path())?,
+    )?;
     let rp = if entry.path_is_symlink() {
       lib_crawl::ResolvedPath {
         unresolved_path,
         resolved_path: fs::read_link(entry.path())?,
+        metadata: Some(metadata),
+        digest: None,
       }
     } else {
       lib_crawl::ResolvedPath {
         unresolved_path,
         resolved_path: entry.path().to_path_buf(),
+        metadata: Some(metadata),
+        digest: None,
       }
     };
     real_file_paths.push(rp);
   }
 
-  let mut ret = lib_crawl::CrawlResult { real_file_paths };
+  let mut ret = lib_crawl::CrawlResult {
+    real_file_paths,
+    ..Default::default()
+  };
   ret.clean_up_for_export(extracted_dir);
   Ok(ret)
 }
@@ -125,15 +139,23 @@ pub async fn execute_medusa_zip(
 ) -> Result<zip::ZipArchive<fs::File>, lib_zip::MedusaZipError> {
   let zip_spec = lib_zip::MedusaZip {
     input_files,
+    input_symlinks: Vec::new(),
     zip_options: lib_zip::ZipOutputOptions {
       mtime_behavior: lib_zip::ModifiedTimeBehavior::Explicit(zip::DateTime::zero()),
       compression_options: lib_zip::CompressionStrategy::Deflated(Some(6)),
+      compression_policy: lib_zip::CompressionPolicy::default(),
+      incompressible_detection: None,
+      parallel_deflate: None,
+      entry_cache: None,
     },
     modifications: lib_zip::EntryModifications::default(),
     parallelism,
   };
   let output_zip = OutputWrapper::wrap(zip::ZipWriter::new(tempfile::tempfile()?));
-  let mut output_zip = zip_spec.zip(output_zip).await?.reclaim();
+  let mut output_zip = zip_spec
+    .zip(output_zip, lib_zip::PipelineTuning::default())
+    .await?
+    .reclaim();
   Ok(output_zip.finish_into_readable()?)
 }
 
@@ -146,7 +168,7 @@ pub fn execute_basic_zip(
     .compression_method(zip::CompressionMethod::Deflated)
     .compression_level(Some(6))
     .last_modified_time(zip::DateTime::zero());
-  for FileSource { name, source } in input_files.into_iter() {
+  for FileSource { name, source, .. } in input_files.into_iter() {
     let mut in_f = fs::OpenOptions::new().read(true).open(source)?;
     output_zip.start_file(name.into_string(), options)?;
     io::copy(&mut in_f, &mut output_zip)?;
@@ -0,0 +1,206 @@
+/*
+ * Description: ???
+ *
+ * Copyright (C) 2023 Danny McClanahan <dmcC2@hypnicjerk.ai>
+ * SPDX-License-Identifier: Apache-2.0
+ *
+ * Licensed under the Apache License, Version 2.0 (see LICENSE).
+ */
+
+//! ???
+
+use crate::{
+  precompressed::{CompressedEntry, EntryBuffer, EntryRecord, OrderedEntryWriter},
+  zip::PipelineTuning,
+  EntryName,
+};
+
+use displaydoc::Display;
+use sha2::{Digest, Sha256};
+use tempfile;
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+use std::{
+  fmt::Write as _,
+  fs,
+  io::{self, Read, Seek},
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+#[derive(Debug, Display, Error)]
+pub enum EntryCacheError {
+  /// i/o error accessing entry cache: {0}
+  Io(#[from] io::Error),
+  /// zip error reading or writing cached entry: {0}
+  Zip(#[from] ZipError),
+}
+
+/// Identifies the compressed form of a file's contents under a specific set of
+/// zip options.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntryCacheKey(String);
+
+impl EntryCacheKey {
+  pub fn as_str(&self) -> &str { self.0.as_str() }
+}
+
+/// An on-disk cache of compressed file entries, keyed by the contents of the
+/// source file along with every option which affects the compressed entry.
+///
+/// Each cached entry is stored as a zip file containing just that entry, so it
+/// can be raw-copied into the output without decompressing it. Entries are
+/// evicted in least-recently-used order once the cache grows beyond
+/// [`Self::max_size()`].
+#[derive(Clone, Debug)]
+pub struct EntryCache {
+  dir: PathBuf,
+  max_size: u64,
+}
+
+impl EntryCache {
+  pub const DEFAULT_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+  const ENTRY_EXTENSION: &'static str = "zip";
+
+  pub fn new(dir: PathBuf, max_size: u64) -> Self { Self { dir, max_size } }
+
+  pub fn dir(&self) -> &Path { &self.dir }
+
+  pub fn max_size(&self) -> u64 { self.max_size }
+
+  /// Create the cache directory if it does not already exist.
+  pub fn ensure_dir(&self) -> Result<(), EntryCacheError> {
+    fs::create_dir_all(&self.dir)?;
+    Ok(())
+  }
+
+  fn entry_path(&self, key: &EntryCacheKey) -> PathBuf {
+    self
+      .dir
+      .join(key.as_str())
+      .with_extension(Self::ENTRY_EXTENSION)
+  }
+
+  /// Hash the contents of `handle` along with `options`, which must describe
+  /// everything else that affects the compressed entry. `handle` is rewound
+  /// afterwards.
+  ///
+  /// The crate version is also hashed, so that entries are never reused across
+  /// versions which may compress them differently.
+  pub fn key_for_file(
+    &self,
+    handle: &mut fs::File,
+    options: &str,
+  ) -> Result<EntryCacheKey, EntryCacheError> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update([0]);
+    hasher.update(options.as_bytes());
+    hasher.update([0]);
+
+    let mut buf: Vec<u8> = vec![0; 64 * 1024];
+    loop {
+      let n = handle.read(&mut buf)?;
+      if n == 0 {
+        break;
+      }
+      hasher.update(&buf[..n]);
+    }
+    handle.rewind()?;
+
+    let mut key = String::new();
+    for byte in hasher.finalize().iter() {
+      write!(&mut key, "{:02x}", byte).expect("writing to a string should not fail");
+    }
+    Ok(EntryCacheKey(key))
+  }
+
+  /// Look up a cached entry, and if found, copy its compressed contents into a
+  /// new buffer under the entry name `name`.
+  pub fn get(
+    &self,
+    key: &EntryCacheKey,
+    name: &EntryName,
+    tuning: &PipelineTuning,
+  ) -> Result<Option<CompressedEntry>, EntryCacheError> {
+    let path = self.entry_path(key);
+    let cached = match fs::File::open(&path) {
+      Ok(cached) => cached,
+      /* The entry may also have just been evicted by another process. */
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(e.into()),
+    };
+    /* Mark this entry as recently used. This may fail if the cache is shared
+     * with other users, in which case the entry may just be evicted sooner. */
+    let _ = cached.set_modified(SystemTime::now());
+    let mut cached = ZipArchive::new(cached)?;
+
+    let mut cached = cached.by_index_raw(0)?;
+    let record = EntryRecord::copied(name, &cached)?;
+
+    let temp_file = tuning.spooled_tempfile(tuning.per_file_spool_threshold);
+    let mut data = EntryBuffer::Spooled(temp_file);
+    io::copy(&mut cached, &mut data)?;
+    data.rewind()?;
+    Ok(Some(CompressedEntry { record, data }))
+  }
+
+  /// Store `entry` under `key`, then rewind its compressed contents.
+  pub fn insert(
+    &self,
+    key: &EntryCacheKey,
+    entry: &mut CompressedEntry,
+  ) -> Result<(), EntryCacheError> {
+    /* Write to a temp file first, so that concurrent readers never see a
+     * partially written entry. */
+    let mut temp_entry = tempfile::NamedTempFile::new_in(&self.dir)?;
+    {
+      let mut writer = OrderedEntryWriter::new(temp_entry.as_file_mut());
+      writer.append_compressed(entry)?;
+      writer.finish()?;
+    }
+    entry.data.rewind()?;
+    temp_entry
+      .persist(self.entry_path(key))
+      .map_err(|e| e.error)?;
+    Ok(())
+  }
+
+  /// Delete the least recently used entries until the cache is no larger than
+  /// [`Self::max_size()`].
+  pub fn evict(&self) -> Result<(), EntryCacheError> {
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    for dir_entry in fs::read_dir(&self.dir)? {
+      let path = dir_entry?.path();
+      if path.extension().and_then(|ext| ext.to_str()) != Some(Self::ENTRY_EXTENSION) {
+        continue;
+      }
+      let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+        Err(e) => return Err(e.into()),
+      };
+      entries.push((metadata.modified()?, metadata.len(), path));
+    }
+
+    let mut total_size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    if total_size <= self.max_size {
+      return Ok(());
+    }
+    /* Oldest first. */
+    entries.sort();
+    for (_, len, path) in entries.into_iter() {
+      if total_size <= self.max_size {
+        break;
+      }
+      match fs::remove_file(&path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+      }
+      total_size -= len;
+    }
+    Ok(())
+  }
+}
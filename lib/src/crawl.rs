@@ -10,22 +10,43 @@
 //! ???
 
 use crate::{
-  zip::{EntryModifications, MedusaZip, Parallelism, ZipOutputOptions},
-  EntryName, FileSource, MedusaNameFormatError,
+  destination::OutputWrapper,
+  digest::{ContentDigest, DigestAlgorithm},
+  zip::{
+    EntryModifications, MedusaZip, MedusaZipError, Parallelism, PipelineTuning, StreamedEntry,
+    ZipOutputOptions,
+  },
+  EntryName, FileSource, MedusaNameFormatError, OpenFileLimit, SymlinkSource,
 };
 
 use async_recursion::async_recursion;
+use cfg_if::cfg_if;
 use displaydoc::Display;
-use futures::{future::try_join_all, stream::StreamExt};
+use futures::{
+  future::{self, try_join, try_join3, try_join_all},
+  stream::{StreamExt, TryStreamExt},
+  TryFutureExt,
+};
+use globset::{GlobBuilder, GlobMatcher};
 use rayon::prelude::*;
 use regex::RegexSet;
 use thiserror::Error;
-use tokio::{fs, io};
-use tokio_stream::wrappers::ReadDirStream;
+use tokio::{
+  fs, io,
+  sync::{mpsc, OwnedSemaphorePermit},
+  task,
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use zip::ZipWriter;
 
 use std::{
   env, fmt,
+  io::{Seek, Write},
+  num::NonZeroUsize,
   path::{Path, PathBuf},
+  process::{Command, ExitStatus},
+  sync::Arc,
+  time::SystemTime,
 };
 
 #[derive(Debug, Display, Error)]
@@ -40,6 +61,141 @@ pub enum MedusaCrawlError {
   Io(#[from] io::Error),
   /// crawl input format error: {0}
   CrawlFormat(#[from] MedusaCrawlFormatError),
+  /// path {0:?} resolves to {1:?} through a cycle of symlinks
+  SymlinkCycle(PathBuf, PathBuf),
+  /// directory {0:?} is nested more than the maximum depth of {1}
+  MaxDepthExceeded(PathBuf, usize),
+  /// path {0:?} is a {1}, which cannot be written to a zip file
+  SpecialFile(PathBuf, SpecialFileKind),
+  /// error parsing ignore file {0:?} at {1}
+  IgnoreFile(PathBuf, IgnoreFileError),
+  /// error running git: {0}
+  GitCommand(#[source] io::Error),
+  /// git ls-files failed with {0}: {1}
+  GitListFailed(ExitStatus, String),
+  /// git listed a path which was not valid utf-8: {0:?}
+  GitPathEncoding(Vec<u8>),
+  /// crawled path was not valid unicode: {0:?}
+  PathEncoding(PathBuf),
+  /// symlink {0:?} has a target which was not valid unicode: {1:?}
+  SymlinkTargetEncoding(PathBuf, PathBuf),
+  /// {0} cannot be used when crawling the git index
+  UnsupportedWithGitIndex(&'static str),
+  /// crawled path could not be used as an entry name: {0}
+  NameFormat(#[from] MedusaNameFormatError),
+  /// error computing digest of {0:?}: {1}
+  Digest(PathBuf, #[source] io::Error),
+  /// file {0:?} has no {1} digest
+  MissingDigest(PathBuf, DigestAlgorithm),
+  /// file {0:?} is not within the root directory {1:?}
+  OutsideRoot(PathBuf, PathBuf),
+}
+
+#[derive(Debug, Display, Error)]
+pub enum MedusaCrawlZipError {
+  /// error crawling: {0}
+  Crawl(#[from] MedusaCrawlError),
+  /// error zipping: {0}
+  Zip(#[from] MedusaZipError),
+}
+
+/// Any type of file which is not a regular file, directory, or symlink.
+#[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+pub enum SpecialFileKind {
+  /// fifo
+  Fifo,
+  /// socket
+  Socket,
+  /// block device
+  BlockDevice,
+  /// character device
+  CharDevice,
+  /// file of unknown type
+  Unknown,
+}
+
+impl SpecialFileKind {
+  fn from_file_type(file_type: std::fs::FileType) -> Self {
+    cfg_if! {
+      if #[cfg(unix)] {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+          Self::Fifo
+        } else if file_type.is_socket() {
+          Self::Socket
+        } else if file_type.is_block_device() {
+          Self::BlockDevice
+        } else if file_type.is_char_device() {
+          Self::CharDevice
+        } else {
+          Self::Unknown
+        }
+      } else {
+        let _ = file_type;
+        Self::Unknown
+      }
+    }
+  }
+}
+
+/// What to do upon encountering a path which is not a regular file, directory,
+/// or symlink, such as a unix socket.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum SpecialFilePolicy {
+  /// Fail the crawl with [`MedusaCrawlError::SpecialFile`].
+  #[default]
+  Error,
+  /// Leave the file out of the crawl, and record it in
+  /// [`CrawlResult::skipped_files`] so that the caller can report it.
+  Skip,
+}
+
+/// A file left out of the crawl with [`SpecialFilePolicy::Skip`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SkippedFile {
+  pub path: PathBuf,
+  pub kind: SpecialFileKind,
+}
+
+/// How a crawled path is recorded in a [`CrawlResult`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EntryKind {
+  /// A regular file, possibly reached by following symlinks.
+  File,
+  /// A symlink which was preserved instead of being followed.
+  Symlink,
+}
+
+/// Filesystem metadata captured when a path was crawled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct EntryMetadata {
+  pub kind: EntryKind,
+  /// For a symlink, the length of its target.
+  pub size: u64,
+  /// Unix permission bits, without the file type. On other platforms this is
+  /// approximated from whether the file is read-only.
+  pub mode: u32,
+  pub mtime: SystemTime,
+}
+
+impl EntryMetadata {
+  pub fn from_metadata(kind: EntryKind, metadata: &std::fs::Metadata) -> Result<Self, io::Error> {
+    cfg_if! {
+      if #[cfg(unix)] {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o7777;
+      } else {
+        let mode = if metadata.permissions().readonly() { 0o444 } else { 0o644 };
+      }
+    }
+    Ok(Self {
+      kind,
+      size: metadata.len(),
+      mode,
+      mtime: metadata.modified()?,
+    })
+  }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -49,6 +205,11 @@ pub struct ResolvedPath {
   /// The path *with* symlink resolution (may be the same, if the original
   /// path had no symlinks).
   pub resolved_path: PathBuf,
+  /// Metadata for [`Self::resolved_path`], if it was recorded by the crawl.
+  pub metadata: Option<EntryMetadata>,
+  /// Digest of the contents of [`Self::resolved_path`], if requested with
+  /// [`MedusaCrawl::digest`].
+  pub digest: Option<ContentDigest>,
 }
 
 impl ResolvedPath {
@@ -57,6 +218,7 @@ impl ResolvedPath {
     let Self {
       unresolved_path,
       resolved_path,
+      ..
     } = self;
     if let Ok(stripped) = resolved_path.strip_prefix(".") {
       *resolved_path = stripped.to_path_buf();
@@ -75,6 +237,8 @@ impl ResolvedPath {
     Self {
       resolved_path: root.join(&relative_path),
       unresolved_path: relative_path,
+      metadata: None,
+      digest: None,
     }
   }
 
@@ -82,46 +246,389 @@ impl ResolvedPath {
     let Self {
       unresolved_path,
       resolved_path,
+      ..
     } = self;
     Self {
       unresolved_path: unresolved_path.join(path),
       resolved_path: resolved_path.join(path),
+      metadata: None,
+      digest: None,
     }
   }
 
-  pub(crate) fn resolve_child_dir_entry(self, child: fs::DirEntry) -> Self {
+  fn with_metadata(self, kind: EntryKind, metadata: &std::fs::Metadata) -> Result<Self, io::Error> {
+    Ok(Self {
+      metadata: Some(EntryMetadata::from_metadata(kind, metadata)?),
+      ..self
+    })
+  }
+
+  pub(crate) fn resolve_child_dir_entry(self, child: &fs::DirEntry) -> Self {
     let file_name: PathBuf = child.file_name().into();
     self.join(&file_name)
   }
+
+  /// Rewrite [`Self::resolved_path`] to be relative to `root`. See
+  /// [`CrawlResult::strip_root()`].
+  pub fn strip_root(&mut self, root: &Path) -> Result<(), MedusaCrawlError> {
+    let Self { resolved_path, .. } = self;
+    let relative = resolved_path
+      .strip_prefix(root)
+      .map_err(|_| MedusaCrawlError::OutsideRoot(resolved_path.clone(), root.to_path_buf()))?;
+    *resolved_path = relative.to_path_buf();
+    Ok(())
+  }
+
+  /// Resolve [`Self::resolved_path`] against `root` if it is relative. See
+  /// [`CrawlResult::join_root()`].
+  pub fn join_root(&mut self, root: &Path) {
+    let Self { resolved_path, .. } = self;
+    if resolved_path.is_relative() {
+      *resolved_path = root.join(&resolved_path);
+    }
+  }
+
+  fn compute_digest(&mut self, algorithm: DigestAlgorithm) -> Result<(), MedusaCrawlError> {
+    let digest = algorithm
+      .digest_file(&self.resolved_path)
+      .map_err(|e| MedusaCrawlError::Digest(self.resolved_path.clone(), e))?;
+    self.digest = Some(digest);
+    Ok(())
+  }
+
+  fn into_file_source(self) -> Result<FileSource, MedusaCrawlError> {
+    let Self {
+      unresolved_path,
+      resolved_path,
+      metadata,
+      ..
+    } = self;
+    let name = unresolved_path
+      .into_os_string()
+      .into_string()
+      .map_err(|name| MedusaCrawlError::PathEncoding(name.into()))?;
+    Ok(FileSource {
+      name: EntryName::validate(name)?,
+      source: resolved_path,
+      metadata,
+    })
+  }
+}
+
+/// A symlink which was recorded as-is instead of being followed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedSymlink {
+  /// The path of the symlink itself.
+  pub unresolved_path: PathBuf,
+  /// The contents of the symlink, exactly as read by [`fs::read_link()`].
+  pub target: PathBuf,
+  /// Metadata for the symlink itself, if it was recorded by the crawl.
+  pub metadata: Option<EntryMetadata>,
+}
+
+impl ResolvedSymlink {
+  pub(crate) fn clean_up_for_export(&mut self) {
+    let Self {
+      unresolved_path, ..
+    } = self;
+    if let Ok(stripped) = unresolved_path.strip_prefix(".") {
+      *unresolved_path = stripped.to_path_buf();
+    }
+  }
+
+  fn into_symlink_source(self) -> Result<SymlinkSource, MedusaCrawlError> {
+    let Self {
+      unresolved_path,
+      target,
+      ..
+    } = self;
+    let target = match target.into_os_string().into_string() {
+      Ok(target) => target,
+      Err(target) => {
+        return Err(MedusaCrawlError::SymlinkTargetEncoding(
+          unresolved_path,
+          target.into(),
+        ))
+      },
+    };
+    let name = unresolved_path
+      .into_os_string()
+      .into_string()
+      .map_err(|name| MedusaCrawlError::PathEncoding(name.into()))?;
+    Ok(SymlinkSource {
+      name: EntryName::validate(name)?,
+      target,
+    })
+  }
+}
+
+/// What to do upon encountering a symlink while crawling.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum SymlinkBehavior {
+  /// Crawl the file or directory the symlink points to, as if it were located
+  /// at the symlink's path.
+  #[default]
+  Follow,
+  /// Record the symlink itself in [`CrawlResult::symlinks`], so that it is
+  /// written to the zip as a symlink entry.
+  Preserve,
+}
+
+/// What to do upon entering a directory through a symlink which is already
+/// being crawled higher up the same path, or upon a chain of symlinks which
+/// loops back on itself.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum SymlinkCyclePolicy {
+  /// Fail the crawl with [`MedusaCrawlError::SymlinkCycle`].
+  #[default]
+  Error,
+  /// Silently skip the symlink.
+  Skip,
+  /// Crawl the directory one more time, then skip the symlink when it is
+  /// encountered again within that copy. A chain of symlinks which loops back
+  /// on itself is skipped.
+  FollowOnce,
+}
+
+/// Where to find the paths to crawl.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum CrawlSource {
+  /// Walk the filesystem.
+  #[default]
+  Filesystem,
+  /// Crawl each file tracked by the git repository containing the crawl's
+  /// working directory, including those within any submodules.
+  ///
+  /// Tracked files which are not present in the working tree, such as those
+  /// excluded by a sparse checkout, are skipped. Directories are only ever
+  /// reached through symlinks, so per-directory ignore files are not supported,
+  /// and [`MedusaCrawl::ignore_file_names`] must be empty.
+  GitIndex,
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct CrawlResult {
   pub real_file_paths: Vec<ResolvedPath>,
+  pub symlinks: Vec<ResolvedSymlink>,
+  /// Files which were not written to the result, along with why.
+  pub skipped_files: Vec<SkippedFile>,
+}
+
+/// A file or symlink sent from [`MedusaCrawl::crawl_streaming()`] as soon as
+/// it has been found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CrawledEntry {
+  File(ResolvedPath),
+  Symlink(ResolvedSymlink),
+}
+
+impl CrawledEntry {
+  /// See [`ResolvedPath::strip_root()`]. Symlinks are left unchanged.
+  pub fn strip_root(&mut self, root: &Path) -> Result<(), MedusaCrawlError> {
+    match self {
+      Self::File(resolved_path) => resolved_path.strip_root(root),
+      Self::Symlink(_) => Ok(()),
+    }
+  }
+
+  /// See [`ResolvedPath::join_root()`]. Symlinks are left unchanged.
+  pub fn join_root(&mut self, root: &Path) {
+    if let Self::File(resolved_path) = self {
+      resolved_path.join_root(root);
+    }
+  }
+
+  pub fn into_streamed_entry(self) -> Result<StreamedEntry, MedusaCrawlError> {
+    Ok(match self {
+      Self::File(resolved_path) => StreamedEntry::File(resolved_path.into_file_source()?),
+      Self::Symlink(symlink) => StreamedEntry::Symlink(symlink.into_symlink_source()?),
+    })
+  }
+}
+
+/// Compute [`CrawlResult::aggregate_digest()`] one entry at a time, e.g. over
+/// the output of [`MedusaCrawl::crawl_streaming()`].
+#[derive(Debug)]
+pub struct AggregateDigest {
+  algorithm: DigestAlgorithm,
+  /* Each entry is tagged with its type after a NUL byte, which can't appear in
+   * a path, so that no two different sets of entries hash the same bytes. The
+   * hash is only computed in [`Self::finish()`], after sorting by name. */
+  entries: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl AggregateDigest {
+  pub fn new(algorithm: DigestAlgorithm) -> Self {
+    Self {
+      algorithm,
+      entries: Vec::new(),
+    }
+  }
+
+  /// The file must already have a digest from the same algorithm.
+  pub fn add_file(&mut self, resolved_path: &ResolvedPath) -> Result<(), MedusaCrawlError> {
+    let Self { algorithm, entries } = self;
+    let ResolvedPath {
+      unresolved_path,
+      digest,
+      ..
+    } = resolved_path;
+    let digest = digest
+      .as_ref()
+      .filter(|digest| digest.algorithm == *algorithm)
+      .ok_or_else(|| MedusaCrawlError::MissingDigest(unresolved_path.clone(), *algorithm))?;
+    let mut payload: Vec<u8> = vec![b'f'];
+    payload.extend_from_slice(&digest.bytes);
+    entries.push((unresolved_path.clone(), payload));
+    Ok(())
+  }
+
+  pub fn add_symlink(&mut self, symlink: &ResolvedSymlink) {
+    let ResolvedSymlink {
+      unresolved_path,
+      target,
+      ..
+    } = symlink;
+    let mut payload: Vec<u8> = vec![b'l'];
+    payload.extend_from_slice(target.as_os_str().as_encoded_bytes());
+    payload.push(0);
+    self.entries.push((unresolved_path.clone(), payload));
+  }
+
+  pub fn add(&mut self, entry: &CrawledEntry) -> Result<(), MedusaCrawlError> {
+    match entry {
+      CrawledEntry::File(resolved_path) => self.add_file(resolved_path),
+      CrawledEntry::Symlink(symlink) => {
+        self.add_symlink(symlink);
+        Ok(())
+      },
+    }
+  }
+
+  pub fn finish(self) -> ContentDigest {
+    let Self {
+      algorithm,
+      mut entries,
+    } = self;
+    entries.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = algorithm.hasher();
+    for (name, payload) in entries.into_iter() {
+      hasher.update(name.as_os_str().as_encoded_bytes());
+      hasher.update(&[0]);
+      hasher.update(&payload);
+    }
+    hasher.finalize()
+  }
 }
 
 impl CrawlResult {
   pub fn single(path: ResolvedPath) -> Self {
     Self {
       real_file_paths: vec![path],
+      ..Default::default()
+    }
+  }
+
+  pub fn single_symlink(symlink: ResolvedSymlink) -> Self {
+    Self {
+      symlinks: vec![symlink],
+      ..Default::default()
+    }
+  }
+
+  pub fn single_skipped(skipped: SkippedFile) -> Self {
+    Self {
+      skipped_files: vec![skipped],
+      ..Default::default()
     }
   }
 
   pub fn merge(results: Vec<Self>) -> Self {
-    let merged_file_paths: Vec<ResolvedPath> = results
-      .into_par_iter()
-      .flat_map(|Self { real_file_paths }| real_file_paths)
-      .collect();
+    let ((merged_file_paths, merged_symlinks), merged_skipped_files): ((Vec<_>, Vec<_>), Vec<_>) =
+      results
+        .into_par_iter()
+        .map(
+          |Self {
+             real_file_paths,
+             symlinks,
+             skipped_files,
+           }| ((real_file_paths, symlinks), skipped_files),
+        )
+        .unzip();
     Self {
-      real_file_paths: merged_file_paths,
+      real_file_paths: merged_file_paths.into_iter().flatten().collect(),
+      symlinks: merged_symlinks.into_iter().flatten().collect(),
+      skipped_files: merged_skipped_files.into_iter().flatten().collect(),
     }
   }
 
   pub fn clean_up_for_export(&mut self, cwd: &Path) {
-    let Self { real_file_paths } = self;
+    let Self {
+      real_file_paths,
+      symlinks,
+      ..
+    } = self;
     real_file_paths
       .par_iter_mut()
       .for_each(|resolved_path| resolved_path.clean_up_for_export(cwd));
+    symlinks
+      .par_iter_mut()
+      .for_each(ResolvedSymlink::clean_up_for_export);
+  }
+
+  /// Rewrite every [`ResolvedPath::resolved_path`] to be relative to `root`,
+  /// so that the result can be moved to another machine and resolved again
+  /// with [`Self::join_root()`].
+  pub fn strip_root(&mut self, root: &Path) -> Result<(), MedusaCrawlError> {
+    self
+      .real_file_paths
+      .par_iter_mut()
+      .try_for_each(|resolved_path| resolved_path.strip_root(root))
+  }
+
+  /// Resolve every relative [`ResolvedPath::resolved_path`] against `root`.
+  /// This undoes [`Self::strip_root()`].
+  pub fn join_root(&mut self, root: &Path) {
+    self
+      .real_file_paths
+      .par_iter_mut()
+      .for_each(|resolved_path| resolved_path.join_root(root));
+  }
+
+  /// Compute a digest of the contents of every file in parallel, and store it
+  /// in [`ResolvedPath::digest`].
+  pub fn compute_digests(&mut self, algorithm: DigestAlgorithm) -> Result<(), MedusaCrawlError> {
+    self
+      .real_file_paths
+      .par_iter_mut()
+      .try_for_each(|resolved_path| resolved_path.compute_digest(algorithm))
+  }
+
+  /// Compute a single digest over the name and digest of every file, along
+  /// with the name and target of every symlink.
+  ///
+  /// This changes whenever the entries of a zip produced from this crawl
+  /// would, so it can be combined with the zip options as a cache key for that
+  /// zip. Every file must already have a digest from `algorithm`, as from
+  /// [`Self::compute_digests()`].
+  pub fn aggregate_digest(
+    &self,
+    algorithm: DigestAlgorithm,
+  ) -> Result<ContentDigest, MedusaCrawlError> {
+    let Self {
+      real_file_paths,
+      symlinks,
+      ..
+    } = self;
+    let mut aggregate = AggregateDigest::new(algorithm);
+    for resolved_path in real_file_paths.iter() {
+      aggregate.add_file(resolved_path)?;
+    }
+    for symlink in symlinks.iter() {
+      aggregate.add_symlink(symlink);
+    }
+    Ok(aggregate.finish())
   }
 
   pub fn medusa_zip(
@@ -129,28 +636,23 @@ impl CrawlResult {
     zip_options: ZipOutputOptions,
     modifications: EntryModifications,
     parallelism: Parallelism,
-  ) -> Result<MedusaZip, MedusaNameFormatError> {
-    let Self { real_file_paths } = self;
+  ) -> Result<MedusaZip, MedusaCrawlError> {
+    let Self {
+      real_file_paths,
+      symlinks,
+      ..
+    } = self;
     let input_files: Vec<FileSource> = real_file_paths
       .into_par_iter()
-      .map(
-        |ResolvedPath {
-           unresolved_path,
-           resolved_path,
-         }| {
-          let name = unresolved_path
-            .into_os_string()
-            .into_string()
-            .expect("expected valid unicode path");
-          Ok(FileSource {
-            name: EntryName::validate(name)?,
-            source: resolved_path,
-          })
-        },
-      )
+      .map(ResolvedPath::into_file_source)
       .collect::<Result<Vec<FileSource>, _>>()?;
+    let input_symlinks: Vec<SymlinkSource> = symlinks
+      .into_par_iter()
+      .map(ResolvedSymlink::into_symlink_source)
+      .collect::<Result<Vec<SymlinkSource>, _>>()?;
     Ok(MedusaZip {
       input_files,
+      input_symlinks,
       zip_options,
       modifications,
       parallelism,
@@ -158,31 +660,279 @@ impl CrawlResult {
   }
 }
 
+#[derive(Debug, Display, Error)]
+pub enum GlobRuleParseError {
+  /// glob pattern {0:?} matches nothing
+  Empty(String),
+  /// error compiling glob pattern: {0}
+  Glob(#[from] globset::Error),
+}
+
+/// line {line}: {error}
+#[derive(Debug, Display, Error)]
+pub struct IgnoreFileError {
+  pub line: usize,
+  #[source]
+  pub error: GlobRuleParseError,
+}
+
+/// A single glob pattern in the syntax of a line from a `.gitignore` file.
+///
+/// - A leading `!` negates the pattern.
+/// - A trailing `/` only matches directories.
+/// - A pattern containing any other `/` is matched against the whole path, and
+///   a pattern without one is matched against the final component of the path
+///   at any depth.
+/// - `*` does not match across `/`, while `**` does.
+#[derive(Clone, Debug)]
+pub struct GlobRule {
+  pattern: String,
+  matcher: GlobMatcher,
+  negated: bool,
+  dir_only: bool,
+}
+
+impl GlobRule {
+  pub fn parse(pattern: &str) -> Result<Self, GlobRuleParseError> {
+    let (negated, glob) = match pattern.strip_prefix('!') {
+      Some(rest) => (true, rest),
+      None => (false, pattern),
+    };
+    let (dir_only, glob) = match glob.strip_suffix('/') {
+      Some(rest) => (true, rest),
+      None => (false, glob),
+    };
+    let glob: String = match glob.strip_prefix('/') {
+      Some(rest) => rest.to_string(),
+      None if glob.contains('/') => glob.to_string(),
+      None => format!("**/{}", glob),
+    };
+    if glob.is_empty() || glob == "**/" {
+      return Err(GlobRuleParseError::Empty(pattern.to_string()));
+    }
+    let matcher = GlobBuilder::new(&glob)
+      .literal_separator(true)
+      .build()?
+      .compile_matcher();
+    Ok(Self {
+      pattern: pattern.to_string(),
+      matcher,
+      negated,
+      dir_only,
+    })
+  }
+
+  pub fn as_str(&self) -> &str { &self.pattern }
+
+  pub fn is_negated(&self) -> bool { self.negated }
+
+  /// `path` must be relative to the directory the crawl started from.
+  pub fn is_match(&self, path: &Path, is_dir: bool) -> bool {
+    let Self {
+      matcher, dir_only, ..
+    } = self;
+    (is_dir || !dir_only) && matcher.is_match(path)
+  }
+}
+
+impl fmt::Display for GlobRule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.pattern) }
+}
+
+/// An ordered list of [`GlobRule`]s, where the last rule to match a path
+/// decides whether it is matched.
+#[derive(Clone, Default, Debug)]
+pub struct GlobRules {
+  pub rules: Vec<GlobRule>,
+}
+
+impl GlobRules {
+  pub fn new(rules: Vec<GlobRule>) -> Self { Self { rules } }
+
+  pub fn is_empty(&self) -> bool { self.rules.is_empty() }
+
+  /// Parse the contents of a `.gitignore` file, where each line which is not
+  /// blank or a `#` comment is a [`GlobRule`].
+  pub fn parse_ignore_file(contents: &str) -> Result<Self, IgnoreFileError> {
+    let mut rules: Vec<GlobRule> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+      /* Trailing spaces are ignored unless escaped with a backslash. */
+      let trimmed = line.trim_end();
+      let line = if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..trimmed.len() + 1]
+      } else {
+        trimmed
+      };
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let rule = GlobRule::parse(line).map_err(|error| IgnoreFileError {
+        line: index + 1,
+        error,
+      })?;
+      rules.push(rule);
+    }
+    Ok(Self { rules })
+  }
+
+  /// Returns `Some(true)` if the last rule matching `path` is a positive
+  /// pattern, `Some(false)` if it is a negated pattern, or `None` if no rule
+  /// matches.
+  pub fn last_match(&self, path: &Path, is_dir: bool) -> Option<bool> {
+    self
+      .rules
+      .iter()
+      .rev()
+      .find(|rule| rule.is_match(path, is_dir))
+      .map(|rule| !rule.is_negated())
+  }
+}
+
+impl fmt::Display for GlobRules {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let quoted: Vec<String> = self.rules.iter().map(|r| format!("'{}'", r)).collect();
+    let joined: String = quoted.join(", ");
+    write!(f, "[{}]", joined)
+  }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct Ignores {
   pub patterns: RegexSet,
+  /// Paths matching these globs are ignored, unless a later negated glob
+  /// matches them again. Ignoring a directory ignores everything inside it.
+  pub ignore_globs: GlobRules,
+  /// If non-empty, only files matching these globs are crawled. Directories
+  /// are always traversed.
+  pub include_globs: GlobRules,
 }
 
 impl Ignores {
-  pub fn new(patterns: RegexSet) -> Self { Self { patterns } }
+  pub fn new(patterns: RegexSet, ignore_globs: GlobRules, include_globs: GlobRules) -> Self {
+    Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    }
+  }
+
+  /// Glob patterns are matched against paths relative to the crawl's working
+  /// directory, without any leading `./`.
+  fn glob_path(path: &Path) -> &Path { path.strip_prefix(".").unwrap_or(path) }
+
+  pub fn should_ignore(&self, path: &Path, is_dir: bool) -> bool {
+    self.should_ignore_within(path, is_dir, None)
+  }
 
-  pub fn should_ignore(&self, path: &Path) -> bool {
-    let Self { patterns } = self;
+  /// Globs provided directly take precedence over those read from any ignore
+  /// files in `scope`.
+  fn should_ignore_within(&self, path: &Path, is_dir: bool, scope: Option<&IgnoreScope>) -> bool {
+    let Self {
+      patterns,
+      ignore_globs,
+      ..
+    } = self;
     let path_str = format!("{}", path.display());
-    patterns.is_match(&path_str)
+    if patterns.is_match(&path_str) {
+      return true;
+    }
+    let path = Self::glob_path(path);
+    if path.as_os_str().is_empty() {
+      return false;
+    }
+    ignore_globs
+      .last_match(path, is_dir)
+      .or_else(|| scope.and_then(|scope| scope.last_match(path, is_dir)))
+      .unwrap_or(false)
+  }
+
+  /// Whether a file at `path` matches the include globs (or there are none).
+  pub fn is_included(&self, path: &Path) -> bool {
+    let Self { include_globs, .. } = self;
+    if include_globs.is_empty() {
+      return true;
+    }
+    include_globs
+      .last_match(Self::glob_path(path), false)
+      .unwrap_or(false)
+  }
+}
+
+/// Glob rules read from ignore files within a single directory, which apply to
+/// every path underneath that directory.
+#[derive(Debug)]
+struct IgnoreScope {
+  /// Where the ignore files were found, in the same form as
+  /// [`Ignores::glob_path()`].
+  dir: PathBuf,
+  rules: GlobRules,
+  parent: Option<Arc<IgnoreScope>>,
+}
+
+impl IgnoreScope {
+  /// Read any of [`CrawlSettings::ignore_file_names`] from `dir`, returning a
+  /// new scope nested within `parent` if any rules were found.
+  async fn enter(
+    dir: &ResolvedPath,
+    settings: &CrawlSettings,
+    parent: Option<&Arc<Self>>,
+  ) -> Result<Option<Arc<Self>>, MedusaCrawlError> {
+    let mut rules: Vec<GlobRule> = Vec::new();
+    for name in settings.ignore_file_names.iter() {
+      let ignore_file = dir.resolved_path.join(name);
+      let _permit = settings.open_file_permit().await;
+      let contents = match fs::read_to_string(&ignore_file).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+        Err(e) => return Err(e.into()),
+      };
+      let GlobRules { rules: file_rules } = GlobRules::parse_ignore_file(&contents)
+        .map_err(|e| MedusaCrawlError::IgnoreFile(ignore_file, e))?;
+      rules.extend(file_rules);
+    }
+    if rules.is_empty() {
+      return Ok(parent.cloned());
+    }
+    Ok(Some(Arc::new(Self {
+      dir: Ignores::glob_path(&dir.unresolved_path).to_path_buf(),
+      rules: GlobRules::new(rules),
+      parent: parent.cloned(),
+    })))
+  }
+
+  /// Rules in more deeply nested directories take precedence.
+  fn last_match(&self, path: &Path, is_dir: bool) -> Option<bool> {
+    let mut scope = Some(self);
+    while let Some(Self { dir, rules, parent }) = scope {
+      if let Ok(relative) = path.strip_prefix(dir) {
+        if let Some(matched) = rules.last_match(relative, is_dir) {
+          return Some(matched);
+        }
+      }
+      scope = parent.as_deref();
+    }
+    None
   }
 }
 
 impl fmt::Display for Ignores {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let Self { patterns } = self;
+    let Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    } = self;
     let quoted: Vec<String> = patterns
       .patterns()
       .iter()
       .map(|s| format!("'{}'", s))
       .collect();
     let joined: String = quoted.join(", ");
-    write!(f, "[{}]", joined)
+    write!(
+      f,
+      "[{}] (ignore globs: {}, include globs: {})",
+      joined, ignore_globs, include_globs
+    )
   }
 }
 
@@ -191,6 +941,7 @@ enum Entry {
   Symlink(ResolvedPath),
   Directory(ResolvedPath),
   File(ResolvedPath),
+  Special(ResolvedPath, SpecialFileKind),
 }
 
 impl Entry {
@@ -199,84 +950,411 @@ impl Entry {
       Self::Symlink(p) => p,
       Self::Directory(p) => p,
       Self::File(p) => p,
+      Self::Special(p, _) => p,
     }
   }
 
-  pub fn should_ignore_this(&self, ignores: &Ignores) -> bool {
+  pub fn should_ignore_this(
+    &self,
+    ignores: &Ignores,
+    scope: Option<&IgnoreScope>,
+    symlink_behavior: SymlinkBehavior,
+  ) -> bool {
     let ResolvedPath {
       unresolved_path, ..
     } = self.as_resolved_path();
+    let (is_dir, is_crawled_as_file) = match self {
+      Self::Directory(_) => (true, false),
+      /* A symlink which is followed is checked again against its target's type. */
+      Self::Symlink(_) if matches!(symlink_behavior, SymlinkBehavior::Follow) => (false, false),
+      _ => (false, true),
+    };
     /* NB: Because we are doing regex-based matching, we are intentionally not
      * taking into account matching against any idea of filesystem structure.
      * To this end, our "ignores" will not detect if a symlink leads to a
      * path which itself is ignored, but only whether the path
      * before expanding any symlinks matches the regex pattern. */
-    ignores.should_ignore(unresolved_path)
+    ignores.should_ignore_within(unresolved_path, is_dir, scope)
+      || (is_crawled_as_file && !ignores.is_included(unresolved_path))
+  }
+}
+
+cfg_if! {
+  if #[cfg(unix)] {
+    /// Identifies a directory regardless of the path used to reach it.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct DirectoryId {
+      dev: u64,
+      ino: u64,
+    }
+
+    impl DirectoryId {
+      async fn for_dir(path: &Path) -> Result<Self, io::Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path).await?;
+        Ok(Self {
+          dev: metadata.dev(),
+          ino: metadata.ino(),
+        })
+      }
+
+      fn device(&self) -> Option<u64> { Some(self.dev) }
+    }
+
+    fn device_of(metadata: &std::fs::Metadata) -> Option<u64> {
+      use std::os::unix::fs::MetadataExt;
+
+      Some(metadata.dev())
+    }
+  } else {
+    /// Identifies a directory regardless of the path used to reach it.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct DirectoryId(PathBuf);
+
+    impl DirectoryId {
+      async fn for_dir(path: &Path) -> Result<Self, io::Error> {
+        Ok(Self(fs::canonicalize(path).await?))
+      }
+
+      fn device(&self) -> Option<u64> { None }
+    }
+
+    fn device_of(_metadata: &std::fs::Metadata) -> Option<u64> { None }
+  }
+}
+
+/// Predicates on the metadata of each file and directory, which are checked
+/// as the crawl reaches them.
+#[derive(Clone, Debug, Default)]
+pub struct FileFilters {
+  /// If provided, skip files larger than this many bytes.
+  pub max_size: Option<u64>,
+  /// If provided, skip files last modified before this time.
+  pub modified_after: Option<SystemTime>,
+  /// If provided, skip files last modified at or after this time.
+  pub modified_before: Option<SystemTime>,
+  /// If set, skip any file or directory which is on a different filesystem
+  /// than the directory being crawled, like `find -xdev`.
+  ///
+  /// This is checked against each of [`MedusaCrawl::paths_to_crawl`] which is
+  /// a directory, or against the working directory with
+  /// [`CrawlSource::GitIndex`]. Only supported on unix.
+  pub same_file_system: bool,
+}
+
+impl FileFilters {
+  fn excludes_file(
+    &self,
+    metadata: &std::fs::Metadata,
+    ancestors: &[DirectoryId],
+  ) -> Result<bool, io::Error> {
+    let Self {
+      max_size,
+      modified_after,
+      modified_before,
+      ..
+    } = self;
+    if max_size.is_some_and(|max_size| metadata.len() > max_size) {
+      return Ok(true);
+    }
+    if modified_after.is_some() || modified_before.is_some() {
+      let mtime = metadata.modified()?;
+      if modified_after.is_some_and(|after| mtime < after)
+        || modified_before.is_some_and(|before| mtime >= before)
+      {
+        return Ok(true);
+      }
+    }
+    Ok(self.crosses_file_system(ancestors, device_of(metadata)))
+  }
+
+  fn crosses_file_system(&self, ancestors: &[DirectoryId], device: Option<u64>) -> bool {
+    if !self.same_file_system {
+      return false;
+    }
+    /* The first ancestor is the directory the crawl started from. */
+    match (ancestors.first().and_then(DirectoryId::device), device) {
+      (Some(root), Some(device)) => root != device,
+      _ => false,
+    }
+  }
+}
+
+/// Options which apply to every path visited during a single crawl.
+#[derive(Debug)]
+struct CrawlSettings {
+  ignores: Ignores,
+  filters: FileFilters,
+  ignore_file_names: Vec<String>,
+  symlink_behavior: SymlinkBehavior,
+  symlink_cycle_policy: SymlinkCyclePolicy,
+  max_depth: Option<usize>,
+  special_file_policy: SpecialFilePolicy,
+  /// Held for the duration of each filesystem operation, so that the crawl
+  /// never has more than this many files open at once.
+  open_files: OpenFileLimit,
+  /// If provided, each file and symlink is sent here as soon as it is found,
+  /// instead of being returned in a [`CrawlResult`].
+  sink: Option<CrawlSink>,
+}
+
+#[derive(Debug)]
+struct CrawlSink {
+  cwd: PathBuf,
+  entries: mpsc::UnboundedSender<CrawledEntry>,
+}
+
+impl CrawlSettings {
+  /// Send the contents of `result` to [`Self::sink`] if there is one, or
+  /// otherwise return it unchanged.
+  fn emit(&self, mut result: CrawlResult) -> Result<CrawlResult, MedusaCrawlError> {
+    let Some(CrawlSink { cwd, entries }) = &self.sink else {
+      return Ok(result);
+    };
+    result.clean_up_for_export(cwd);
+    let CrawlResult {
+      real_file_paths,
+      symlinks,
+      skipped_files,
+    } = result;
+    /* The receiver is only dropped if its consumer has failed, and that error
+     * is reported instead. */
+    for path in real_file_paths.into_iter() {
+      let _ = entries.send(CrawledEntry::File(path));
+    }
+    for symlink in symlinks.into_iter() {
+      let _ = entries.send(CrawledEntry::Symlink(symlink));
+    }
+    /* These are returned from the crawl instead. */
+    Ok(CrawlResult {
+      skipped_files,
+      ..Default::default()
+    })
+  }
+
+  async fn open_file_permit(&self) -> OwnedSemaphorePermit { self.open_files.acquire(1).await }
+
+  /// Read every entry of `dir` up front, so that the directory is closed again
+  /// before any of its entries are crawled.
+  async fn read_dir_entries(&self, dir: &ResolvedPath) -> Result<Vec<Input>, io::Error> {
+    let _permit = self.open_file_permit().await;
+    let mut read_dir = fs::read_dir(&dir.resolved_path).await?;
+    let mut entries: Vec<Input> = Vec::new();
+    while let Some(dir_entry) = read_dir.next_entry().await? {
+      let file_type = dir_entry.file_type().await?;
+      entries.push(Input::DirEntry(
+        dir.clone().resolve_child_dir_entry(&dir_entry),
+        file_type,
+      ));
+    }
+    Ok(entries)
   }
 }
 
 #[derive(Debug)]
 enum Input {
   Path(ResolvedPath),
-  /// The `ResolvedPath` corresponds to the parent directory.
-  DirEntry(ResolvedPath, fs::DirEntry),
+  /// A path whose file type (without following symlinks) is already known,
+  /// such as an entry read from its parent directory.
+  DirEntry(ResolvedPath, std::fs::FileType),
+  /// A path whose metadata (without following symlinks) has already been read.
+  Stat(ResolvedPath, std::fs::Metadata),
 }
 
 impl Input {
-  async fn classify(self) -> Result<Entry, io::Error> {
-    let (file_type, path) = match self {
+  /// The longest chain of symlinks to follow before treating it as a cycle,
+  /// which is the same limit linux places on path resolution.
+  const MAX_SYMLINK_HOPS: usize = 40;
+
+  /// Determine the type of this path, along with its metadata if that had to
+  /// be read to do so.
+  async fn classify(
+    self,
+    settings: &CrawlSettings,
+  ) -> Result<(Entry, Option<std::fs::Metadata>), io::Error> {
+    let (file_type, path, metadata) = match self {
       Self::Path(path) => {
-        let file_type = fs::symlink_metadata(&path.resolved_path).await?.file_type();
-        (file_type, path)
-      },
-      Self::DirEntry(parent_path, entry) => {
-        let file_type = entry.file_type().await?;
-        (file_type, parent_path.resolve_child_dir_entry(entry))
+        let _permit = settings.open_file_permit().await;
+        let metadata = fs::symlink_metadata(&path.resolved_path).await?;
+        (metadata.file_type(), path, Some(metadata))
       },
+      Self::DirEntry(path, file_type) => (file_type, path, None),
+      Self::Stat(path, metadata) => (metadata.file_type(), path, Some(metadata)),
     };
-    if file_type.is_symlink() {
-      Ok(Entry::Symlink(path))
+    let entry = if file_type.is_symlink() {
+      Entry::Symlink(path)
     } else if file_type.is_dir() {
-      Ok(Entry::Directory(path))
+      Entry::Directory(path)
+    } else if file_type.is_file() {
+      Entry::File(path)
     } else {
-      assert!(file_type.is_file());
-      Ok(Entry::File(path))
-    }
+      Entry::Special(path, SpecialFileKind::from_file_type(file_type))
+    };
+    Ok((entry, metadata))
   }
 
+  /// `ancestors` contains each directory above this path which is currently
+  /// being crawled, starting from the top-level path. `scope` contains the
+  /// rules from any ignore files found within those directories.
   #[async_recursion]
-  pub async fn crawl_single(self, ignores: &Ignores) -> Result<CrawlResult, MedusaCrawlError> {
-    let classified = self.classify().await?;
-    if classified.should_ignore_this(ignores) {
+  async fn crawl_single(
+    self,
+    settings: &CrawlSettings,
+    ancestors: &[DirectoryId],
+    scope: Option<&'async_recursion Arc<IgnoreScope>>,
+  ) -> Result<CrawlResult, MedusaCrawlError> {
+    let CrawlSettings {
+      ignores,
+      filters,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      ..
+    } = settings;
+    let (classified, metadata) = self.classify(settings).await?;
+    if classified.should_ignore_this(ignores, scope.map(Arc::as_ref), *symlink_behavior) {
       return Ok(CrawlResult::default());
     }
     match classified {
-      Entry::File(resolved_path) => Ok(CrawlResult::single(resolved_path)),
+      Entry::File(resolved_path) => {
+        let metadata = match metadata {
+          Some(metadata) => metadata,
+          None => {
+            let _permit = settings.open_file_permit().await;
+            fs::symlink_metadata(&resolved_path.resolved_path).await?
+          },
+        };
+        if filters.excludes_file(&metadata, ancestors)? {
+          return Ok(CrawlResult::default());
+        }
+        settings.emit(CrawlResult::single(
+          resolved_path.with_metadata(EntryKind::File, &metadata)?,
+        ))
+      },
+      Entry::Special(ResolvedPath { resolved_path, .. }, kind) => match special_file_policy {
+        SpecialFilePolicy::Error => Err(MedusaCrawlError::SpecialFile(resolved_path, kind)),
+        SpecialFilePolicy::Skip => Ok(CrawlResult::single_skipped(SkippedFile {
+          path: resolved_path,
+          kind,
+        })),
+      },
+      Entry::Symlink(resolved_path) if matches!(symlink_behavior, SymlinkBehavior::Preserve) => {
+        let _permit = settings.open_file_permit().await;
+        let metadata = match metadata {
+          Some(metadata) => metadata,
+          None => fs::symlink_metadata(&resolved_path.resolved_path).await?,
+        };
+        let ResolvedPath {
+          unresolved_path,
+          resolved_path,
+          metadata,
+          ..
+        } = resolved_path.with_metadata(EntryKind::Symlink, &metadata)?;
+        let target = fs::read_link(&resolved_path).await?;
+        settings.emit(CrawlResult::single_symlink(ResolvedSymlink {
+          unresolved_path,
+          target,
+          metadata,
+        }))
+      },
       Entry::Symlink(ResolvedPath {
         unresolved_path,
         resolved_path,
+        ..
       }) => {
-        /* Symlinks are resolved relative to the parent directory! */
-        let resolved_parent_dir = resolved_path
-          .parent()
-          .expect("should always be a parent, even if empty");
-        let new_path = resolved_parent_dir.join(fs::read_link(&resolved_path).await?);
-        let inner = Self::Path(ResolvedPath {
-          unresolved_path,
-          resolved_path: new_path,
-        });
-        Ok(inner.crawl_single(ignores).await?)
+        /* Follow the whole chain of symlinks here, since a chain which loops back
+         * on itself never reaches a directory to be caught below. */
+        let mut new_path = resolved_path;
+        let mut hops: usize = 0;
+        let metadata = loop {
+          /* Symlinks are resolved relative to the parent directory! */
+          let resolved_parent_dir = new_path
+            .parent()
+            .expect("should always be a parent, even if empty");
+          let _permit = settings.open_file_permit().await;
+          new_path = resolved_parent_dir.join(fs::read_link(&new_path).await?);
+          let metadata = fs::symlink_metadata(&new_path).await?;
+          if !metadata.file_type().is_symlink() {
+            break metadata;
+          }
+          hops += 1;
+          if hops >= Self::MAX_SYMLINK_HOPS {
+            return match symlink_cycle_policy {
+              SymlinkCyclePolicy::Error => {
+                Err(MedusaCrawlError::SymlinkCycle(unresolved_path, new_path))
+              },
+              /* There is no directory to follow even once. */
+              SymlinkCyclePolicy::Skip | SymlinkCyclePolicy::FollowOnce => {
+                Ok(CrawlResult::default())
+              },
+            };
+          }
+        };
+        let inner = Self::Stat(
+          ResolvedPath {
+            unresolved_path,
+            resolved_path: new_path,
+            metadata: None,
+            digest: None,
+          },
+          metadata,
+        );
+        Ok(inner.crawl_single(settings, ancestors, scope).await?)
       },
       Entry::Directory(parent_resolved_path) => {
-        let results = ReadDirStream::new(fs::read_dir(&parent_resolved_path.resolved_path).await?)
-          .then(|dir_entry| async {
-            let inner = Self::DirEntry(parent_resolved_path.clone(), dir_entry?);
-            inner.crawl_single(ignores).await
-          })
-          .collect::<Vec<Result<CrawlResult, MedusaCrawlError>>>()
-          .await
-          .into_iter()
-          .collect::<Result<Vec<CrawlResult>, MedusaCrawlError>>()?;
+        /* A directory can only be its own ancestor if we reached it through a
+         * symlink. */
+        let dir_id = {
+          let _permit = settings.open_file_permit().await;
+          DirectoryId::for_dir(&parent_resolved_path.resolved_path).await?
+        };
+        if filters.crosses_file_system(ancestors, dir_id.device()) {
+          return Ok(CrawlResult::default());
+        }
+        let times_visited = ancestors.iter().filter(|a| **a == dir_id).count();
+        if times_visited > 0 {
+          match symlink_cycle_policy {
+            SymlinkCyclePolicy::Error => {
+              let ResolvedPath {
+                unresolved_path,
+                resolved_path,
+                ..
+              } = parent_resolved_path;
+              return Err(MedusaCrawlError::SymlinkCycle(
+                unresolved_path,
+                resolved_path,
+              ));
+            },
+            SymlinkCyclePolicy::FollowOnce if times_visited == 1 => (),
+            SymlinkCyclePolicy::Skip | SymlinkCyclePolicy::FollowOnce => {
+              return Ok(CrawlResult::default());
+            },
+          }
+        }
+        if let Some(max_depth) = max_depth {
+          if ancestors.len() > *max_depth {
+            return Err(MedusaCrawlError::MaxDepthExceeded(
+              parent_resolved_path.unresolved_path,
+              *max_depth,
+            ));
+          }
+        }
+        let ancestors: Vec<DirectoryId> = ancestors
+          .iter()
+          .cloned()
+          .chain(std::iter::once(dir_id))
+          .collect();
+        let scope = IgnoreScope::enter(&parent_resolved_path, settings, scope).await?;
+
+        let entries = settings.read_dir_entries(&parent_resolved_path).await?;
+        let results: Vec<CrawlResult> = try_join_all(
+          entries
+            .into_iter()
+            .map(|inner| inner.crawl_single(settings, &ancestors, scope.as_ref())),
+        )
+        .await?;
         Ok(CrawlResult::merge(results))
       },
     }
@@ -287,7 +1365,35 @@ impl Input {
 pub struct MedusaCrawl {
   pub paths_to_crawl: Vec<PathBuf>,
   pub ignores: Ignores,
+  pub filters: FileFilters,
   pub cwd: Option<PathBuf>,
+  pub symlink_behavior: SymlinkBehavior,
+  pub symlink_cycle_policy: SymlinkCyclePolicy,
+  /// If provided, fail the crawl upon reaching any directory nested more than
+  /// this many levels below one of [`Self::paths_to_crawl`].
+  pub max_depth: Option<usize>,
+  pub special_file_policy: SpecialFilePolicy,
+  /// Names of files such as `.gitignore` to read from each directory crawled.
+  /// Each file contains [`GlobRule`]s which are matched against paths relative
+  /// to its directory, like a `.gitignore` file.
+  ///
+  /// Rules from later file names and deeper directories take precedence, while
+  /// [`Ignores::ignore_globs`] take precedence over all of them.
+  pub ignore_file_names: Vec<String>,
+  /// With [`CrawlSource::GitIndex`], each of [`Self::paths_to_crawl`] is
+  /// used as a pathspec to select tracked files.
+  pub source: CrawlSource,
+  /// Maximum number of directories, files, and symlinks which may be open or
+  /// read from at once. Defaults to [`crate::default_max_open_files()`].
+  ///
+  /// [`Self::crawl_zip()`] ignores this, and counts the crawl against
+  /// [`PipelineTuning::max_open_files`] instead, so that the crawl and the zip
+  /// share a single limit.
+  pub max_open_files: NonZeroUsize,
+  /// If provided, [`Self::crawl_paths()`] computes a digest of each file's
+  /// contents in parallel once the crawl is complete. See
+  /// [`CrawlResult::compute_digests()`].
+  pub digest: Option<DigestAlgorithm>,
 }
 
 impl Default for MedusaCrawl {
@@ -295,7 +1401,16 @@ impl Default for MedusaCrawl {
     Self {
       paths_to_crawl: vec![PathBuf::from(".")],
       ignores: Ignores::default(),
+      filters: FileFilters::default(),
       cwd: None,
+      symlink_behavior: SymlinkBehavior::default(),
+      symlink_cycle_policy: SymlinkCyclePolicy::default(),
+      max_depth: None,
+      special_file_policy: SpecialFilePolicy::default(),
+      ignore_file_names: Vec::new(),
+      source: CrawlSource::default(),
+      max_open_files: crate::default_max_open_files(),
+      digest: None,
     }
   }
 }
@@ -305,17 +1420,110 @@ impl MedusaCrawl {
     Self {
       paths_to_crawl: vec![PathBuf::from(".")],
       ignores,
+      filters: FileFilters::default(),
       cwd: Some(dir),
+      symlink_behavior: SymlinkBehavior::default(),
+      symlink_cycle_policy: SymlinkCyclePolicy::default(),
+      max_depth: None,
+      special_file_policy: SpecialFilePolicy::default(),
+      ignore_file_names: Vec::new(),
+      source: CrawlSource::default(),
+      max_open_files: crate::default_max_open_files(),
+      digest: None,
     }
   }
 
-  pub async fn crawl_paths(self) -> Result<CrawlResult, MedusaCrawlError> {
+  /// List the files tracked by git which match `pathspecs`, relative to `cwd`.
+  async fn list_git_files(
+    cwd: PathBuf,
+    pathspecs: Vec<PathBuf>,
+  ) -> Result<Vec<PathBuf>, MedusaCrawlError> {
+    let output = tokio::task::spawn_blocking(move || {
+      Command::new("git")
+        .current_dir(&cwd)
+        .args(["ls-files", "-z", "--cached", "--recurse-submodules", "--"])
+        .args(pathspecs)
+        .output()
+    })
+    .await
+    .expect("git subprocess task panicked")
+    .map_err(MedusaCrawlError::GitCommand)?;
+    if !output.status.success() {
+      let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+      return Err(MedusaCrawlError::GitListFailed(output.status, stderr));
+    }
+
+    output
+      .stdout
+      .split(|b| *b == 0)
+      .filter(|p| !p.is_empty())
+      .map(|p| {
+        cfg_if! {
+          if #[cfg(unix)] {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            Ok(PathBuf::from(OsStr::from_bytes(p)))
+          } else {
+            std::str::from_utf8(p)
+              .map(PathBuf::from)
+              .map_err(|_| MedusaCrawlError::GitPathEncoding(p.to_vec()))
+          }
+        }
+      })
+      .collect()
+  }
+
+  /// Crawl a single file listed by git, unless it or any directory containing
+  /// it is ignored. `root` identifies the working directory, which is treated
+  /// as the directory the crawl started from.
+  async fn crawl_git_file(
+    relative_path: PathBuf,
+    cwd: &Path,
+    root: &DirectoryId,
+    settings: &CrawlSettings,
+  ) -> Result<CrawlResult, MedusaCrawlError> {
+    let ignored_dir = relative_path
+      .ancestors()
+      .skip(1)
+      .filter(|dir| !dir.as_os_str().is_empty())
+      .any(|dir| settings.ignores.should_ignore(dir, true));
+    if ignored_dir {
+      return Ok(CrawlResult::default());
+    }
+    let input = ResolvedPath::from_path_and_root(relative_path, cwd);
+    let metadata = {
+      let _permit = settings.open_file_permit().await;
+      match fs::symlink_metadata(&input.resolved_path).await {
+        Ok(metadata) => metadata,
+        /* Not checked out, e.g. due to a sparse checkout. */
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(CrawlResult::default()),
+        Err(e) => return Err(e.into()),
+      }
+    };
+    Input::Stat(input, metadata)
+      .crawl_single(settings, std::slice::from_ref(root), None)
+      .await
+  }
+
+  /// Crawl every path, sending each entry to `sink` instead of returning it if
+  /// provided. Every filesystem operation is counted against `open_files`.
+  async fn crawl_into(
+    self,
+    cwd: PathBuf,
+    sink: Option<mpsc::UnboundedSender<CrawledEntry>>,
+    open_files: OpenFileLimit,
+  ) -> Result<CrawlResult, MedusaCrawlError> {
     let Self {
       paths_to_crawl,
       ignores,
-      cwd,
+      filters,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      ignore_file_names,
+      source,
+      ..
     } = self;
-    let cwd: PathBuf = cwd.map(Ok).unwrap_or_else(env::current_dir)?;
 
     /* Validate all paths in a rayon blast before touching the filesystem. */
     paths_to_crawl
@@ -329,14 +1537,696 @@ impl MedusaCrawl {
       })
       .collect::<Result<(), MedusaCrawlFormatError>>()?;
 
-    let results: Vec<CrawlResult> = try_join_all(paths_to_crawl.into_iter().map(|relative_path| {
-      Input::Path(ResolvedPath::from_path_and_root(relative_path, &cwd)).crawl_single(&ignores)
-    }))
-    .await?;
-    let mut result = CrawlResult::merge(results);
+    let settings = CrawlSettings {
+      ignores,
+      filters,
+      ignore_file_names,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      open_files,
+      sink: sink.map(|entries| CrawlSink {
+        cwd: cwd.clone(),
+        entries,
+      }),
+    };
+    let results: Vec<CrawlResult> = match source {
+      CrawlSource::Filesystem => {
+        try_join_all(paths_to_crawl.into_iter().map(|relative_path| {
+          Input::Path(ResolvedPath::from_path_and_root(relative_path, &cwd)).crawl_single(
+            &settings,
+            &[],
+            None,
+          )
+        }))
+        .await?
+      },
+      CrawlSource::GitIndex => {
+        if !settings.ignore_file_names.is_empty() {
+          return Err(MedusaCrawlError::UnsupportedWithGitIndex(
+            "per-directory ignore files",
+          ));
+        }
+        let root = DirectoryId::for_dir(&cwd).await?;
+        let tracked_files = Self::list_git_files(cwd.clone(), paths_to_crawl).await?;
+        try_join_all(
+          tracked_files
+            .into_iter()
+            .map(|relative_path| Self::crawl_git_file(relative_path, &cwd, &root, &settings)),
+        )
+        .await?
+      },
+    };
+    Ok(CrawlResult::merge(results))
+  }
+
+  pub async fn crawl_paths(self) -> Result<CrawlResult, MedusaCrawlError> {
+    let cwd: PathBuf = self.cwd.clone().map(Ok).unwrap_or_else(env::current_dir)?;
+    let digest = self.digest;
+
+    let open_files = OpenFileLimit::new(self.max_open_files);
+    let mut result = self.crawl_into(cwd.clone(), None, open_files).await?;
 
     result.clean_up_for_export(&cwd);
 
+    if let Some(algorithm) = digest {
+      result = task::spawn_blocking(move || {
+        result.compute_digests(algorithm)?;
+        Ok::<_, MedusaCrawlError>(result)
+      })
+      .await
+      .expect("joining should not fail")?;
+    }
+
     Ok(result)
   }
+
+  /// Like [`Self::crawl_paths()`], but send each file and symlink to `entries`
+  /// as soon as it has been found (and its digest computed, if requested)
+  /// instead of collecting them. Entries are sent in no particular order.
+  ///
+  /// Returns the files left out with [`SpecialFilePolicy::Skip`].
+  pub async fn crawl_streaming(
+    self,
+    entries: mpsc::UnboundedSender<CrawledEntry>,
+  ) -> Result<Vec<SkippedFile>, MedusaCrawlError> {
+    let cwd: PathBuf = self.cwd.clone().map(Ok).unwrap_or_else(env::current_dir)?;
+    let max_open_files = self.max_open_files;
+    let open_files = OpenFileLimit::new(max_open_files);
+    let Some(algorithm) = self.digest else {
+      let CrawlResult { skipped_files, .. } =
+        self.crawl_into(cwd, Some(entries), open_files).await?;
+      return Ok(skipped_files);
+    };
+
+    let (crawled_tx, crawled_rx) = mpsc::unbounded_channel::<CrawledEntry>();
+    let crawl = self.crawl_into(cwd, Some(crawled_tx), open_files.clone());
+    let digests = UnboundedReceiverStream::new(crawled_rx)
+      .map(|entry| {
+        let open_files = open_files.clone();
+        async move {
+          match entry {
+            CrawledEntry::File(mut resolved_path) => {
+              let permit = open_files.acquire(1).await;
+              task::spawn_blocking(move || {
+                resolved_path.compute_digest(algorithm)?;
+                /* The file has been closed. */
+                drop(permit);
+                Ok(CrawledEntry::File(resolved_path))
+              })
+              .await
+              .expect("joining should not fail")
+            },
+            symlink => Ok(symlink),
+          }
+        }
+      })
+      .buffer_unordered(max_open_files.get())
+      .try_for_each(|entry| {
+        /* The receiver is only dropped if its consumer has failed, and that
+         * error is reported instead. */
+        let _ = entries.send(entry);
+        future::ready(Ok(()))
+      });
+    let (CrawlResult { skipped_files, .. }, ()) = try_join(crawl, digests).await?;
+    Ok(skipped_files)
+  }
+
+  /// Crawl the filesystem and write every entry found into `output_zip`,
+  /// compressing each file as soon as it is found instead of waiting for the
+  /// crawl to complete.
+  ///
+  /// The output is identical to that of [`Self::crawl_paths()`], then
+  /// [`CrawlResult::medusa_zip()`], then [`MedusaZip::zip()`]. The files left
+  /// out with [`SpecialFilePolicy::Skip`] are returned alongside the zip.
+  pub async fn crawl_zip<Output>(
+    self,
+    zip_options: ZipOutputOptions,
+    modifications: EntryModifications,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    tuning: PipelineTuning,
+  ) -> Result<(OutputWrapper<ZipWriter<Output>>, Vec<SkippedFile>), MedusaCrawlZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    let cwd: PathBuf = self
+      .cwd
+      .clone()
+      .map(Ok)
+      .unwrap_or_else(env::current_dir)
+      .map_err(MedusaCrawlError::from)?;
+
+    let (crawled_tx, mut crawled_rx) = mpsc::unbounded_channel::<CrawledEntry>();
+    let (entries_tx, entries_rx) = mpsc::unbounded_channel::<StreamedEntry>();
+    /* Each sender is dropped, closing its channel, once the crawl completes. */
+    /* The crawl and the zip share a single limit on open files. */
+    let open_files = OpenFileLimit::new(tuning.max_open_files);
+    let crawl = self
+      .crawl_into(cwd, Some(crawled_tx), open_files.clone())
+      .map_err(MedusaCrawlZipError::from);
+    let convert = async move {
+      while let Some(entry) = crawled_rx.recv().await {
+        let _ = entries_tx.send(entry.into_streamed_entry()?);
+      }
+      Ok::<_, MedusaCrawlError>(())
+    }
+    .map_err(MedusaCrawlZipError::from);
+    let zip = MedusaZip::zip_streaming_within(
+      entries_rx,
+      zip_options,
+      modifications,
+      output_zip,
+      tuning,
+      open_files,
+    )
+    .map_err(MedusaCrawlZipError::from);
+    let (CrawlResult { skipped_files, .. }, (), output_zip) =
+      try_join3(crawl, convert, zip).await?;
+
+    Ok((output_zip, skipped_files))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+      .current_dir(dir)
+      .args([
+        "-c",
+        "user.name=medusa",
+        "-c",
+        "user.email=medusa@example.com",
+        "-c",
+        "commit.gpgsign=false",
+        "-c",
+        "protocol.file.allow=always",
+      ])
+      .args(args)
+      .output()
+      .unwrap()
+      .status;
+    assert!(status.success(), "git {:?} failed in {:?}", args, dir);
+  }
+
+  /// Create a repo at `dir` which tracks each of `files`.
+  fn git_repo(dir: &Path, files: &[&str]) {
+    std::fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "-q"]);
+    for name in files.iter() {
+      let path = dir.join(name);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(&path, format!("contents of {}\n", name)).unwrap();
+    }
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "initial"]);
+  }
+
+  /// Run `crawl` from `dir`, returning the sorted names of the files found.
+  async fn crawl_file_names(
+    dir: &Path,
+    crawl: MedusaCrawl,
+  ) -> Result<Vec<String>, MedusaCrawlError> {
+    let crawl = MedusaCrawl {
+      cwd: Some(dir.to_path_buf()),
+      ..crawl
+    };
+    let CrawlResult {
+      real_file_paths, ..
+    } = crawl.crawl_paths().await?;
+    let mut names: Vec<String> = real_file_paths
+      .into_iter()
+      .map(|path| path.unresolved_path.into_os_string().into_string().unwrap())
+      .collect();
+    names.sort();
+    Ok(names)
+  }
+
+  async fn crawl_git_index(
+    repo: &Path,
+    ignore_file_names: Vec<String>,
+  ) -> Result<Vec<String>, MedusaCrawlError> {
+    crawl_file_names(repo, MedusaCrawl {
+      source: CrawlSource::GitIndex,
+      ignore_file_names,
+      ..Default::default()
+    })
+    .await
+  }
+
+  /// Write an empty file at each of `files` under `dir`.
+  fn write_files(dir: &Path, files: &[&str]) {
+    for name in files.iter() {
+      let path = dir.join(name);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(&path, "").unwrap();
+    }
+  }
+
+  fn glob_rules(patterns: &[&str]) -> GlobRules {
+    GlobRules::new(
+      patterns
+        .iter()
+        .map(|pattern| GlobRule::parse(pattern).unwrap())
+        .collect(),
+    )
+  }
+
+  #[test]
+  fn glob_rule_syntax() {
+    /* A pattern without '/' matches the final component at any depth. */
+    let pyc = GlobRule::parse("*.pyc").unwrap();
+    assert!(pyc.is_match(Path::new("a.pyc"), false));
+    assert!(pyc.is_match(Path::new("a/b/c.pyc"), false));
+    assert!(!pyc.is_match(Path::new("a.pyc/b.py"), false));
+
+    let pycache = GlobRule::parse("**/__pycache__/**").unwrap();
+    assert!(pycache.is_match(Path::new("__pycache__/a.pyc"), false));
+    assert!(pycache.is_match(Path::new("a/b/__pycache__/c/d.pyc"), false));
+    assert!(!pycache.is_match(Path::new("a/__pycache__.py"), false));
+
+    /* A leading '/' anchors the pattern to the top-level directory. */
+    let anchored = GlobRule::parse("/anchored").unwrap();
+    assert!(anchored.is_match(Path::new("anchored"), false));
+    assert!(!anchored.is_match(Path::new("a/anchored"), false));
+
+    /* '*' does not match across '/'. */
+    let nested = GlobRule::parse("a/*.txt").unwrap();
+    assert!(nested.is_match(Path::new("a/b.txt"), false));
+    assert!(!nested.is_match(Path::new("a/b/c.txt"), false));
+    assert!(!nested.is_match(Path::new("x/a/b.txt"), false));
+
+    /* A trailing '/' only matches directories. */
+    let dir = GlobRule::parse("dir/").unwrap();
+    assert!(dir.is_match(Path::new("dir"), true));
+    assert!(dir.is_match(Path::new("a/dir"), true));
+    assert!(!dir.is_match(Path::new("dir"), false));
+
+    let negated = GlobRule::parse("!keep.pyc").unwrap();
+    assert!(negated.is_negated());
+    assert!(negated.is_match(Path::new("a/keep.pyc"), false));
+
+    for empty in ["/", "!", "!/"].into_iter() {
+      assert!(matches!(
+        GlobRule::parse(empty),
+        Err(GlobRuleParseError::Empty(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn glob_rules_last_match() {
+    let rules = glob_rules(&["*.pyc", "!keep.pyc", "/build/"]);
+    assert_eq!(rules.last_match(Path::new("a/b.pyc"), false), Some(true));
+    assert_eq!(
+      rules.last_match(Path::new("a/keep.pyc"), false),
+      Some(false)
+    );
+    assert_eq!(rules.last_match(Path::new("a/b.py"), false), None);
+    assert_eq!(rules.last_match(Path::new("build"), true), Some(true));
+    assert_eq!(rules.last_match(Path::new("build"), false), None);
+    assert_eq!(rules.last_match(Path::new("a/build"), true), None);
+
+    /* A later rule takes precedence over an earlier one. */
+    let rules = glob_rules(&["!keep.pyc", "*.pyc"]);
+    assert_eq!(rules.last_match(Path::new("a/keep.pyc"), false), Some(true));
+
+    let rules = GlobRules::parse_ignore_file("# comment\n\n*.pyc\n!keep.pyc\n").unwrap();
+    assert_eq!(rules.rules.len(), 2);
+    assert!(matches!(
+      GlobRules::parse_ignore_file("*.pyc\n/\n"),
+      Err(IgnoreFileError { line: 2, .. })
+    ));
+  }
+
+  #[test]
+  fn ignores_and_includes() {
+    let ignores = Ignores::new(
+      RegexSet::empty(),
+      glob_rules(&["**/__pycache__/**", "test_*.py", "!test_keep.py"]),
+      glob_rules(&["*.py"]),
+    );
+    assert!(ignores.should_ignore(Path::new("./a/__pycache__/b.py"), false));
+    assert!(ignores.should_ignore(Path::new("./a/test_b.py"), false));
+    assert!(!ignores.should_ignore(Path::new("./a/test_keep.py"), false));
+    assert!(!ignores.should_ignore(Path::new("./a/b.txt"), false));
+    assert!(ignores.is_included(Path::new("./a/b.py")));
+    assert!(!ignores.is_included(Path::new("./a/b.txt")));
+    /* With no include globs, everything is included. */
+    assert!(Ignores::default().is_included(Path::new("./a/b.txt")));
+  }
+
+  #[tokio::test]
+  async fn include_and_ignore_globs() {
+    let root = tempfile::tempdir().unwrap();
+    write_files(root.path(), &[
+      "a.py",
+      "a.txt",
+      "pkg/b.py",
+      "pkg/test_b.py",
+      "pkg/__pycache__/b.py",
+      "pkg/data/c.py",
+    ]);
+    let crawl = MedusaCrawl {
+      ignores: Ignores::new(
+        RegexSet::empty(),
+        glob_rules(&["**/__pycache__/**", "test_*.py"]),
+        glob_rules(&["*.py"]),
+      ),
+      ..Default::default()
+    };
+    assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+      "a.py",
+      "pkg/b.py",
+      "pkg/data/c.py"
+    ]);
+  }
+
+  #[tokio::test]
+  async fn git_index_plain() {
+    let root = tempfile::tempdir().unwrap();
+    let repo = root.path().join("repo");
+    git_repo(&repo, &[".gitignore", "a.txt", "dir/b.txt"]);
+    std::fs::write(repo.join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(repo.join("untracked.txt"), "").unwrap();
+    std::fs::write(repo.join("ignored.log"), "").unwrap();
+
+    assert_eq!(crawl_git_index(&repo, Vec::new()).await.unwrap(), vec![
+      ".gitignore",
+      "a.txt",
+      "dir/b.txt"
+    ]);
+    assert!(matches!(
+      crawl_git_index(&repo, vec![".gitignore".to_string()]).await,
+      Err(MedusaCrawlError::UnsupportedWithGitIndex(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn git_index_submodule() {
+    let root = tempfile::tempdir().unwrap();
+    let sub = root.path().join("sub");
+    git_repo(&sub, &["s.txt", "nested/t.txt"]);
+    let repo = root.path().join("repo");
+    git_repo(&repo, &["a.txt"]);
+    git(&repo, &[
+      "submodule",
+      "add",
+      "-q",
+      sub.to_str().unwrap(),
+      "sub",
+    ]);
+    git(&repo, &["commit", "-q", "-m", "add submodule"]);
+
+    assert_eq!(crawl_git_index(&repo, Vec::new()).await.unwrap(), vec![
+      ".gitmodules",
+      "a.txt",
+      "sub/nested/t.txt",
+      "sub/s.txt"
+    ]);
+  }
+
+  #[tokio::test]
+  async fn git_index_sparse_checkout() {
+    let root = tempfile::tempdir().unwrap();
+    let repo = root.path().join("repo");
+    git_repo(&repo, &["top.txt", "kept/x.txt", "skipped/y.txt"]);
+    git(&repo, &["sparse-checkout", "set", "kept"]);
+    assert!(!repo.join("skipped").exists());
+
+    assert_eq!(crawl_git_index(&repo, Vec::new()).await.unwrap(), vec![
+      "kept/x.txt",
+      "top.txt"
+    ]);
+  }
+
+  #[tokio::test]
+  async fn nested_ignore_files() {
+    let root = tempfile::tempdir().unwrap();
+    write_files(root.path(), &[
+      "a.txt",
+      "top.log",
+      "x.log",
+      "build/out.txt",
+      "sub/b.txt",
+      "sub/keep.log",
+      "sub/other.log",
+      "sub/forced.log",
+      "sub/deeper/c.txt",
+      "sub/deeper/keep.log",
+    ]);
+    std::fs::write(root.path().join(".gitignore"), "*.log\nbuild/\n").unwrap();
+    /* Later ignore file names take precedence within the same directory. */
+    std::fs::write(root.path().join(".medusaignore"), "!top.log\n").unwrap();
+    /* Rules are relative to the directory of the ignore file. */
+    std::fs::write(root.path().join("sub/.gitignore"), "!keep.log\n/*.txt\n").unwrap();
+    std::fs::write(root.path().join("sub/deeper/.medusaignore"), "keep.log\n").unwrap();
+    let crawl = MedusaCrawl {
+      ignore_file_names: vec![".gitignore".to_string(), ".medusaignore".to_string()],
+      ..Default::default()
+    };
+
+    /* The deepest ignore file with a matching rule wins. */
+    assert_eq!(
+      crawl_file_names(root.path(), crawl.clone()).await.unwrap(),
+      vec![
+        ".gitignore",
+        ".medusaignore",
+        "a.txt",
+        "sub/.gitignore",
+        "sub/deeper/.medusaignore",
+        "sub/deeper/c.txt",
+        "sub/keep.log",
+        "top.log",
+      ]
+    );
+
+    /* Globs provided directly win over every ignore file. */
+    let crawl = MedusaCrawl {
+      ignores: Ignores::new(
+        RegexSet::empty(),
+        glob_rules(&["keep.log", "!forced.log", "!sub/b.txt"]),
+        GlobRules::default(),
+      ),
+      ..crawl
+    };
+    assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+      ".gitignore",
+      ".medusaignore",
+      "a.txt",
+      "sub/.gitignore",
+      "sub/b.txt",
+      "sub/deeper/.medusaignore",
+      "sub/deeper/c.txt",
+      "sub/forced.log",
+      "top.log",
+    ]);
+  }
+
+  #[tokio::test]
+  async fn file_filter_boundaries() {
+    use std::time::Duration;
+
+    let root = tempfile::tempdir().unwrap();
+    let cutoff = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let files: [(&str, SystemTime, usize); 4] = [
+      ("old.txt", cutoff - Duration::from_secs(1), 1),
+      ("cutoff.txt", cutoff, 1),
+      ("exact.txt", cutoff + Duration::from_secs(1), 10),
+      ("big.txt", cutoff + Duration::from_secs(1), 11),
+    ];
+    for (name, mtime, size) in files.into_iter() {
+      let file = std::fs::File::create(root.path().join(name)).unwrap();
+      file.set_len(size as u64).unwrap();
+      file.set_modified(mtime).unwrap();
+    }
+
+    /* The upper bound on modification time is exclusive. */
+    let crawl = MedusaCrawl {
+      filters: FileFilters {
+        modified_before: Some(cutoff),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+      "old.txt"
+    ]);
+
+    /* The lower bound is inclusive, as is the size limit. */
+    let crawl = MedusaCrawl {
+      filters: FileFilters {
+        modified_after: Some(cutoff),
+        max_size: Some(10),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+      "cutoff.txt",
+      "exact.txt"
+    ]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn crosses_file_system() {
+    let ancestors = [DirectoryId { dev: 1, ino: 2 }, DirectoryId {
+      dev: 3,
+      ino: 4,
+    }];
+    let filters = FileFilters {
+      same_file_system: true,
+      ..Default::default()
+    };
+    /* Only the directory the crawl started from is compared against. */
+    assert!(!filters.crosses_file_system(&ancestors, Some(1)));
+    assert!(filters.crosses_file_system(&ancestors, Some(3)));
+    assert!(!filters.crosses_file_system(&[], Some(3)));
+    assert!(!FileFilters::default().crosses_file_system(&ancestors, Some(3)));
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn symlink_to_ancestor_directory() {
+    let root = tempfile::tempdir().unwrap();
+    write_files(root.path(), &["a/f.txt"]);
+    std::os::unix::fs::symlink("..", root.path().join("a/up")).unwrap();
+
+    assert!(matches!(
+      crawl_file_names(root.path(), MedusaCrawl::default()).await,
+      Err(MedusaCrawlError::SymlinkCycle(path, _)) if path == Path::new("./a/up")
+    ));
+    let crawl = MedusaCrawl {
+      symlink_cycle_policy: SymlinkCyclePolicy::Skip,
+      ..Default::default()
+    };
+    assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+      "a/f.txt"
+    ]);
+    let crawl = MedusaCrawl {
+      symlink_cycle_policy: SymlinkCyclePolicy::FollowOnce,
+      ..Default::default()
+    };
+    assert_eq!(
+      crawl_file_names(root.path(), crawl.clone()).await.unwrap(),
+      vec!["a/f.txt", "a/up/a/f.txt"]
+    );
+
+    /* The copy crawled through the symlink counts towards the depth. */
+    assert!(matches!(
+      crawl_file_names(root.path(), MedusaCrawl {
+        max_depth: Some(2),
+        ..crawl.clone()
+      })
+      .await,
+      Err(MedusaCrawlError::MaxDepthExceeded(path, 2)) if path == Path::new("./a/up/a")
+    ));
+    assert_eq!(
+      crawl_file_names(root.path(), MedusaCrawl {
+        max_depth: Some(3),
+        ..crawl
+      })
+      .await
+      .unwrap(),
+      vec!["a/f.txt", "a/up/a/f.txt"]
+    );
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn symlink_chain_cycle() {
+    let root = tempfile::tempdir().unwrap();
+    write_files(root.path(), &["f.txt"]);
+    std::os::unix::fs::symlink("b", root.path().join("a")).unwrap();
+    std::os::unix::fs::symlink("a", root.path().join("b")).unwrap();
+    std::os::unix::fs::symlink("self", root.path().join("self")).unwrap();
+
+    assert!(matches!(
+      crawl_file_names(root.path(), MedusaCrawl::default()).await,
+      Err(MedusaCrawlError::SymlinkCycle(_, _))
+    ));
+    for symlink_cycle_policy in [SymlinkCyclePolicy::Skip, SymlinkCyclePolicy::FollowOnce] {
+      let crawl = MedusaCrawl {
+        symlink_cycle_policy,
+        ..Default::default()
+      };
+      assert_eq!(crawl_file_names(root.path(), crawl).await.unwrap(), vec![
+        "f.txt"
+      ]);
+    }
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn skip_special_files() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("a.txt"), "a\n").unwrap();
+    let _listener = std::os::unix::net::UnixListener::bind(root.path().join("sock")).unwrap();
+    let crawl = MedusaCrawl {
+      paths_to_crawl: vec![PathBuf::from(".")],
+      cwd: Some(root.path().to_path_buf()),
+      ..Default::default()
+    };
+
+    assert!(matches!(
+      crawl.clone().crawl_paths().await,
+      Err(MedusaCrawlError::SpecialFile(_, SpecialFileKind::Socket))
+    ));
+
+    let crawl = MedusaCrawl {
+      special_file_policy: SpecialFilePolicy::Skip,
+      ..crawl
+    };
+    let CrawlResult {
+      real_file_paths,
+      skipped_files,
+      ..
+    } = crawl.clone().crawl_paths().await.unwrap();
+    assert_eq!(real_file_paths.len(), 1);
+    assert_eq!(skipped_files.len(), 1);
+    assert!(skipped_files[0].path.ends_with("sock"));
+    assert_eq!(skipped_files[0].kind, SpecialFileKind::Socket);
+
+    let (entries_tx, mut entries_rx) = mpsc::unbounded_channel::<CrawledEntry>();
+    let streamed_skipped_files = crawl.crawl_streaming(entries_tx).await.unwrap();
+    assert_eq!(streamed_skipped_files, skipped_files);
+    assert!(matches!(
+      entries_rx.recv().await,
+      Some(CrawledEntry::File(_))
+    ));
+    assert!(entries_rx.recv().await.is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_unicode_paths() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let non_unicode = PathBuf::from(OsStr::from_bytes(b"a\xff.txt"));
+    let file = CrawledEntry::File(ResolvedPath {
+      unresolved_path: non_unicode.clone(),
+      resolved_path: non_unicode.clone(),
+      metadata: None,
+      digest: None,
+    });
+    assert!(matches!(
+      file.into_streamed_entry(),
+      Err(MedusaCrawlError::PathEncoding(path)) if path == non_unicode
+    ));
+
+    let symlink = CrawledEntry::Symlink(ResolvedSymlink {
+      unresolved_path: PathBuf::from("link"),
+      target: non_unicode.clone(),
+      metadata: None,
+    });
+    assert!(matches!(
+      symlink.into_streamed_entry(),
+      Err(MedusaCrawlError::SymlinkTargetEncoding(path, target))
+        if path == Path::new("link") && target == non_unicode
+    ));
+  }
 }
@@ -0,0 +1,159 @@
+/*
+ * Description: ???
+ *
+ * Copyright (C) 2023 Danny McClanahan <dmcC2@hypnicjerk.ai>
+ * SPDX-License-Identifier: Apache-2.0
+ *
+ * Licensed under the Apache License, Version 2.0 (see LICENSE).
+ */
+
+//! Content digests of crawled files.
+
+use displaydoc::Display;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use std::{
+  fmt::{self, Write as _},
+  fs,
+  io::{self, Write},
+  path::Path,
+  str::FromStr,
+};
+
+/// Which hash function to compute [`ContentDigest`]s with.
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+  /// sha256
+  Sha256,
+  /// blake3
+  Blake3,
+}
+
+impl DigestAlgorithm {
+  pub(crate) fn hasher(self) -> DigestHasher {
+    match self {
+      Self::Sha256 => DigestHasher::Sha256(Sha256::new()),
+      Self::Blake3 => DigestHasher::Blake3(Box::new(blake3::Hasher::new())),
+    }
+  }
+
+  /// Read the entire contents of the file at `path` into a digest.
+  pub fn digest_file(self, path: &Path) -> io::Result<ContentDigest> {
+    let mut handle = fs::File::open(path)?;
+    let mut hasher = self.hasher();
+    io::copy(&mut handle, &mut hasher)?;
+    Ok(hasher.finalize())
+  }
+
+  pub fn digest_bytes(self, bytes: &[u8]) -> ContentDigest {
+    let mut hasher = self.hasher();
+    hasher.update(bytes);
+    hasher.finalize()
+  }
+}
+
+impl FromStr for DigestAlgorithm {
+  type Err = ContentDigestParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "sha256" => Ok(Self::Sha256),
+      "blake3" => Ok(Self::Blake3),
+      _ => Err(ContentDigestParseError::UnknownAlgorithm(s.to_string())),
+    }
+  }
+}
+
+/// The digest of some content, written as `<algorithm>:<hex digest>`, e.g.
+/// `sha256:e3b0c442...`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ContentDigest {
+  pub algorithm: DigestAlgorithm,
+  pub bytes: Vec<u8>,
+}
+
+impl ContentDigest {
+  pub fn hex(&self) -> String {
+    let mut hex = String::with_capacity(self.bytes.len() * 2);
+    for byte in self.bytes.iter() {
+      write!(&mut hex, "{:02x}", byte).expect("writing to a string should not fail");
+    }
+    hex
+  }
+}
+
+impl fmt::Display for ContentDigest {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.algorithm, self.hex())
+  }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ContentDigestParseError {
+  /// digest {0:?} was not of the form '<algorithm>:<hex digest>'
+  MissingAlgorithm(String),
+  /// unknown digest algorithm {0:?}
+  UnknownAlgorithm(String),
+  /// digest {0:?} was not valid hex
+  InvalidHex(String),
+}
+
+impl FromStr for ContentDigest {
+  type Err = ContentDigestParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (algorithm, hex) = s
+      .split_once(':')
+      .ok_or_else(|| ContentDigestParseError::MissingAlgorithm(s.to_string()))?;
+    let algorithm: DigestAlgorithm = algorithm.parse()?;
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+      return Err(ContentDigestParseError::InvalidHex(hex.to_string()));
+    }
+    let bytes: Vec<u8> = (0..hex.len())
+      .step_by(2)
+      .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+      .collect::<Result<_, _>>()
+      .map_err(|_| ContentDigestParseError::InvalidHex(hex.to_string()))?;
+    Ok(Self { algorithm, bytes })
+  }
+}
+
+/// Incrementally computes a [`ContentDigest`].
+pub(crate) enum DigestHasher {
+  Sha256(Sha256),
+  Blake3(Box<blake3::Hasher>),
+}
+
+impl DigestHasher {
+  pub fn update(&mut self, bytes: &[u8]) {
+    match self {
+      Self::Sha256(hasher) => hasher.update(bytes),
+      Self::Blake3(hasher) => {
+        hasher.update(bytes);
+      },
+    }
+  }
+
+  pub fn finalize(self) -> ContentDigest {
+    match self {
+      Self::Sha256(hasher) => ContentDigest {
+        algorithm: DigestAlgorithm::Sha256,
+        bytes: hasher.finalize().to_vec(),
+      },
+      Self::Blake3(hasher) => ContentDigest {
+        algorithm: DigestAlgorithm::Blake3,
+        bytes: hasher.finalize().as_bytes().to_vec(),
+      },
+    }
+  }
+}
+
+impl Write for DigestHasher {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.update(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
@@ -46,10 +46,13 @@
 /* Arc<Mutex> can be more clear than needing to grok Orderings. */
 #![allow(clippy::mutex_atomic)]
 
+use cfg_if::cfg_if;
 use displaydoc::Display;
 use thiserror::Error;
 
-use std::{cmp, fmt, ops::Range, path::PathBuf};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use std::{cmp, fmt, num::NonZeroUsize, ops::Range, path::PathBuf, sync::Arc};
 
 /// Allowed zip format quirks that we refuse to handle right now.
 #[derive(Debug, Display, Error)]
@@ -98,6 +101,8 @@ impl EntryName {
     }
   }
 
+  pub fn as_str(&self) -> &str { &self.name }
+
   pub fn into_string(self) -> String {
     if self.is_empty() {
       panic!("attempted to write an empty EntryName!");
@@ -166,6 +171,9 @@ impl EntryName {
 pub struct FileSource {
   pub name: EntryName,
   pub source: PathBuf,
+  /// Metadata for [`Self::source`], if it is already known (e.g. from a crawl).
+  /// Otherwise the file is examined once it is opened.
+  pub metadata: Option<crawl::EntryMetadata>,
 }
 
 /* Implement {Partial,}Ord to sort a vector of these by name without
@@ -180,12 +188,105 @@ impl cmp::Ord for FileSource {
   fn cmp(&self, other: &Self) -> cmp::Ordering { self.name.cmp(&other.name) }
 }
 
+/// A symlink to be written as its own entry, rather than as the contents of the
+/// file it points to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymlinkSource {
+  pub name: EntryName,
+  /// The contents of the symlink, which is not resolved or validated in any
+  /// way.
+  pub target: String,
+}
+
+/// The default limit on how many files may be open at once while crawling or
+/// zipping.
+///
+/// On unix this is the soft `RLIMIT_NOFILE` limit, less a few descriptors for
+/// those which are never counted against it: the standard streams, the output
+/// zip, the async runtime's own, and any held by the caller.
+pub fn default_max_open_files() -> NonZeroUsize {
+  const MIN: usize = 4;
+  const MAX: usize = 4096;
+  const RESERVED: usize = 32;
+
+  cfg_if! {
+    if #[cfg(unix)] {
+      use rustix::process::{getrlimit, Resource};
+      let soft_limit: usize = match getrlimit(Resource::Nofile).current {
+        Some(limit) => usize::try_from(limit).unwrap_or(usize::MAX),
+        /* No limit. */
+        None => usize::MAX,
+      };
+      let limit = soft_limit.saturating_sub(RESERVED);
+    } else {
+      let limit: usize = 512;
+    }
+  }
+  NonZeroUsize::new(limit.clamp(MIN, MAX)).unwrap()
+}
+
+/// A limit on how many files may be open at once, which can be shared by a
+/// crawl and a zip running together. Cloning this shares the same limit.
+#[derive(Clone, Debug)]
+pub struct OpenFileLimit {
+  max: NonZeroUsize,
+  permits: Arc<Semaphore>,
+}
+
+impl OpenFileLimit {
+  pub fn new(max: NonZeroUsize) -> Self {
+    Self {
+      max,
+      permits: Arc::new(Semaphore::new(max.get())),
+    }
+  }
+
+  pub fn max(&self) -> NonZeroUsize { self.max }
+
+  /// Wait until `count` more files may be opened, and count them against the
+  /// limit until the returned permit is dropped. No more than the whole limit
+  /// is ever waited for.
+  pub async fn acquire(&self, count: usize) -> OwnedSemaphorePermit {
+    let count = cmp::min(count, self.max.get());
+    self
+      .permits
+      .clone()
+      .acquire_many_owned(u32::try_from(count).unwrap_or(u32::MAX))
+      .await
+      .expect("semaphore is never closed")
+  }
+
+  /// Stop counting one of the files counted by `permit`, unless it is the
+  /// only one.
+  pub fn release_one(permit: &mut OwnedSemaphorePermit) {
+    if permit.num_permits() > 1 {
+      drop(permit.split(1));
+    }
+  }
+
+  /// Like [`Self::acquire()`], but for files held open for as long as a whole
+  /// crawl or zip. This always leaves at least two files which can be opened
+  /// for each entry (its source and its buffer), so that it can never prevent
+  /// the crawl or zip from making progress.
+  pub async fn reserve(&self, count: usize) -> OwnedSemaphorePermit {
+    self
+      .acquire(cmp::min(count, self.max.get().saturating_sub(2)))
+      .await
+  }
+}
+
 pub mod destination;
 
 pub mod crawl;
 
+pub mod digest;
+
 pub mod zip;
 
+pub mod precompressed;
+
+pub mod cache;
+
 pub mod merge;
 
 #[cfg(feature = "bench-utils")]
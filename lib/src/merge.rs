@@ -11,7 +11,10 @@
 
 use crate::{
   destination::OutputWrapper,
-  zip::{calculate_new_rightmost_components, DefaultInitializeZipOptions, ModifiedTimeBehavior},
+  zip::{
+    calculate_new_rightmost_components, DefaultInitializeZipOptions, ModifiedTimeBehavior,
+    PipelineTuning,
+  },
   EntryName,
 };
 
@@ -59,13 +62,12 @@ pub enum IntermediateMergeEntry {
   MergeZip(ZipArchive<std::fs::File>),
 }
 
-const PARALLEL_MERGE_ENTRIES: usize = 10;
-
 impl MedusaMerge {
   pub async fn merge<Output>(
     self,
     mtime_behavior: ModifiedTimeBehavior,
     output_zip: OutputWrapper<ZipWriter<Output>>,
+    tuning: PipelineTuning,
   ) -> Result<OutputWrapper<ZipWriter<Output>>, MedusaMergeError>
   where
     Output: Write+Seek+Send+'static,
@@ -75,7 +77,8 @@ impl MedusaMerge {
 
     /* This shouldn't really need to be bounded at all, since the task is
      * entirely synchronous. */
-    let (handle_tx, handle_rx) = mpsc::channel::<IntermediateMergeEntry>(PARALLEL_MERGE_ENTRIES);
+    let (handle_tx, handle_rx) =
+      mpsc::channel::<IntermediateMergeEntry>(tuning.parallel_merge_entries.get());
     let mut handle_jobs = ReceiverStream::new(handle_rx);
     let handle_stream_task = task::spawn(async move {
       let mut previous_directory_components: Vec<String> = Vec::new();
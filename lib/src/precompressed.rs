@@ -0,0 +1,1334 @@
+/*
+ * Description: ???
+ *
+ * Copyright (C) 2023 Danny McClanahan <dmcC2@hypnicjerk.ai>
+ * SPDX-License-Identifier: Apache-2.0
+ *
+ * Licensed under the Apache License, Version 2.0 (see LICENSE).
+ */
+
+//! ???
+
+use crate::EntryName;
+
+use bzip2::write::BzEncoder;
+use flate2::{write::DeflateEncoder, Compress, Compression, FlushCompress, Status};
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use zip::{
+  read::ZipFile,
+  result::{ZipError, ZipResult},
+  CompressionMethod as ZipCompressionMethod, DateTime as ZipDateTime, ZipArchive, ZipWriter,
+  ZIP64_BYTES_THR,
+};
+
+#[cfg(unix)]
+use std::any::Any;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+use std::{
+  fs,
+  io::{self, Read, Seek, SeekFrom, Write},
+  mem,
+  num::NonZeroUsize,
+  sync::Arc,
+};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
+
+const ZIP64_EXTRA_FIELD_TAG: u16 = 0x0001;
+const ZIP64_ENTRY_THR: u64 = u16::MAX as u64;
+
+const STORED_METHOD_ID: u16 = 0;
+const DEFLATED_METHOD_ID: u16 = 8;
+const BZIP2_METHOD_ID: u16 = 12;
+const ZSTD_METHOD_ID: u16 = 93;
+
+/* The same values the zip library writes: made by unix, spec version 4.6. */
+const VERSION_MADE_BY: u16 = (3 << 8) | 46;
+const VERSION_NEEDED_DEFAULT: u16 = 20;
+const VERSION_NEEDED_ZIP64: u16 = 45;
+const VERSION_NEEDED_BZIP2: u16 = 46;
+const VERSION_NEEDED_ZSTD: u16 = 63;
+
+/// The fields of an entry header which are determined by the zip options
+/// rather than the entry contents.
+#[derive(Copy, Clone, Debug)]
+pub struct EntryHeaderMetadata {
+  pub last_modified: ZipDateTime,
+  pub unix_mode: u32,
+}
+
+impl EntryHeaderMetadata {
+  /// The zip library only keeps the permission bits of `permissions`
+  /// (defaulting to `0o644`), and marks every file entry as a regular file.
+  pub fn for_file(last_modified: ZipDateTime, permissions: Option<u32>) -> Self {
+    Self {
+      last_modified,
+      unix_mode: (permissions.unwrap_or(0o644) & 0o777) | 0o100000,
+    }
+  }
+}
+
+/// How to split up and compress the contents of a single entry with
+/// [`deflate_blocks_parallel()`].
+#[derive(Copy, Clone, Debug)]
+pub struct DeflateBlocks {
+  pub level: Compression,
+  pub block_size: NonZeroUsize,
+}
+
+/// Sizes and checksum of a compressed stream.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CompressedStreamInfo {
+  pub crc32: u32,
+  pub uncompressed_size: u64,
+  pub compressed_size: u64,
+}
+
+fn deflate_block(block: &[u8], level: Compression, is_final: bool) -> io::Result<Vec<u8>> {
+  /* Each block is compressed independently as a raw deflate stream. All but
+   * the last end with a sync flush, which pads the output to a byte boundary
+   * without marking the end of the stream, so the blocks can simply be
+   * concatenated. */
+  let mut compress = Compress::new(level, false);
+  let flush = if is_final {
+    FlushCompress::Finish
+  } else {
+    FlushCompress::Sync
+  };
+  let mut out: Vec<u8> = Vec::with_capacity(block.len() / 2 + 128);
+  loop {
+    if out.len() == out.capacity() {
+      out.reserve(out.capacity());
+    }
+    let consumed = compress.total_in() as usize;
+    let status = compress
+      .compress_vec(&block[consumed..], &mut out, flush)
+      .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let done = match status {
+      Status::StreamEnd => true,
+      _ => !is_final && compress.total_in() as usize == block.len() && out.len() < out.capacity(),
+    };
+    if done {
+      return Ok(out);
+    }
+  }
+}
+
+fn read_block<R: Read>(input: &mut R, block_size: usize) -> io::Result<Vec<u8>> {
+  let mut block: Vec<u8> = Vec::with_capacity(block_size);
+  input
+    .by_ref()
+    .take(block_size as u64)
+    .read_to_end(&mut block)?;
+  Ok(block)
+}
+
+/// Compress `input` into a single raw deflate stream in `output`, splitting it
+/// into blocks which are compressed in parallel on the rayon thread pool.
+///
+/// The output only depends upon the input and `params`, so it is
+/// deterministic regardless of the number of threads.
+pub fn deflate_blocks_parallel<R: Read, W: Write>(
+  input: &mut R,
+  output: &mut W,
+  params: DeflateBlocks,
+) -> io::Result<CompressedStreamInfo> {
+  let DeflateBlocks { level, block_size } = params;
+  let block_size = block_size.get();
+
+  /* Only read enough blocks at once to keep every thread busy. */
+  let batch_len = rayon::current_num_threads() * 2;
+
+  let mut crc = crc32fast::Hasher::new();
+  let mut info = CompressedStreamInfo::default();
+
+  let mut next_block = read_block(input, block_size)?;
+  loop {
+    let mut batch: Vec<Vec<u8>> = Vec::with_capacity(batch_len);
+    while batch.len() < batch_len && !next_block.is_empty() {
+      let cur_block = mem::replace(&mut next_block, read_block(input, block_size)?);
+      batch.push(cur_block);
+    }
+    let is_final = next_block.is_empty();
+    /* An empty input still needs a final (empty) deflate block. */
+    if batch.is_empty() {
+      batch.push(Vec::new());
+    }
+
+    let last_index = batch.len() - 1;
+    let compressed: Vec<(Vec<u8>, crc32fast::Hasher, usize)> = batch
+      .into_par_iter()
+      .enumerate()
+      .map(|(i, block)| {
+        let mut block_crc = crc32fast::Hasher::new();
+        block_crc.update(&block);
+        let out = deflate_block(&block, level, is_final && i == last_index)?;
+        Ok((out, block_crc, block.len()))
+      })
+      .collect::<io::Result<_>>()?;
+
+    for (out, block_crc, block_len) in compressed.into_iter() {
+      output.write_all(&out)?;
+      crc.combine(&block_crc);
+      info.uncompressed_size += block_len as u64;
+      info.compressed_size += out.len() as u64;
+    }
+
+    if is_final {
+      break;
+    }
+  }
+
+  info.crc32 = crc.finalize();
+  Ok(info)
+}
+
+/// How to compress the contents of a single entry without going through the
+/// zip library.
+#[derive(Copy, Clone, Debug)]
+pub enum EntryEncoding {
+  Stored,
+  Deflated(Compression),
+  DeflatedBlocks(DeflateBlocks),
+  Bzip2(bzip2::Compression),
+  Zstd(i32),
+}
+
+struct Crc32Reader<R> {
+  inner: R,
+  crc: crc32fast::Hasher,
+  len: u64,
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.crc.update(&buf[..n]);
+    self.len += n as u64;
+    Ok(n)
+  }
+}
+
+struct CountingWriter<W> {
+  inner: W,
+  len: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let n = self.inner.write(buf)?;
+    self.len += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+impl EntryEncoding {
+  pub fn method(&self) -> ZipCompressionMethod {
+    match self {
+      Self::Stored => ZipCompressionMethod::Stored,
+      Self::Deflated(_) | Self::DeflatedBlocks(_) => ZipCompressionMethod::Deflated,
+      Self::Bzip2(_) => ZipCompressionMethod::Bzip2,
+      Self::Zstd(_) => ZipCompressionMethod::Zstd,
+    }
+  }
+
+  fn method_id(&self) -> u16 {
+    match self {
+      Self::Stored => STORED_METHOD_ID,
+      Self::Deflated(_) | Self::DeflatedBlocks(_) => DEFLATED_METHOD_ID,
+      Self::Bzip2(_) => BZIP2_METHOD_ID,
+      Self::Zstd(_) => ZSTD_METHOD_ID,
+    }
+  }
+
+  /// Compress all of `input` into `output` as a raw stream (without any zip
+  /// headers).
+  pub fn encode<R: Read, W: Write>(
+    &self,
+    input: &mut R,
+    output: &mut W,
+  ) -> io::Result<CompressedStreamInfo> {
+    if let Self::DeflatedBlocks(params) = self {
+      return deflate_blocks_parallel(input, output, *params);
+    }
+
+    let mut input = Crc32Reader {
+      inner: input,
+      crc: crc32fast::Hasher::new(),
+      len: 0,
+    };
+    let mut output = CountingWriter {
+      inner: output,
+      len: 0,
+    };
+    match self {
+      Self::Stored => {
+        io::copy(&mut input, &mut output)?;
+      },
+      Self::Deflated(level) => {
+        let mut encoder = DeflateEncoder::new(&mut output, *level);
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+      },
+      Self::Bzip2(level) => {
+        let mut encoder = BzEncoder::new(&mut output, *level);
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+      },
+      Self::Zstd(level) => {
+        let mut encoder = zstd::stream::write::Encoder::new(&mut output, *level)?;
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+      },
+      Self::DeflatedBlocks(_) => unreachable!("handled above"),
+    }
+    Ok(CompressedStreamInfo {
+      crc32: input.crc.finalize(),
+      uncompressed_size: input.len,
+      compressed_size: output.len,
+    })
+  }
+}
+
+/// Temporary storage for the compressed contents of a single entry.
+#[derive(Debug)]
+pub enum EntryBuffer {
+  /// Kept in memory until it grows too large, then written to disk.
+  Spooled(tempfile::SpooledTempFile),
+  /// Written directly to an unnamed file on disk, so that data can be copied
+  /// into it by the kernel.
+  File(fs::File),
+}
+
+impl Read for EntryBuffer {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Self::Spooled(f) => f.read(buf),
+      Self::File(f) => f.read(buf),
+    }
+  }
+}
+
+impl Write for EntryBuffer {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Self::Spooled(f) => f.write(buf),
+      Self::File(f) => f.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Self::Spooled(f) => f.flush(),
+      Self::File(f) => f.flush(),
+    }
+  }
+}
+
+impl Seek for EntryBuffer {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    match self {
+      Self::Spooled(f) => f.seek(pos),
+      Self::File(f) => f.seek(pos),
+    }
+  }
+}
+
+/// A temporary file which the compressed contents of many entries are
+/// appended to, so that they can be kept on disk without holding a file open
+/// for each of them.
+#[derive(Debug)]
+pub struct SpillFile(Mutex<fs::File>);
+
+impl SpillFile {
+  pub fn new(file: fs::File) -> Self { Self(Mutex::new(file)) }
+
+  /// Append the rest of `data`, and return a reader for the appended bytes.
+  pub fn append<R: Read>(self: &Arc<Self>, data: &mut R) -> io::Result<SpilledData> {
+    let Self(file) = self.as_ref();
+    let mut file = file.lock();
+    let start = file.seek(SeekFrom::End(0))?;
+    let len = io::copy(data, &mut *file)?;
+    Ok(SpilledData {
+      spill_file: self.clone(),
+      start,
+      pos: start,
+      end: start + len,
+    })
+  }
+}
+
+/// A range of bytes previously written to a [`SpillFile`].
+#[derive(Debug)]
+pub struct SpilledData {
+  spill_file: Arc<SpillFile>,
+  start: u64,
+  pos: u64,
+  end: u64,
+}
+
+impl Read for SpilledData {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let Self {
+      spill_file,
+      pos,
+      end,
+      ..
+    } = self;
+    let remaining = (*end - *pos).min(buf.len() as u64) as usize;
+    if remaining == 0 {
+      return Ok(0);
+    }
+    let SpillFile(file) = spill_file.as_ref();
+    let mut file = file.lock();
+    file.seek(SeekFrom::Start(*pos))?;
+    let n = file.read(&mut buf[..remaining])?;
+    *pos += n as u64;
+    Ok(n)
+  }
+}
+
+impl Seek for SpilledData {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let Self {
+      start,
+      pos: cur,
+      end,
+      ..
+    } = self;
+    let new_pos = seek_within(*cur - *start, *end - *start, pos)?;
+    *cur = *start + new_pos;
+    Ok(new_pos)
+  }
+}
+
+/// Resolve `pos` against a stream of `len` bytes which is currently at
+/// `cur`.
+fn seek_within(cur: u64, len: u64, pos: SeekFrom) -> io::Result<u64> {
+  let new_pos = match pos {
+    SeekFrom::Start(offset) => Some(offset),
+    SeekFrom::End(offset) => len.checked_add_signed(offset),
+    SeekFrom::Current(offset) => cur.checked_add_signed(offset),
+  };
+  new_pos.ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "invalid seek to a negative or overflowing position",
+    )
+  })
+}
+
+fn write_u16<W: Write>(w: &mut W, x: u16) -> io::Result<()> { w.write_all(&x.to_le_bytes()) }
+fn write_u32<W: Write>(w: &mut W, x: u32) -> io::Result<()> { w.write_all(&x.to_le_bytes()) }
+fn write_u64<W: Write>(w: &mut W, x: u64) -> io::Result<()> { w.write_all(&x.to_le_bytes()) }
+
+/// Everything written about a single entry in both its local header and its
+/// central directory record.
+#[derive(Clone, Debug)]
+pub struct EntryRecord {
+  /// The name of a directory entry ends with '/'.
+  pub name: String,
+  pub header: EntryHeaderMetadata,
+  method_id: u16,
+  /// Whether the local header has room for zip64 sizes. This has the same
+  /// meaning as [`zip::write::FileOptions::large_file()`], and must be set if
+  /// either size of the entry may exceed 4GiB.
+  pub large_file: bool,
+  pub info: CompressedStreamInfo,
+}
+
+impl EntryRecord {
+  /// A file entry of `size` bytes, which will be compressed with `encoding`.
+  pub fn file(
+    name: &EntryName,
+    header: EntryHeaderMetadata,
+    encoding: &EntryEncoding,
+    size: u64,
+  ) -> Self {
+    Self {
+      name: name.as_str().to_string(),
+      header,
+      method_id: encoding.method_id(),
+      large_file: size >= ZIP64_BYTES_THR,
+      info: CompressedStreamInfo::default(),
+    }
+  }
+
+  /// An uncompressed file entry of `size` bytes, whose checksum is filled in
+  /// once its data has been copied.
+  pub fn stored(name: &EntryName, header: EntryHeaderMetadata, size: u64) -> Self {
+    Self {
+      info: CompressedStreamInfo {
+        crc32: 0,
+        uncompressed_size: size,
+        compressed_size: size,
+      },
+      ..Self::file(name, header, &EntryEncoding::Stored, size)
+    }
+  }
+
+  /// A directory entry, written the same way as
+  /// [`ZipWriter::add_directory()`](zip::ZipWriter::add_directory).
+  pub fn directory(name: &EntryName, last_modified: ZipDateTime) -> Self {
+    Self {
+      name: format!("{}/", name.as_str()),
+      header: EntryHeaderMetadata {
+        last_modified,
+        unix_mode: 0o40755,
+      },
+      method_id: STORED_METHOD_ID,
+      large_file: false,
+      info: CompressedStreamInfo::default(),
+    }
+  }
+
+  /// A symlink entry, written the same way as
+  /// [`ZipWriter::add_symlink()`](zip::ZipWriter::add_symlink). Its contents
+  /// are `target`.
+  pub fn symlink(name: &EntryName, target: &str, last_modified: ZipDateTime) -> Self {
+    let len = target.len() as u64;
+    Self {
+      name: name.as_str().to_string(),
+      header: EntryHeaderMetadata {
+        last_modified,
+        unix_mode: 0o120777,
+      },
+      method_id: STORED_METHOD_ID,
+      large_file: false,
+      info: CompressedStreamInfo {
+        crc32: crc32fast::hash(target.as_bytes()),
+        uncompressed_size: len,
+        compressed_size: len,
+      },
+    }
+  }
+
+  /// The entry `file` from another archive, renamed to `name`, as with
+  /// [`ZipWriter::raw_copy_file_rename()`](zip::ZipWriter::raw_copy_file_rename).
+  pub fn copied(name: &EntryName, file: &ZipFile) -> ZipResult<Self> {
+    let method_id = match file.compression() {
+      ZipCompressionMethod::Stored => STORED_METHOD_ID,
+      ZipCompressionMethod::Deflated => DEFLATED_METHOD_ID,
+      ZipCompressionMethod::Bzip2 => BZIP2_METHOD_ID,
+      ZipCompressionMethod::Zstd => ZSTD_METHOD_ID,
+      _ => {
+        return Err(ZipError::UnsupportedArchive(
+          "unsupported compression method",
+        ))
+      },
+    };
+    let info = CompressedStreamInfo {
+      crc32: file.crc32(),
+      uncompressed_size: file.size(),
+      compressed_size: file.compressed_size(),
+    };
+    Ok(Self {
+      name: name.as_str().to_string(),
+      header: EntryHeaderMetadata {
+        last_modified: file.last_modified(),
+        unix_mode: file.unix_mode().unwrap_or(0o100644),
+      },
+      method_id,
+      large_file: info.uncompressed_size.max(info.compressed_size) >= ZIP64_BYTES_THR,
+      info,
+    })
+  }
+
+  fn general_purpose_flag(&self) -> u16 {
+    if self.name.is_ascii() {
+      0
+    } else {
+      1 << 11
+    }
+  }
+
+  fn version_needed(&self, header_start: u64) -> u16 {
+    let Self {
+      method_id, info, ..
+    } = self;
+    if *method_id == ZSTD_METHOD_ID {
+      VERSION_NEEDED_ZSTD
+    } else if *method_id == BZIP2_METHOD_ID {
+      VERSION_NEEDED_BZIP2
+    } else if info.uncompressed_size >= ZIP64_BYTES_THR
+      || info.compressed_size >= ZIP64_BYTES_THR
+      || header_start >= ZIP64_BYTES_THR
+    {
+      VERSION_NEEDED_ZIP64
+    } else {
+      VERSION_NEEDED_DEFAULT
+    }
+  }
+
+  fn check_large_file(&self) -> ZipResult<()> {
+    let Self {
+      large_file, info, ..
+    } = self;
+    if !large_file
+      && (info.uncompressed_size >= ZIP64_BYTES_THR || info.compressed_size >= ZIP64_BYTES_THR)
+    {
+      return Err(ZipError::Io(io::Error::new(
+        io::ErrorKind::Other,
+        "Large file option has not been set",
+      )));
+    }
+    Ok(())
+  }
+
+  fn write_local_header<W: Write>(&self, output: &mut W, header_start: u64) -> io::Result<()> {
+    let Self {
+      name,
+      header,
+      method_id,
+      large_file,
+      info,
+    } = self;
+    let name = name.as_bytes();
+    write_u32(output, LOCAL_FILE_HEADER_SIGNATURE)?;
+    write_u16(output, self.version_needed(header_start))?;
+    write_u16(output, self.general_purpose_flag())?;
+    write_u16(output, *method_id)?;
+    write_u16(output, header.last_modified.timepart())?;
+    write_u16(output, header.last_modified.datepart())?;
+    write_u32(output, info.crc32)?;
+    if *large_file {
+      write_u32(output, ZIP64_BYTES_THR as u32)?;
+      write_u32(output, ZIP64_BYTES_THR as u32)?;
+    } else {
+      write_u32(output, info.compressed_size as u32)?;
+      write_u32(output, info.uncompressed_size as u32)?;
+    }
+    write_u16(output, name.len() as u16)?;
+    write_u16(output, if *large_file { 20 } else { 0 })?;
+    output.write_all(name)?;
+    if *large_file {
+      write_u16(output, ZIP64_EXTRA_FIELD_TAG)?;
+      write_u16(output, 16)?;
+      write_u64(output, info.uncompressed_size)?;
+      write_u64(output, info.compressed_size)?;
+    }
+    Ok(())
+  }
+
+  fn write_central_directory_header<W: Write>(
+    &self,
+    output: &mut W,
+    header_start: u64,
+  ) -> io::Result<()> {
+    let Self {
+      name,
+      header,
+      method_id,
+      info,
+      ..
+    } = self;
+    let name = name.as_bytes();
+
+    let mut zip64_extra: Vec<u8> = Vec::new();
+    if info.uncompressed_size >= ZIP64_BYTES_THR {
+      write_u64(&mut zip64_extra, info.uncompressed_size)?;
+    }
+    if info.compressed_size >= ZIP64_BYTES_THR {
+      write_u64(&mut zip64_extra, info.compressed_size)?;
+    }
+    if header_start >= ZIP64_BYTES_THR {
+      write_u64(&mut zip64_extra, header_start)?;
+    }
+    let zip64_extra_len = if zip64_extra.is_empty() {
+      0
+    } else {
+      4 + zip64_extra.len()
+    };
+
+    write_u32(output, CENTRAL_DIRECTORY_HEADER_SIGNATURE)?;
+    write_u16(output, VERSION_MADE_BY)?;
+    write_u16(output, self.version_needed(header_start))?;
+    write_u16(output, self.general_purpose_flag())?;
+    write_u16(output, *method_id)?;
+    write_u16(output, header.last_modified.timepart())?;
+    write_u16(output, header.last_modified.datepart())?;
+    write_u32(output, info.crc32)?;
+    write_u32(output, info.compressed_size.min(ZIP64_BYTES_THR) as u32)?;
+    write_u32(output, info.uncompressed_size.min(ZIP64_BYTES_THR) as u32)?;
+    write_u16(output, name.len() as u16)?;
+    write_u16(output, zip64_extra_len as u16)?;
+    /* file comment length, disk number start, internal file attributes */
+    write_u16(output, 0)?;
+    write_u16(output, 0)?;
+    write_u16(output, 0)?;
+    write_u32(output, header.unix_mode << 16)?;
+    write_u32(output, header_start.min(ZIP64_BYTES_THR) as u32)?;
+    output.write_all(name)?;
+    if !zip64_extra.is_empty() {
+      write_u16(output, ZIP64_EXTRA_FIELD_TAG)?;
+      write_u16(output, zip64_extra.len() as u16)?;
+      output.write_all(&zip64_extra)?;
+    }
+    Ok(())
+  }
+}
+
+/// Write the records which end a zip file, for a central directory of
+/// `num_entries` entries spanning `central_directory_start` up to
+/// `central_directory_end`.
+fn write_end_of_central_directory<W: Write>(
+  output: &mut W,
+  num_entries: u64,
+  central_directory_start: u64,
+  central_directory_end: u64,
+) -> io::Result<()> {
+  let central_directory_size = central_directory_end - central_directory_start;
+  if num_entries > ZIP64_ENTRY_THR
+    || central_directory_size.max(central_directory_start) >= ZIP64_BYTES_THR
+  {
+    write_u32(output, ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE)?;
+    /* size of the remaining zip64 end of central directory record */
+    write_u64(output, 44)?;
+    write_u16(output, VERSION_MADE_BY)?;
+    write_u16(output, VERSION_NEEDED_ZIP64)?;
+    /* number of this disk, and of the disk with the central directory */
+    write_u32(output, 0)?;
+    write_u32(output, 0)?;
+    /* number of entries on this disk, and in total */
+    write_u64(output, num_entries)?;
+    write_u64(output, num_entries)?;
+    write_u64(output, central_directory_size)?;
+    write_u64(output, central_directory_start)?;
+
+    write_u32(output, ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE)?;
+    write_u32(output, 0)?;
+    write_u64(output, central_directory_end)?;
+    write_u32(output, 1)?;
+  }
+
+  let num_entries = num_entries.min(ZIP64_ENTRY_THR) as u16;
+  write_u32(output, CENTRAL_DIRECTORY_END_SIGNATURE)?;
+  write_u16(output, 0)?;
+  write_u16(output, 0)?;
+  write_u16(output, num_entries)?;
+  write_u16(output, num_entries)?;
+  write_u32(output, central_directory_size.min(ZIP64_BYTES_THR) as u32)?;
+  write_u32(output, central_directory_start.min(ZIP64_BYTES_THR) as u32)?;
+  /* comment length */
+  write_u16(output, 0)?;
+  Ok(())
+}
+
+/// The compressed contents of a single file entry, without any zip headers.
+#[derive(Debug)]
+pub struct CompressedEntry {
+  pub record: EntryRecord,
+  /// Positioned at the start of the compressed data.
+  pub data: EntryBuffer,
+}
+
+impl CompressedEntry {
+  /// Compress `input` with `encoding` into `data`. `record` must have been
+  /// created for the same `encoding`.
+  pub fn encode<R: Read>(
+    mut record: EntryRecord,
+    input: &mut R,
+    encoding: EntryEncoding,
+    mut data: EntryBuffer,
+  ) -> ZipResult<Self> {
+    record.info = encoding.encode(input, &mut data)?;
+    record.check_large_file()?;
+    data.rewind()?;
+    Ok(Self { record, data })
+  }
+}
+
+/// An entry which was compressed elsewhere, read as a zip file containing
+/// just that entry.
+///
+/// [`ZipWriter`] can only copy compressed data out of another archive, so
+/// this lets it copy `data` without writing it to a zip file of its own first.
+/// Only the headers are kept in memory.
+pub struct SingleEntryArchive<R> {
+  local_header: Vec<u8>,
+  data: R,
+  data_len: u64,
+  /// The position of [`Self::data`], so that it is only sought when this
+  /// archive is.
+  data_pos: u64,
+  /// The central directory and the end of central directory record.
+  trailer: Vec<u8>,
+  pos: u64,
+}
+
+impl<R: Read+Seek> SingleEntryArchive<R> {
+  /// `data` must be positioned at the start of the compressed contents of
+  /// `record`.
+  pub fn new(record: &EntryRecord, data: R) -> io::Result<Self> {
+    let mut local_header: Vec<u8> = Vec::new();
+    record.write_local_header(&mut local_header, 0)?;
+    let data_len = record.info.compressed_size;
+    let central_directory_start = local_header.len() as u64 + data_len;
+    let mut trailer: Vec<u8> = Vec::new();
+    record.write_central_directory_header(&mut trailer, 0)?;
+    let central_directory_end = central_directory_start + trailer.len() as u64;
+    write_end_of_central_directory(
+      &mut trailer,
+      1,
+      central_directory_start,
+      central_directory_end,
+    )?;
+    Ok(Self {
+      local_header,
+      data,
+      data_len,
+      data_pos: 0,
+      trailer,
+      pos: 0,
+    })
+  }
+
+  /// Append the entry to the archive being written by `output`.
+  pub fn copy_into<W: Write+Seek>(self, output: &mut ZipWriter<W>) -> ZipResult<()> {
+    let mut archive = ZipArchive::new(self)?;
+    let entry = archive.by_index_raw(0)?;
+    output.raw_copy_file(entry)
+  }
+}
+
+impl<R: Read+Seek> Read for SingleEntryArchive<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let Self {
+      local_header,
+      data,
+      data_len,
+      data_pos,
+      trailer,
+      pos,
+    } = self;
+    let header_len = local_header.len() as u64;
+    let data_end = header_len + *data_len;
+    let n = if *pos < header_len {
+      (&local_header[*pos as usize..]).read(buf)?
+    } else if *pos < data_end {
+      if *data_pos != *pos - header_len {
+        *data_pos = data.seek(SeekFrom::Start(*pos - header_len))?;
+      }
+      let remaining = (data_end - *pos).min(buf.len() as u64) as usize;
+      match data.read(&mut buf[..remaining])? {
+        0 => {
+          return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "compressed entry was truncated",
+          ))
+        },
+        n => {
+          *data_pos += n as u64;
+          n
+        },
+      }
+    } else {
+      let offset = (*pos - data_end).min(trailer.len() as u64) as usize;
+      (&trailer[offset..]).read(buf)?
+    };
+    *pos += n as u64;
+    Ok(n)
+  }
+}
+
+impl<R> Seek for SingleEntryArchive<R> {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let len = self.local_header.len() as u64 + self.data_len + self.trailer.len() as u64;
+    self.pos = seek_within(self.pos, len, pos)?;
+    Ok(self.pos)
+  }
+}
+
+/// Appends entries to a zip file one after another, then writes a single
+/// central directory for all of them.
+///
+/// Unlike [`ZipWriter`], this appends data which was compressed elsewhere
+/// directly, and can leave space for stored entries to be filled in later. The
+/// result is then merged into a [`ZipWriter`] in one pass.
+pub struct OrderedEntryWriter<W> {
+  output: W,
+  /// Each entry appended so far, along with the offset of its local header.
+  entries: Vec<(EntryRecord, u64)>,
+}
+
+impl<W: Write+Seek> OrderedEntryWriter<W> {
+  /// Start a new archive at the current position of `output`.
+  pub fn new(output: W) -> Self {
+    Self {
+      output,
+      entries: Vec::new(),
+    }
+  }
+
+  fn start_entry(&mut self, record: EntryRecord) -> io::Result<()> {
+    let header_start = self.output.stream_position()?;
+    record.write_local_header(&mut self.output, header_start)?;
+    self.entries.push((record, header_start));
+    Ok(())
+  }
+
+  pub fn append_directory(
+    &mut self,
+    name: &EntryName,
+    last_modified: ZipDateTime,
+  ) -> io::Result<()> {
+    self.start_entry(EntryRecord::directory(name, last_modified))
+  }
+
+  pub fn append_symlink(
+    &mut self,
+    name: &EntryName,
+    target: &str,
+    last_modified: ZipDateTime,
+  ) -> io::Result<()> {
+    self.start_entry(EntryRecord::symlink(name, target, last_modified))?;
+    self.output.write_all(target.as_bytes())
+  }
+
+  /// Copy the already-compressed contents of `entry` into a new entry, leaving
+  /// `entry` positioned at the end of its data.
+  pub fn append_compressed(&mut self, entry: &mut CompressedEntry) -> ZipResult<()> {
+    let CompressedEntry { record, data } = entry;
+    self.append_raw(record.clone(), data)
+  }
+
+  /// Copy the already-compressed contents of an entry from `data` into a new
+  /// entry.
+  pub fn append_raw<R: Read>(&mut self, record: EntryRecord, data: &mut R) -> ZipResult<()> {
+    let compressed_size = record.info.compressed_size;
+    self.start_entry(record)?;
+    let copied = io::copy(data, &mut self.output)?;
+    if copied != compressed_size {
+      return Err(ZipError::Io(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "compressed entry was truncated",
+      )));
+    }
+    Ok(())
+  }
+
+  /// Compress `input` with `encoding` directly into a new entry. `record` must
+  /// have been created for the same `encoding`.
+  pub fn append_from<R: Read>(
+    &mut self,
+    mut record: EntryRecord,
+    input: &mut R,
+    encoding: EntryEncoding,
+  ) -> ZipResult<()> {
+    /* Write the local header with placeholder values, then fill them in after
+     * the data is written. */
+    let header_start = self.output.stream_position()?;
+    record.write_local_header(&mut self.output, header_start)?;
+    record.info = encoding.encode(input, &mut self.output)?;
+    record.check_large_file()?;
+    let data_end = self.output.stream_position()?;
+    self.output.seek(SeekFrom::Start(header_start))?;
+    record.write_local_header(&mut self.output, header_start)?;
+    self.output.seek(SeekFrom::Start(data_end))?;
+    self.entries.push((record, header_start));
+    Ok(())
+  }
+
+  /// Write the local header for `record`, which must have been created with
+  /// [`EntryRecord::stored()`], and leave a hole for its data to be filled in
+  /// later with [`PositionalOutput::copy_stored_at()`].
+  ///
+  /// Returns the index of the new entry, for [`Self::set_crc32()`], along with
+  /// the offset of its data.
+  pub fn reserve_stored(&mut self, record: EntryRecord) -> io::Result<(usize, u64)> {
+    let size = record.info.compressed_size;
+    self.start_entry(record)?;
+    let data_start = self.output.stream_position()?;
+    self.output.seek(SeekFrom::Start(data_start + size))?;
+    Ok((self.entries.len() - 1, data_start))
+  }
+
+  /// Record the checksum of an entry reserved with [`Self::reserve_stored()`].
+  pub fn set_crc32(&mut self, index: usize, crc32: u32) -> io::Result<()> {
+    let (record, header_start) = &mut self.entries[index];
+    record.info.crc32 = crc32;
+    let end = self.output.stream_position()?;
+    /* The checksum follows the signature, versions, flags, method and time. */
+    self.output.seek(SeekFrom::Start(*header_start + 14))?;
+    write_u32(&mut self.output, crc32)?;
+    self.output.seek(SeekFrom::Start(end))?;
+    Ok(())
+  }
+
+  /// Write the central directory, and return the output positioned at the
+  /// end of the archive.
+  pub fn finish(self) -> io::Result<W> {
+    let Self {
+      mut output,
+      entries,
+    } = self;
+    let central_directory_start = output.stream_position()?;
+    for (record, header_start) in entries.iter() {
+      record.write_central_directory_header(&mut output, *header_start)?;
+    }
+    let central_directory_end = output.stream_position()?;
+    write_end_of_central_directory(
+      &mut output,
+      entries.len() as u64,
+      central_directory_start,
+      central_directory_end,
+    )?;
+    Ok(output)
+  }
+}
+
+#[cfg(unix)]
+impl<W: Write+Seek+'static> OrderedEntryWriter<W> {
+  /// If the output is a file, open another handle to it which can fill in
+  /// entries reserved with [`Self::reserve_stored()`] while more entries are
+  /// appended.
+  pub fn positional_output(&self) -> io::Result<Option<PositionalOutput>> {
+    match (&self.output as &dyn Any).downcast_ref::<fs::File>() {
+      Some(output) => Ok(Some(PositionalOutput(output.try_clone()?))),
+      None => Ok(None),
+    }
+  }
+}
+
+/// A second handle to an output file, which writes without moving the cursor
+/// of the [`OrderedEntryWriter`] which created it.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct PositionalOutput(fs::File);
+
+#[cfg(unix)]
+impl PositionalOutput {
+  /// Copy exactly `size` bytes of `input` to `offset` in the output, and
+  /// return their checksum.
+  pub fn copy_stored_at<R: Read>(&self, input: &mut R, offset: u64, size: u64) -> io::Result<u32> {
+    let Self(output) = self;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut input = input.take(size);
+    let mut buf: Vec<u8> = vec![0; 64 * 1024];
+    let mut copied: u64 = 0;
+    loop {
+      let n = input.read(&mut buf)?;
+      if n == 0 {
+        break;
+      }
+      hasher.update(&buf[..n]);
+      output.write_all_at(&buf[..n], offset + copied)?;
+      copied += n as u64;
+    }
+    if copied != size {
+      return Err(Self::truncated());
+    }
+    Ok(hasher.finalize())
+  }
+
+  /// Like [`Self::copy_stored_at()`], but copy from the current position of
+  /// `input` with `copy_file_range()` where the platform and filesystems
+  /// support it, so that the data never passes through userspace. `input` is
+  /// then read through once to compute the checksum.
+  pub fn copy_stored_file_at(
+    &self,
+    input: &mut fs::File,
+    offset: u64,
+    size: u64,
+  ) -> io::Result<u32> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if self.copy_file_range_at(input, offset, size)? {
+      let mut hasher = crc32fast::Hasher::new();
+      let mut input = input.take(size);
+      let mut buf: Vec<u8> = vec![0; 64 * 1024];
+      let mut read: u64 = 0;
+      loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+          break;
+        }
+        hasher.update(&buf[..n]);
+        read += n as u64;
+      }
+      if read != size {
+        return Err(Self::truncated());
+      }
+      return Ok(hasher.finalize());
+    }
+    self.copy_stored_at(input, offset, size)
+  }
+
+  /// Copy `size` bytes from the current position of `input` to `offset` in
+  /// the output without moving the cursor of either file.
+  ///
+  /// Returns `false` without copying anything if `copy_file_range()` can't be
+  /// used between these files, e.g. because they are on different
+  /// filesystems.
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn copy_file_range_at(&self, input: &mut fs::File, offset: u64, size: u64) -> io::Result<bool> {
+    use rustix::{fs::copy_file_range, io::Errno};
+
+    let Self(output) = self;
+    let mut input_offset = input.stream_position()?;
+    let mut output_offset = offset;
+    let mut remaining = size;
+    while remaining > 0 {
+      let len = usize::try_from(remaining).unwrap_or(usize::MAX);
+      match copy_file_range(
+        &*input,
+        Some(&mut input_offset),
+        output,
+        Some(&mut output_offset),
+        len,
+      ) {
+        Ok(0) => return Err(Self::truncated()),
+        Ok(n) => remaining -= n as u64,
+        Err(Errno::INTR) => continue,
+        Err(Errno::XDEV | Errno::NOSYS | Errno::INVAL) if remaining == size => return Ok(false),
+        Err(e) => return Err(e.into()),
+      }
+    }
+    Ok(true)
+  }
+
+  fn truncated() -> io::Error {
+    io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      "source file was truncated while it was being copied",
+    )
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  use zip::write::FileOptions;
+
+  use std::io::Cursor;
+
+  fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+  }
+  fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+  }
+
+  fn header() -> EntryHeaderMetadata {
+    EntryHeaderMetadata::for_file(ZipDateTime::default(), Some(0o755))
+  }
+
+  #[test]
+  fn parallel_deflate_round_trip() {
+    /* Several blocks of data which compresses well, plus a partial block. */
+    let contents: Vec<u8> = (0..(4 * 4096 + 1234))
+      .map(|i: u32| (i % 251) as u8 ^ (i / 4096) as u8)
+      .collect();
+    let encoding = EntryEncoding::DeflatedBlocks(DeflateBlocks {
+      level: Compression::default(),
+      block_size: NonZeroUsize::new(4096).unwrap(),
+    });
+    let name = EntryName::validate("a/b.txt".to_string()).unwrap();
+    let record = EntryRecord::file(&name, header(), &encoding, contents.len() as u64);
+    let mut writer = OrderedEntryWriter::new(Cursor::new(Vec::new()));
+    writer
+      .append_from(record, &mut Cursor::new(&contents), encoding)
+      .unwrap();
+    let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+    let mut entry = archive.by_index(0).unwrap();
+    assert_eq!(entry.name(), "a/b.txt");
+    assert_eq!(entry.compression(), ZipCompressionMethod::Deflated);
+    assert_eq!(entry.crc32(), crc32fast::hash(&contents));
+    assert_eq!(entry.unix_mode(), Some(0o100755));
+    assert!(entry.compressed_size() < contents.len() as u64);
+    /* Reading the entry to the end also verifies its checksum. */
+    let mut decompressed: Vec<u8> = Vec::new();
+    entry.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, contents);
+  }
+
+  #[test]
+  fn copy_compressed_between_zip_writes() {
+    let options = FileOptions::default().last_modified_time(ZipDateTime::default());
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("first.txt", options).unwrap();
+    zip.write_all(b"first").unwrap();
+
+    let name = EntryName::validate("dir/second.txt".to_string()).unwrap();
+    let contents = b"second second second second";
+    let encoding = EntryEncoding::Deflated(Compression::best());
+    let record = EntryRecord::file(&name, header(), &encoding, contents.len() as u64);
+    let CompressedEntry { record, data } = CompressedEntry::encode(
+      record,
+      &mut Cursor::new(contents),
+      encoding,
+      EntryBuffer::Spooled(tempfile::spooled_tempfile(1024)),
+    )
+    .unwrap();
+    SingleEntryArchive::new(&record, data)
+      .unwrap()
+      .copy_into(&mut zip)
+      .unwrap();
+
+    zip.start_file("third.txt", options).unwrap();
+    zip.write_all(b"third").unwrap();
+    let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+
+    let names: Vec<&str> = archive.file_names().collect();
+    assert_eq!(names.len(), 3);
+    let expected: [(&str, Option<u32>, &[u8]); 3] = [
+      ("first.txt", Some(0o100644), b"first"),
+      ("dir/second.txt", Some(0o100755), contents),
+      ("third.txt", Some(0o100644), b"third"),
+    ];
+    for (i, (name, mode, contents)) in expected.into_iter().enumerate() {
+      let mut entry = archive.by_index(i).unwrap();
+      assert_eq!(entry.name(), name);
+      assert_eq!(entry.unix_mode(), mode);
+      let mut read: Vec<u8> = Vec::new();
+      entry.read_to_end(&mut read).unwrap();
+      assert_eq!(read, contents);
+    }
+  }
+
+  #[test]
+  fn zstd_entry_headers() {
+    let contents: Vec<u8> = b"medusa ".iter().copied().cycle().take(10_000).collect();
+    let encoding = EntryEncoding::Zstd(3);
+    let name = EntryName::validate("z.txt".to_string()).unwrap();
+    let record = EntryRecord::file(&name, header(), &encoding, contents.len() as u64);
+    let mut writer = OrderedEntryWriter::new(Cursor::new(Vec::new()));
+    writer
+      .append_from(record, &mut Cursor::new(&contents), encoding)
+      .unwrap();
+    let output = writer.finish().unwrap().into_inner();
+
+    /* Both headers must ask for version 6.3, which introduced zstd. */
+    assert_eq!(read_u32(&output, 0), LOCAL_FILE_HEADER_SIGNATURE);
+    assert_eq!(read_u16(&output, 4), VERSION_NEEDED_ZSTD);
+    assert_eq!(read_u16(&output, 8), ZSTD_METHOD_ID);
+    let central_directory_start = output
+      .windows(4)
+      .position(|w| w == CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes())
+      .unwrap();
+    assert_eq!(
+      read_u16(&output, central_directory_start + 6),
+      VERSION_NEEDED_ZSTD
+    );
+    assert_eq!(
+      read_u16(&output, central_directory_start + 10),
+      ZSTD_METHOD_ID
+    );
+
+    let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+    let mut entry = archive.by_index(0).unwrap();
+    assert_eq!(entry.compression(), ZipCompressionMethod::Zstd);
+    let mut decompressed: Vec<u8> = Vec::new();
+    entry.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, contents);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn stored_copy_into_reserved_entry() {
+    let contents: Vec<u8> = (0..(3 * 64 * 1024 + 17)).map(|i: u32| i as u8).collect();
+    let size = contents.len() as u64;
+    let mut writer = OrderedEntryWriter::new(tempfile::tempfile().unwrap());
+    let positional_output = writer.positional_output().unwrap().unwrap();
+
+    let name = EntryName::validate("stored.bin".to_string()).unwrap();
+    let (index, data_start) = writer
+      .reserve_stored(EntryRecord::stored(&name, header(), size))
+      .unwrap();
+    /* Later entries may be written before the reserved data is copied. */
+    let dir = EntryName::validate("after".to_string()).unwrap();
+    writer
+      .append_directory(&dir, ZipDateTime::default())
+      .unwrap();
+    let crc32 = positional_output
+      .copy_stored_at(&mut Cursor::new(&contents), data_start, size)
+      .unwrap();
+    writer.set_crc32(index, crc32).unwrap();
+    /* A source which is shorter than its reserved space is an error. */
+    assert!(positional_output
+      .copy_stored_at(&mut Cursor::new(&contents[1..]), data_start, size)
+      .is_err());
+    positional_output
+      .copy_stored_at(&mut Cursor::new(&contents), data_start, size)
+      .unwrap();
+
+    let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+    assert_eq!(archive.by_index(1).unwrap().name(), "after/");
+    let mut entry = archive.by_index(0).unwrap();
+    assert_eq!(entry.compression(), ZipCompressionMethod::Stored);
+    assert_eq!(entry.crc32(), crc32fast::hash(&contents));
+    let mut read: Vec<u8> = Vec::new();
+    entry.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn stored_file_copy_into_reserved_entry() {
+    let contents: Vec<u8> = (0..(3 * 64 * 1024 + 17)).map(|i: u32| i as u8).collect();
+    let size = contents.len() as u64;
+    let mut input = tempfile::tempfile().unwrap();
+    input.write_all(&contents).unwrap();
+    let mut writer = OrderedEntryWriter::new(tempfile::tempfile().unwrap());
+    let positional_output = writer.positional_output().unwrap().unwrap();
+
+    let name = EntryName::validate("stored.bin".to_string()).unwrap();
+    let (index, data_start) = writer
+      .reserve_stored(EntryRecord::stored(&name, header(), size))
+      .unwrap();
+    /* A source which is shorter than its reserved space is an error. */
+    input.seek(SeekFrom::Start(1)).unwrap();
+    assert!(positional_output
+      .copy_stored_file_at(&mut input, data_start, size)
+      .is_err());
+    input.rewind().unwrap();
+    let crc32 = positional_output
+      .copy_stored_file_at(&mut input, data_start, size)
+      .unwrap();
+    writer.set_crc32(index, crc32).unwrap();
+
+    let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+    let mut entry = archive.by_index(0).unwrap();
+    assert_eq!(entry.crc32(), crc32fast::hash(&contents));
+    let mut read: Vec<u8> = Vec::new();
+    entry.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+  }
+
+  #[test]
+  fn zip64_extra_at_threshold() {
+    let name = EntryName::validate("big".to_string()).unwrap();
+    let mut record = EntryRecord::file(&name, header(), &EntryEncoding::Stored, ZIP64_BYTES_THR);
+    record.info = CompressedStreamInfo {
+      crc32: 0,
+      uncompressed_size: ZIP64_BYTES_THR,
+      compressed_size: ZIP64_BYTES_THR,
+    };
+    let mut central_directory: Vec<u8> = Vec::new();
+    record
+      .write_central_directory_header(&mut central_directory, 0)
+      .unwrap();
+
+    /* Both sizes are exactly 0xFFFFFFFF, so both must be moved into the zip64
+     * extra field, or readers would take them to mean "see the extra field". */
+    assert_eq!(read_u32(&central_directory, 20), ZIP64_BYTES_THR as u32);
+    assert_eq!(read_u32(&central_directory, 24), ZIP64_BYTES_THR as u32);
+    let extra_len = read_u16(&central_directory, 30) as usize;
+    assert_eq!(extra_len, 4 + 16);
+    let extra_start = 46 + "big".len();
+    assert_eq!(
+      read_u16(&central_directory, extra_start),
+      ZIP64_EXTRA_FIELD_TAG
+    );
+    assert_eq!(read_u16(&central_directory, extra_start + 2), 16);
+  }
+}
@@ -9,21 +9,32 @@
 
 //! ???
 
-use crate::{destination::OutputWrapper, EntryName, FileSource, MedusaNameFormatError};
+use crate::{
+  cache::{EntryCache, EntryCacheError},
+  crawl::{EntryKind, EntryMetadata},
+  destination::OutputWrapper,
+  precompressed::{
+    CompressedEntry, DeflateBlocks, EntryBuffer, EntryEncoding, EntryHeaderMetadata, EntryRecord,
+    OrderedEntryWriter, SingleEntryArchive, SpillFile, SpilledData,
+  },
+  EntryName, FileSource, MedusaNameFormatError, OpenFileLimit, SymlinkSource,
+};
 
 use cfg_if::cfg_if;
 use displaydoc::Display;
+use flate2::Compression;
 use futures::stream::StreamExt;
+use globset::{Glob, GlobMatcher};
 use once_cell::sync::Lazy;
-use parking_lot::Mutex;
 use rayon::prelude::*;
+use regex::Regex;
 use static_init;
 use tempfile;
 use thiserror::Error;
 use time::{error::ComponentRange, OffsetDateTime, UtcOffset};
 use tokio::{
   fs, io,
-  sync::{mpsc, oneshot},
+  sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore},
   task,
 };
 use tokio_stream::wrappers::ReceiverStream;
@@ -35,14 +46,17 @@ use zip::{
   ZIP64_BYTES_THR,
 };
 
-#[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
 use std::{
   cmp,
+  collections::HashMap,
+  fmt,
   io::{Seek, Write},
-  mem, num, ops,
+  mem,
+  num::{self, NonZeroU64, NonZeroUsize},
+  ops,
   path::{Path, PathBuf},
   sync::Arc,
+  time::SystemTime,
 };
 
 /// All types of errors from the parallel zip process.
@@ -64,6 +78,8 @@ pub enum MedusaZipError {
   OneshotRecv(#[from] oneshot::error::RecvError),
   /// error sending intermediate archiev: {0}
   Send(#[from] mpsc::error::SendError<ZipArchive<tempfile::SpooledTempFile>>),
+  /// error maintaining entry cache: {0}
+  EntryCache(#[from] EntryCacheError),
 }
 
 pub trait DefaultInitializeZipOptions {
@@ -86,6 +102,7 @@ pub trait InitializeZipOptionsForSpecificFile {
   fn set_zip_options_for_file(
     &self,
     options: ZipLibraryFileOptions,
+    name: &EntryName,
     metadata: &std::fs::Metadata,
   ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError>;
 }
@@ -127,99 +144,81 @@ pub enum ModifiedTimeBehavior {
 impl DefaultInitializeZipOptions for ModifiedTimeBehavior {
   #[must_use]
   fn set_zip_options_static(&self, options: ZipLibraryFileOptions) -> ZipLibraryFileOptions {
+    options.last_modified_time(self.static_zip_time())
+  }
+}
+
+impl ModifiedTimeBehavior {
+  /// The modified time to record for entries without a source file of their
+  /// own, such as directories and symlinks.
+  pub fn static_zip_time(&self) -> ZipDateTime {
     match self {
-      Self::Reproducible => options.last_modified_time(MINIMUM_ZIP_TIME),
-      Self::CurrentTime => options.last_modified_time(*CURRENT_ZIP_TIME),
-      Self::PreserveSourceTime => Self::CurrentTime.set_zip_options_static(options),
-      Self::Explicit(timestamp) => options.last_modified_time(*timestamp),
+      Self::Reproducible => MINIMUM_ZIP_TIME,
+      Self::CurrentTime => *CURRENT_ZIP_TIME,
+      Self::PreserveSourceTime => Self::CurrentTime.static_zip_time(),
+      Self::Explicit(timestamp) => *timestamp,
     }
   }
-}
 
-impl InitializeZipOptionsForSpecificFile for ModifiedTimeBehavior {
-  #[must_use]
-  fn set_zip_options_for_file(
+  /// The modified time to record for a file entry with the given `metadata`.
+  pub fn zip_time_for_file(
     &self,
-    options: ZipLibraryFileOptions,
     metadata: &std::fs::Metadata,
-  ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError> {
+  ) -> Result<ZipDateTime, InitializeZipOptionsError> {
+    match self {
+      /* NB: this is not blocking, but will Err on platforms without this available
+       * (the docs don't specify which platforms:
+       * https://doc.rust-lang.org/nightly/std/fs/struct.Metadata.html#method.modified). */
+      Self::PreserveSourceTime => self.zip_time_for_modified(metadata.modified()?),
+      _ => Ok(self.static_zip_time()),
+    }
+  }
+
+  /// The modified time to record for a file entry last modified at `mtime`.
+  pub fn zip_time_for_modified(
+    &self,
+    mtime: SystemTime,
+  ) -> Result<ZipDateTime, InitializeZipOptionsError> {
     match self {
-      Self::Reproducible => Ok(options.last_modified_time(MINIMUM_ZIP_TIME)),
-      Self::CurrentTime => Ok(options.last_modified_time(*CURRENT_ZIP_TIME)),
       Self::PreserveSourceTime => {
-        /* NB: this is not blocking, but will Err on platforms without this available
-         * (the docs don't specify which platforms:
-         * https://doc.rust-lang.org/nightly/std/fs/struct.Metadata.html#method.modified). */
-        let modified_time = metadata.modified()?;
-        let modified_time: ZipDateTime = OffsetDateTime::from(modified_time)
+        let modified_time: ZipDateTime = OffsetDateTime::from(mtime)
           .to_offset(*LOCAL_UTC_OFFSET)
           .try_into()?;
-        Ok(options.last_modified_time(modified_time))
+        Ok(modified_time)
       },
-      Self::Explicit(timestamp) => Ok(options.last_modified_time(*timestamp)),
+      _ => Ok(self.static_zip_time()),
     }
   }
 }
 
-struct PreservePermsBehavior;
-
-impl InitializeZipOptionsForSpecificFile for PreservePermsBehavior {
+impl InitializeZipOptionsForSpecificFile for ModifiedTimeBehavior {
   #[must_use]
   fn set_zip_options_for_file(
     &self,
     options: ZipLibraryFileOptions,
+    _name: &EntryName,
     metadata: &std::fs::Metadata,
   ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError> {
-    let permissions = metadata.permissions();
+    Ok(options.last_modified_time(self.zip_time_for_file(metadata)?))
+  }
+}
+
+struct PreservePermsBehavior;
+
+impl PreservePermsBehavior {
+  fn permissions(metadata: &EntryMetadata) -> Option<u32> {
     cfg_if! {
       if #[cfg(unix)] {
-        let permissions_mode: u32 = permissions.mode();
-        Ok(options.unix_permissions(permissions_mode))
+        Some(metadata.mode)
       } else {
         /* For non-unix, just don't bother trying to provide the same bits. */
-        Ok(options)
+        let _ = metadata;
+        None
       }
     }
   }
 }
 
-/* TODO: make this configurable! */
-const SMALL_FILE_FOR_NO_COMPRESSION_MAX_SIZE: usize = 1_000;
-
-struct SmallFileBehavior;
-
-impl InitializeZipOptionsForSpecificFile for SmallFileBehavior {
-  #[must_use]
-  fn set_zip_options_for_file(
-    &self,
-    options: ZipLibraryFileOptions,
-    metadata: &std::fs::Metadata,
-  ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError> {
-    if metadata.len() <= SMALL_FILE_FOR_NO_COMPRESSION_MAX_SIZE.try_into().unwrap() {
-      Ok(
-        options
-          .compression_method(ZipCompressionMethod::Stored)
-          .compression_level(None),
-      )
-    } else {
-      Ok(options)
-    }
-  }
-}
-
-struct LargeFileBehavior;
-
-impl InitializeZipOptionsForSpecificFile for LargeFileBehavior {
-  #[must_use]
-  fn set_zip_options_for_file(
-    &self,
-    options: ZipLibraryFileOptions,
-    metadata: &std::fs::Metadata,
-  ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError> {
-    Ok(options.large_file(metadata.len() > ZIP64_BYTES_THR))
-  }
-}
-
 #[derive(Copy, Clone, Default, Debug, Display)]
 pub enum CompressionMethod {
   /// uncompressed
@@ -227,12 +226,18 @@ pub enum CompressionMethod {
   /// deflate-compressed
   #[default]
   Deflated,
+  /// bzip2-compressed
+  Bzip2,
+  /// zstd-compressed
+  Zstd,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum CompressionStrategy {
   Stored,
   Deflated(Option<u8>),
+  Bzip2(Option<u8>),
+  Zstd(Option<i8>),
 }
 
 impl Default for CompressionStrategy {
@@ -247,10 +252,28 @@ pub enum ParseCompressionOptionsError {
   InvalidCompressionLevel(CompressionMethod, i8, ops::RangeInclusive<i8>),
   /// error converting from int (this should never happen!): {0}
   TryFromInt(#[from] num::TryFromIntError),
+  /// minimum compression savings {0} must be within 0.0..=1.0
+  InvalidMinSavings(f64),
 }
 
 impl CompressionStrategy {
+  const BZIP2_RANGE: ops::RangeInclusive<i8> = ops::RangeInclusive::new(1, 9);
   const DEFLATE_RANGE: ops::RangeInclusive<i8> = ops::RangeInclusive::new(0, 9);
+  const ZSTD_RANGE: ops::RangeInclusive<i8> = ops::RangeInclusive::new(-7, 22);
+
+  fn check_level(
+    method: CompressionMethod,
+    level: i8,
+    range: ops::RangeInclusive<i8>,
+  ) -> Result<i8, ParseCompressionOptionsError> {
+    if range.contains(&level) {
+      Ok(level)
+    } else {
+      Err(ParseCompressionOptionsError::InvalidCompressionLevel(
+        method, level, range,
+      ))
+    }
+  }
 
   pub fn from_method_and_level(
     method: CompressionMethod,
@@ -266,17 +289,63 @@ impl CompressionStrategy {
       CompressionMethod::Deflated => match level {
         None => Ok(Self::Deflated(None)),
         Some(level) => {
-          if Self::DEFLATE_RANGE.contains(&level) {
-            Ok(Self::Deflated(Some(level.try_into()?)))
-          } else {
-            Err(ParseCompressionOptionsError::InvalidCompressionLevel(
-              method,
-              level,
-              Self::DEFLATE_RANGE,
-            ))
-          }
+          let level = Self::check_level(method, level, Self::DEFLATE_RANGE)?;
+          Ok(Self::Deflated(Some(level.try_into()?)))
+        },
+      },
+      CompressionMethod::Bzip2 => match level {
+        None => Ok(Self::Bzip2(None)),
+        Some(level) => {
+          let level = Self::check_level(method, level, Self::BZIP2_RANGE)?;
+          Ok(Self::Bzip2(Some(level.try_into()?)))
         },
       },
+      CompressionMethod::Zstd => match level {
+        None => Ok(Self::Zstd(None)),
+        Some(level) => {
+          let level = Self::check_level(method, level, Self::ZSTD_RANGE)?;
+          Ok(Self::Zstd(Some(level)))
+        },
+      },
+    }
+  }
+
+  pub fn method(&self) -> CompressionMethod {
+    match self {
+      Self::Stored => CompressionMethod::Stored,
+      Self::Deflated(_) => CompressionMethod::Deflated,
+      Self::Bzip2(_) => CompressionMethod::Bzip2,
+      Self::Zstd(_) => CompressionMethod::Zstd,
+    }
+  }
+
+  pub fn level(&self) -> Option<i8> {
+    match self {
+      Self::Stored => None,
+      Self::Deflated(level) | Self::Bzip2(level) => level.map(|l| {
+        l.try_into()
+          .expect("these values have already been checked")
+      }),
+      Self::Zstd(level) => *level,
+    }
+  }
+}
+
+impl From<CompressionStrategy> for EntryEncoding {
+  fn from(x: CompressionStrategy) -> Self {
+    match x {
+      CompressionStrategy::Stored => Self::Stored,
+      CompressionStrategy::Deflated(level) => {
+        Self::Deflated(level.map_or_else(Compression::default, |l| Compression::new(l.into())))
+      },
+      CompressionStrategy::Bzip2(level) => {
+        Self::Bzip2(level.map_or_else(bzip2::Compression::default, |l| {
+          bzip2::Compression::new(l.into())
+        }))
+      },
+      CompressionStrategy::Zstd(level) => {
+        Self::Zstd(level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l.into()))
+      },
     }
   }
 }
@@ -284,30 +353,298 @@ impl CompressionStrategy {
 impl DefaultInitializeZipOptions for CompressionStrategy {
   #[must_use]
   fn set_zip_options_static(&self, options: ZipLibraryFileOptions) -> ZipLibraryFileOptions {
-    let (method, level): (ZipCompressionMethod, Option<i8>) = match self {
-      Self::Stored => (ZipCompressionMethod::Stored, None),
-      Self::Deflated(level) => (
-        ZipCompressionMethod::Deflated,
-        level.map(|l| {
-          l.try_into()
-            .expect("these values have already been checked")
-        }),
-      ),
+    let method: ZipCompressionMethod = match self {
+      Self::Stored => ZipCompressionMethod::Stored,
+      Self::Deflated(_) => ZipCompressionMethod::Deflated,
+      Self::Bzip2(_) => ZipCompressionMethod::Bzip2,
+      Self::Zstd(_) => ZipCompressionMethod::Zstd,
     };
     options
       .compression_method(method)
-      .compression_level(level.map(|l| {
-        l.try_into()
-          .expect("these values have already been checked")
-      }))
+      .compression_level(self.level().map(|l| l.into()))
+  }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum EntryNamePattern {
+  /// Match the entry name against a glob. Note that `*` will also match across
+  /// `/`, so `*.so` matches `a/b/c.so`.
+  Glob(GlobMatcher),
+  /// Match the entry name against a regular expression. This is unanchored
+  /// unless `^` or `$` are used.
+  Regex(Regex),
+}
+
+impl EntryNamePattern {
+  pub fn glob(pattern: &str) -> Result<Self, globset::Error> {
+    Ok(Self::Glob(Glob::new(pattern)?.compile_matcher()))
+  }
+
+  pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+    Ok(Self::Regex(Regex::new(pattern)?))
+  }
+
+  pub fn is_match(&self, name: &EntryName) -> bool {
+    match self {
+      Self::Glob(matcher) => matcher.is_match(name.as_str()),
+      Self::Regex(pattern) => pattern.is_match(name.as_str()),
+    }
+  }
+}
+
+impl fmt::Display for EntryNamePattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Glob(matcher) => write!(f, "glob:{}", matcher.glob()),
+      Self::Regex(pattern) => write!(f, "regex:{}", pattern),
+    }
+  }
+}
+
+/// Select a compression strategy for any file entry which matches all of the
+/// provided conditions.
+#[derive(Clone, Debug)]
+pub struct CompressionRule {
+  /// Matched against the final name of the entry in the output zip (including
+  /// any prefixes from [`EntryModifications`]).
+  pub name_pattern: Option<EntryNamePattern>,
+  /// Minimum size of the source file in bytes, inclusive.
+  pub min_size: Option<u64>,
+  /// Maximum size of the source file in bytes, inclusive.
+  pub max_size: Option<u64>,
+  pub strategy: CompressionStrategy,
+}
+
+impl CompressionRule {
+  pub fn matches(&self, name: &EntryName, size: u64) -> bool {
+    let Self {
+      name_pattern,
+      min_size,
+      max_size,
+      ..
+    } = self;
+    if min_size.map(|min_size| size < min_size).unwrap_or(false) {
+      return false;
+    }
+    if max_size.map(|max_size| size > max_size).unwrap_or(false) {
+      return false;
+    }
+    name_pattern
+      .as_ref()
+      .map(|p| p.is_match(name))
+      .unwrap_or(true)
+  }
+}
+
+/// An ordered list of [`CompressionRule`]s. The first rule to match a file
+/// entry overrides the [`ZipOutputOptions::compression_options`] for that
+/// entry, and entries matching no rule use the default.
+#[derive(Clone, Debug)]
+pub struct CompressionPolicy {
+  pub rules: Vec<CompressionRule>,
+}
+
+impl CompressionPolicy {
+  const SMALL_FILE_FOR_NO_COMPRESSION_MAX_SIZE: u64 = 1_000;
+
+  pub fn empty() -> Self { Self { rules: Vec::new() } }
+
+  pub fn matching_strategy(&self, name: &EntryName, size: u64) -> Option<CompressionStrategy> {
+    let Self { rules } = self;
+    rules
+      .iter()
+      .find(|rule| rule.matches(name, size))
+      .map(|CompressionRule { strategy, .. }| *strategy)
+  }
+}
+
+impl Default for CompressionPolicy {
+  /// Store small files uncompressed, as compression generally does not make
+  /// them any smaller.
+  fn default() -> Self {
+    Self {
+      rules: vec![CompressionRule {
+        name_pattern: None,
+        min_size: None,
+        max_size: Some(Self::SMALL_FILE_FOR_NO_COMPRESSION_MAX_SIZE),
+        strategy: CompressionStrategy::Stored,
+      }],
+    }
+  }
+}
+
+impl InitializeZipOptionsForSpecificFile for CompressionPolicy {
+  #[must_use]
+  fn set_zip_options_for_file(
+    &self,
+    options: ZipLibraryFileOptions,
+    name: &EntryName,
+    metadata: &std::fs::Metadata,
+  ) -> Result<ZipLibraryFileOptions, InitializeZipOptionsError> {
+    match self.matching_strategy(name, metadata.len()) {
+      Some(strategy) => Ok(strategy.set_zip_options_static(options)),
+      None => Ok(options),
+    }
+  }
+}
+
+
+/// Check the compressed size of each file entry against its uncompressed
+/// size, and write the entry uncompressed instead if compression did not save
+/// enough space (e.g. for files which are already compressed, like jpgs or
+/// nested zips).
+#[derive(Copy, Clone, Debug)]
+pub struct IncompressibleDetection {
+  min_savings: f64,
+}
+
+impl IncompressibleDetection {
+  /// `min_savings` is the fraction of the uncompressed size which compression
+  /// must save in order to keep the compressed entry.
+  pub fn new(min_savings: f64) -> Result<Self, ParseCompressionOptionsError> {
+    if (0.0..=1.0).contains(&min_savings) {
+      Ok(Self { min_savings })
+    } else {
+      Err(ParseCompressionOptionsError::InvalidMinSavings(min_savings))
+    }
+  }
+
+  pub fn min_savings(&self) -> f64 { self.min_savings }
+
+  pub fn should_store(&self, size: u64, compressed_size: u64) -> bool {
+    if size == 0 {
+      return false;
+    }
+    let savings = 1.0 - (compressed_size as f64 / size as f64);
+    savings < self.min_savings
+  }
+}
+
+/// Split large deflated entries into blocks which are compressed in parallel,
+/// then joined into a single deflate stream (like `pigz`).
+///
+/// Each block is compressed without any knowledge of the preceding block, so
+/// this produces slightly larger output than compressing the file as a whole.
+#[derive(Copy, Clone, Debug)]
+pub struct ParallelDeflate {
+  /// Files at least this large are compressed in parallel blocks.
+  pub min_size: u64,
+  /// Size in bytes of each uncompressed block.
+  pub block_size: NonZeroUsize,
+}
+
+impl Default for ParallelDeflate {
+  fn default() -> Self {
+    Self {
+      min_size: 64 * 1024 * 1024,
+      block_size: NonZeroUsize::new(1024 * 1024).unwrap(),
+    }
   }
 }
 
+impl ParallelDeflate {
+  /// Returns how to compress an entry of `size` bytes, if it should be split
+  /// into blocks. Only entries which would otherwise be deflated are split.
+  pub fn for_entry(&self, strategy: CompressionStrategy, size: u64) -> Option<DeflateBlocks> {
+    let Self {
+      min_size,
+      block_size,
+    } = self;
+    match strategy {
+      CompressionStrategy::Deflated(level) if size >= *min_size => Some(DeflateBlocks {
+        level: level.map_or_else(Compression::default, |l| Compression::new(l.into())),
+        block_size: *block_size,
+      }),
+      _ => None,
+    }
+  }
+}
 
+/// Selects the [`EntryEncoding`] used to compress each file entry.
 #[derive(Copy, Clone, Default, Debug)]
+pub struct EntryEncodingSelector {
+  /// Large entries to split into parallel deflate blocks.
+  pub parallel_deflate: Option<ParallelDeflate>,
+}
+
+impl EntryEncodingSelector {
+  pub fn encoding_for_entry(&self, strategy: CompressionStrategy, size: u64) -> EntryEncoding {
+    let Self { parallel_deflate } = self;
+    match parallel_deflate.and_then(|parallel_deflate| parallel_deflate.for_entry(strategy, size)) {
+      Some(deflate_blocks) => EntryEncoding::DeflatedBlocks(deflate_blocks),
+      None => strategy.into(),
+    }
+  }
+}
+
+/// Options for writing an entry with `header` through [`ZipWriter`], which
+/// must be told up front whether the entry may need zip64 sizes.
+fn zip_library_options(header: &EntryHeaderMetadata, large_file: bool) -> ZipLibraryFileOptions {
+  let EntryHeaderMetadata {
+    last_modified,
+    unix_mode,
+  } = header;
+  ZipLibraryFileOptions::default()
+    .last_modified_time(*last_modified)
+    .unix_permissions(*unix_mode)
+    .large_file(large_file)
+}
+
+/// Everything which affects how a single file entry is compressed, beyond its
+/// header and compression strategy.
+#[derive(Clone, Debug)]
+pub struct SingleEntryBehavior {
+  pub incompressible_detection: Option<IncompressibleDetection>,
+  pub encoding_selector: EntryEncodingSelector,
+  pub entry_cache: Option<EntryCache>,
+  /// A permit is held from opening each source file until it has been
+  /// compressed, and for its buffer until that has been written. See
+  /// [`PipelineTuning::max_open_files`].
+  pub open_files: OpenFileLimit,
+}
+
+impl SingleEntryBehavior {
+  /// Whether each file must be compressed into a buffer before it can be
+  /// copied into the output, instead of being compressed into the output
+  /// directly.
+  pub fn needs_buffer(&self) -> bool {
+    let Self {
+      incompressible_detection,
+      entry_cache,
+      ..
+    } = self;
+    incompressible_detection.is_some() || entry_cache.is_some()
+  }
+
+  /// Whether a file of `size` bytes to be written with `encoding` is copied
+  /// straight into the output. See [`IntermediateSingleEntry::StoredFile`].
+  pub fn copies_directly(
+    &self,
+    encoding: &EntryEncoding,
+    size: u64,
+    tuning: &PipelineTuning,
+  ) -> bool {
+    /* Large files which are written uncompressed would only be copied into a
+     * temporary file on disk (unless they are to be cached). */
+    matches!(encoding, EntryEncoding::Stored)
+      && tuning.stored_copy_fast_path(size)
+      && self.entry_cache.is_none()
+  }
+}
+
+
+#[derive(Clone, Default, Debug)]
 pub struct ZipOutputOptions {
   pub mtime_behavior: ModifiedTimeBehavior,
   pub compression_options: CompressionStrategy,
+  pub compression_policy: CompressionPolicy,
+  /// If provided, entries which compress poorly are written uncompressed.
+  pub incompressible_detection: Option<IncompressibleDetection>,
+  /// If provided, large entries are deflated in parallel blocks.
+  pub parallel_deflate: Option<ParallelDeflate>,
+  /// If provided, compressed entries are reused from and saved to this cache.
+  pub entry_cache: Option<EntryCache>,
 }
 
 
@@ -328,12 +665,91 @@ pub struct EntryModifications {
   /// `--own-prefix .deps` => `[.deps/, .deps/a, .deps/b, ...]`
   /* FIXME: explain how these work when stacked together! */
   pub own_prefix: Option<String>,
+  /// Rewrite the name of each file and symlink, in order, before any
+  /// directories are introduced or either of the prefixes above are applied.
+  ///
+  /// `--rewrite strip-prefix:src --rewrite 'replace:\.py\.in$=.py'` =>
+  /// `src/a.py.in` becomes `a.py`
+  pub rewrites: Vec<NameRewrite>,
+}
+
+impl EntryModifications {
+  /// Apply each of [`Self::rewrites`] to `name`, returning [`None`] if the
+  /// name was unchanged.
+  pub(crate) fn rewrite_name(
+    &self,
+    name: &EntryName,
+  ) -> Result<Option<EntryName>, MedusaNameFormatError> {
+    if self.rewrites.is_empty() {
+      return Ok(None);
+    }
+    let mut rewritten: String = name.as_str().to_string();
+    for rewrite in self.rewrites.iter() {
+      rewritten = rewrite.apply(rewritten);
+    }
+    if rewritten == name.as_str() {
+      return Ok(None);
+    }
+    Ok(Some(EntryName::validate(rewritten)?))
+  }
+}
+
+/// A single rule in [`EntryModifications::rewrites`].
+#[derive(Clone, Debug)]
+pub enum NameRewrite {
+  /// Remove these leading directory components from any name which begins
+  /// with all of them.
+  StripPrefix(EntryName),
+  /// Add these leading directory components to every name.
+  AddPrefix(EntryName),
+  /// Replace every match of `pattern` in the name, as with
+  /// [`Regex::replace_all()`], so `replacement` may refer to capture groups
+  /// such as `$1`.
+  Replace { pattern: Regex, replacement: String },
+}
+
+impl NameRewrite {
+  fn apply(&self, name: String) -> String {
+    match self {
+      Self::StripPrefix(prefix) => match name
+        .strip_prefix(prefix.as_str())
+        .and_then(|rest| rest.strip_prefix('/'))
+      {
+        Some(rest) => rest.to_string(),
+        None => name,
+      },
+      Self::AddPrefix(prefix) => format!("{}/{}", prefix.as_str(), name),
+      Self::Replace {
+        pattern,
+        replacement,
+      } => pattern
+        .replace_all(&name, replacement.as_str())
+        .into_owned(),
+    }
+  }
+}
+
+impl fmt::Display for NameRewrite {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::StripPrefix(prefix) => write!(f, "strip-prefix:{}", prefix.as_str()),
+      Self::AddPrefix(prefix) => write!(f, "add-prefix:{}", prefix.as_str()),
+      Self::Replace {
+        pattern,
+        replacement,
+      } => write!(f, "replace:{}={}", pattern, replacement),
+    }
+  }
 }
 
 #[derive(Debug, Display, Error)]
 pub enum InputConsistencyError {
   /// name {0} was duplicated for source paths {1:?} and {2:?}
   DuplicateName(EntryName, PathBuf, PathBuf),
+  /// names {1} (from {2:?}) and {3} (from {4:?}) were both rewritten to {0}
+  RewriteCollision(EntryName, EntryName, PathBuf, EntryName, PathBuf),
+  /// rewriting name {0} (from {1:?}) produced an invalid name: {2}
+  InvalidRewrite(EntryName, PathBuf, MedusaNameFormatError),
   /// error in name formatting: {0}
   NameFormat(#[from] MedusaNameFormatError),
 }
@@ -342,6 +758,108 @@ pub enum InputConsistencyError {
 pub enum ZipEntrySpecification {
   File(FileSource),
   Directory(EntryName),
+  Symlink(SymlinkSource),
+}
+
+impl FileSource {
+  /// Return [`Self::metadata`], reading it from the filesystem first if the
+  /// crawl did not already record it.
+  async fn metadata_or_stat(&mut self) -> Result<EntryMetadata, MedusaInputReadError> {
+    let Self {
+      source, metadata, ..
+    } = self;
+    if let Some(metadata) = metadata {
+      return Ok(*metadata);
+    }
+    let stat = fs::metadata(&source)
+      .await
+      .and_then(|stat| EntryMetadata::from_metadata(EntryKind::File, &stat))
+      .map_err(|e| MedusaInputReadError::SourceNotFound(source.clone(), e))?;
+    *metadata = Some(stat);
+    Ok(stat)
+  }
+}
+
+/// The contents of a file or symlink provided as input.
+#[derive(Default)]
+enum SourceContents {
+  /// Only used as a placeholder when moving out of a [`SourceEntry`].
+  #[default]
+  Empty,
+  File(PathBuf, Option<EntryMetadata>),
+  Symlink(String),
+}
+
+/// A file or symlink provided as input, before any directory entries have been
+/// introduced.
+struct SourceEntry {
+  name: EntryName,
+  contents: SourceContents,
+  /// The name before it was changed by [`EntryModifications::rewrites`], if it
+  /// was changed at all.
+  original_name: Option<EntryName>,
+}
+
+impl SourceEntry {
+  /// The path to report if this entry's name is duplicated.
+  fn source_path(&self) -> &Path {
+    match &self.contents {
+      SourceContents::Empty => Path::new(""),
+      SourceContents::File(source, _) => source,
+      SourceContents::Symlink(target) => Path::new(target),
+    }
+  }
+
+  fn new(name: EntryName, contents: SourceContents) -> Self {
+    Self {
+      name,
+      contents,
+      original_name: None,
+    }
+  }
+
+  fn rewrite(&mut self, modifications: &EntryModifications) -> Result<(), InputConsistencyError> {
+    let rewritten = modifications.rewrite_name(&self.name).map_err(|e| {
+      InputConsistencyError::InvalidRewrite(self.name.clone(), self.source_path().to_path_buf(), e)
+    })?;
+    if let Some(rewritten) = rewritten {
+      self.original_name = Some(mem::replace(&mut self.name, rewritten));
+    }
+    Ok(())
+  }
+
+  /// The error to report if this entry has the same name as `prev`.
+  fn duplicate_name_error(&self, prev: &Self) -> InputConsistencyError {
+    if self.original_name.is_none() && prev.original_name.is_none() {
+      return InputConsistencyError::DuplicateName(
+        self.name.clone(),
+        prev.source_path().to_path_buf(),
+        self.source_path().to_path_buf(),
+      );
+    }
+    let original_name = |entry: &Self| entry.original_name.as_ref().unwrap_or(&entry.name).clone();
+    InputConsistencyError::RewriteCollision(
+      self.name.clone(),
+      original_name(prev),
+      prev.source_path().to_path_buf(),
+      original_name(self),
+      self.source_path().to_path_buf(),
+    )
+  }
+
+  fn into_spec(name: EntryName, contents: SourceContents) -> ZipEntrySpecification {
+    match contents {
+      SourceContents::Empty => unreachable!("contents should only be taken once"),
+      SourceContents::File(source, metadata) => ZipEntrySpecification::File(FileSource {
+        name,
+        source,
+        metadata,
+      }),
+      SourceContents::Symlink(target) => {
+        ZipEntrySpecification::Symlink(SymlinkSource { name, target })
+      },
+    }
+  }
 }
 
 struct EntrySpecificationList(pub Vec<ZipEntrySpecification>);
@@ -377,89 +895,103 @@ where
 }
 
 impl EntrySpecificationList {
-  fn sort_and_deduplicate(specs: &mut Vec<FileSource>) -> Result<(), InputConsistencyError> {
+  fn sort_and_deduplicate(specs: &mut [SourceEntry]) -> Result<(), InputConsistencyError> {
     /* Sort the resulting files so we can expect them to (mostly) be an inorder
      * directory traversal. Note that directories with names less than top-level
      * files will be sorted above those top-level files. */
-    specs.par_sort_unstable();
+    specs.par_sort_unstable_by(|a, b| a.name.cmp(&b.name));
 
     /* Check for duplicate names. */
-    {
-      let i = EntryName::empty();
-      let p = PathBuf::from("");
-      let mut prev_name: &EntryName = &i;
-      let mut prev_path: &Path = &p;
-      for FileSource { source, name } in specs.iter() {
-        if name == prev_name {
-          return Err(InputConsistencyError::DuplicateName(
-            name.clone(),
-            prev_path.to_path_buf(),
-            source.clone(),
-          ));
-        }
-        prev_name = name;
-        prev_path = source;
+    for pair in specs.windows(2) {
+      let (prev, spec) = (&pair[0], &pair[1]);
+      if spec.name == prev.name {
+        return Err(spec.duplicate_name_error(prev));
       }
     }
 
     Ok(())
   }
 
+  /// Validate the prefix which `modifications` applies to every entry name,
+  /// along with the directory entries it introduces.
+  fn prefix_directories(
+    modifications: EntryModifications,
+  ) -> Result<(EntryName, Vec<ZipEntrySpecification>), InputConsistencyError> {
+    /* TODO: make EntryName work more cleanly for directories and files! */
+    let EntryModifications {
+      silent_external_prefix,
+      own_prefix,
+      ..
+    } = modifications;
+    let silent_external_prefix: Vec<String> = silent_external_prefix
+      .map(EntryName::validate)
+      .transpose()?
+      .map(|name| {
+        name
+          .all_components()
+          .map(|s| s.to_string())
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    let own_prefix: Vec<String> = own_prefix
+      .map(EntryName::validate)
+      .transpose()?
+      .map(|name| {
+        name
+          .all_components()
+          .map(|s| s.to_string())
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+
+    let mut ret: Vec<ZipEntrySpecification> = Vec::new();
+    let mut cur_prefix: Vec<String> = silent_external_prefix;
+    for component in own_prefix.into_iter() {
+      cur_prefix.push(component);
+      let cur_intermediate_directory: String = cur_prefix.join("/");
+      let intermediate_dir = EntryName::validate(cur_intermediate_directory)
+        .expect("constructed virtual directory should be fine");
+      ret.push(ZipEntrySpecification::Directory(intermediate_dir));
+    }
+    let cached_prefix = if cur_prefix.is_empty() {
+      EntryName::empty()
+    } else {
+      EntryName::validate(cur_prefix.join("/")).unwrap()
+    };
+    Ok((cached_prefix, ret))
+  }
+
   pub fn from_file_specs(
-    mut specs: Vec<FileSource>,
+    files: Vec<FileSource>,
+    symlinks: Vec<SymlinkSource>,
     modifications: EntryModifications,
   ) -> Result<Self, InputConsistencyError> {
+    let mut specs: Vec<SourceEntry> = files
+      .into_iter()
+      .map(
+        |FileSource {
+           name,
+           source,
+           metadata,
+         }| SourceEntry::new(name, SourceContents::File(source, metadata)),
+      )
+      .chain(symlinks.into_iter().map(|SymlinkSource { name, target }| {
+        SourceEntry::new(name, SourceContents::Symlink(target))
+      }))
+      .collect();
+    /* Rewrite names before sorting, so that collisions are caught. */
+    specs
+      .par_iter_mut()
+      .try_for_each(|spec| spec.rewrite(&modifications))?;
     Self::sort_and_deduplicate(&mut specs)?;
 
-    let mut ret: Vec<ZipEntrySpecification> = Vec::new();
-
-    let cached_prefix: EntryName = {
-      /* TODO: make EntryName work more cleanly for directories and files! */
-      let EntryModifications {
-        silent_external_prefix,
-        own_prefix,
-      } = modifications;
-      let silent_external_prefix: Vec<String> = silent_external_prefix
-        .map(EntryName::validate)
-        .transpose()?
-        .map(|name| {
-          name
-            .all_components()
-            .map(|s| s.to_string())
-            .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
-      let own_prefix: Vec<String> = own_prefix
-        .map(EntryName::validate)
-        .transpose()?
-        .map(|name| {
-          name
-            .all_components()
-            .map(|s| s.to_string())
-            .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
-
-      let mut cur_prefix: Vec<String> = silent_external_prefix;
-      for component in own_prefix.into_iter() {
-        cur_prefix.push(component);
-        let cur_intermediate_directory: String = cur_prefix.join("/");
-        let intermediate_dir = EntryName::validate(cur_intermediate_directory)
-          .expect("constructed virtual directory should be fine");
-        ret.push(ZipEntrySpecification::Directory(intermediate_dir));
-      }
-      if cur_prefix.is_empty() {
-        EntryName::empty()
-      } else {
-        EntryName::validate(cur_prefix.join("/")).unwrap()
-      }
-    };
+    let (cached_prefix, mut ret) = Self::prefix_directories(modifications)?;
 
     let mut previous_directory_components: Vec<&str> = Vec::new();
 
     /* NB: .iter_mut() is used here to enable the use of &str references in
      * previous_directory_components! */
-    for FileSource { source, name } in specs.iter_mut() {
+    for SourceEntry { name, contents, .. } in specs.iter_mut() {
       /* Split into directory components so we can add directory entries before any
        * files from that directory. */
       let current_directory_components: Vec<&str> = name.parent_components().collect();
@@ -480,10 +1012,7 @@ impl EntrySpecificationList {
       /* Finally we can just write the actual file now! */
       let mut name = name.clone();
       name.add_prefix(&cached_prefix);
-      ret.push(ZipEntrySpecification::File(FileSource {
-        source: mem::take(source),
-        name,
-      }));
+      ret.push(SourceEntry::into_spec(name, mem::take(contents)));
     }
 
     Ok(Self(ret))
@@ -502,74 +1031,285 @@ pub enum MedusaInputReadError {
   Send(#[from] mpsc::error::SendError<IntermediateSingleEntry>),
   /// failed to parse zip output options: {0}
   InitZipOptions(#[from] InitializeZipOptionsError),
+  /// error reading or writing cached entry: {0}
+  Cache(#[from] EntryCacheError),
+}
+
+/// A large file to write uncompressed, which is copied straight into the
+/// output once its place there is known.
+#[derive(Debug)]
+pub struct StoredFile {
+  pub record: EntryRecord,
+  pub source: PathBuf,
+  pub handle: std::fs::File,
+  /// Held until the file has been copied and closed.
+  pub permit: OwnedSemaphorePermit,
+}
+
+/// A compressed file, along with the permit which counts its buffer against
+/// [`PipelineTuning::max_open_files`] until it has been written.
+#[derive(Debug)]
+pub struct BufferedEntry {
+  pub entry: CompressedEntry,
+  pub permit: OwnedSemaphorePermit,
 }
 
 #[derive(Debug)]
 pub enum IntermediateSingleEntry {
   Directory(EntryName),
-  File(oneshot::Receiver<Result<ZipArchive<tempfile::SpooledTempFile>, MedusaInputReadError>>),
+  Symlink(SymlinkSource),
+  File(oneshot::Receiver<Result<BufferedEntry, MedusaInputReadError>>),
+  StoredFile(StoredFile),
+  /// A file which was compressed and moved into a [`SpillFile`] before its
+  /// place in the output was known.
+  Spilled(task::JoinHandle<Result<(EntryRecord, SpilledData), MedusaZipError>>),
 }
 
-const PER_FILE_SPOOL_THRESHOLD: usize = 3_000;
-
 impl IntermediateSingleEntry {
+  fn write_single_entry_encoded(
+    name: &EntryName,
+    handle: &mut std::fs::File,
+    size: u64,
+    header: EntryHeaderMetadata,
+    encoding: EntryEncoding,
+    tuning: &PipelineTuning,
+  ) -> Result<CompressedEntry, MedusaInputReadError> {
+    let data = match encoding {
+      /* If the buffer would be spooled to disk anyway, write it to a file
+       * directly. */
+      EntryEncoding::Stored if tuning.stored_copy_fast_path(size) => {
+        EntryBuffer::File(tuning.tempfile().map_err(ZipError::Io)?)
+      },
+      _ => EntryBuffer::Spooled(tuning.spooled_tempfile(tuning.per_file_spool_threshold)),
+    };
+    let record = EntryRecord::file(name, header, &encoding, size);
+    Ok(CompressedEntry::encode(record, handle, encoding, data)?)
+  }
+
+  /// Compress `handle`, which holds `size` bytes, with `encoding` into a
+  /// buffer, which may be spooled to disk.
+  ///
+  /// If `incompressible_detection` is provided and compressing the file did not
+  /// save enough space, the file is rewound and written again uncompressed.
+  /// This depends only upon the file contents and the zip options, so the
+  /// output remains deterministic.
+  pub fn write_single_entry(
+    name: &EntryName,
+    source: &Path,
+    handle: &mut std::fs::File,
+    size: u64,
+    header: EntryHeaderMetadata,
+    encoding: EntryEncoding,
+    incompressible_detection: Option<IncompressibleDetection>,
+    tuning: &PipelineTuning,
+  ) -> Result<CompressedEntry, MedusaInputReadError> {
+    let mut entry = Self::write_single_entry_encoded(name, handle, size, header, encoding, tuning)?;
+
+    if let Some(incompressible_detection) = incompressible_detection {
+      let info = entry.record.info;
+      if !matches!(encoding, EntryEncoding::Stored)
+        && incompressible_detection.should_store(info.uncompressed_size, info.compressed_size)
+      {
+        handle
+          .rewind()
+          .map_err(|e| MedusaInputReadError::SourceNotFound(source.to_path_buf(), e))?;
+        entry = Self::write_single_entry_encoded(
+          name,
+          handle,
+          size,
+          header,
+          EntryEncoding::Stored,
+          tuning,
+        )?;
+      }
+    }
+
+    Ok(entry)
+  }
+
+  /// Describe everything besides the file contents which may change the
+  /// compressed entry (apart from its name, which is rewritten on a cache hit).
+  ///
+  /// Each field is written out explicitly rather than with [`fmt::Debug`], so
+  /// that cache keys stay the same for as long as the entries they describe
+  /// do.
+  fn entry_cache_recipe(
+    strategy: CompressionStrategy,
+    encoding: EntryEncoding,
+    header: EntryHeaderMetadata,
+    incompressible_detection: Option<IncompressibleDetection>,
+  ) -> String {
+    fn level<L: fmt::Display>(level: Option<L>) -> String {
+      level
+        .map(|level| level.to_string())
+        .unwrap_or_else(|| "default".to_string())
+    }
+    let strategy = match strategy {
+      CompressionStrategy::Stored => "stored".to_string(),
+      CompressionStrategy::Deflated(l) => format!("deflated:{}", level(l)),
+      CompressionStrategy::Bzip2(l) => format!("bzip2:{}", level(l)),
+      CompressionStrategy::Zstd(l) => format!("zstd:{}", level(l)),
+    };
+    let encoding = match encoding {
+      EntryEncoding::Stored => "stored".to_string(),
+      EntryEncoding::Deflated(level) => format!("deflated:{}", level.level()),
+      EntryEncoding::DeflatedBlocks(DeflateBlocks { level, block_size }) => {
+        format!("deflated-blocks:{}:{}", level.level(), block_size)
+      },
+      EntryEncoding::Bzip2(level) => format!("bzip2:{}", level.level()),
+      EntryEncoding::Zstd(level) => format!("zstd:{}", level),
+    };
+    let EntryHeaderMetadata {
+      last_modified,
+      unix_mode,
+    } = header;
+    /* The min savings are compared exactly, so they are written out exactly. */
+    let min_savings = match incompressible_detection {
+      None => "none".to_string(),
+      Some(detection) => format!("{:016x}", detection.min_savings().to_bits()),
+    };
+    format!(
+      "strategy={} encoding={} date={} time={} mode={:o} min-savings={}",
+      strategy,
+      encoding,
+      last_modified.datepart(),
+      last_modified.timepart(),
+      unix_mode,
+      min_savings,
+    )
+  }
+
+  /// Like [`Self::write_single_entry()`], but first looks the file up in the
+  /// entry cache from `entry_behavior`, if any, and stores the result there
+  /// afterwards on a miss.
+  ///
+  /// `strategy` must be the compression selected for this file, since
+  /// `encoding` may not describe it fully.
+  pub fn write_single_entry_with_behavior(
+    name: &EntryName,
+    source: &Path,
+    handle: &mut std::fs::File,
+    size: u64,
+    header: EntryHeaderMetadata,
+    strategy: CompressionStrategy,
+    encoding: EntryEncoding,
+    entry_behavior: &SingleEntryBehavior,
+    tuning: &PipelineTuning,
+  ) -> Result<CompressedEntry, MedusaInputReadError> {
+    let SingleEntryBehavior {
+      incompressible_detection,
+      entry_cache,
+      ..
+    } = entry_behavior;
+    let entry_cache = match entry_cache {
+      None => {
+        return Self::write_single_entry(
+          name,
+          source,
+          handle,
+          size,
+          header,
+          encoding,
+          *incompressible_detection,
+          tuning,
+        )
+      },
+      Some(entry_cache) => entry_cache,
+    };
+
+    let recipe = Self::entry_cache_recipe(strategy, encoding, header, *incompressible_detection);
+    let key = entry_cache.key_for_file(handle, &recipe)?;
+    if let Some(cached) = entry_cache.get(&key, name, tuning)? {
+      return Ok(cached);
+    }
+
+    let mut entry = Self::write_single_entry(
+      name,
+      source,
+      handle,
+      size,
+      header,
+      encoding,
+      *incompressible_detection,
+      tuning,
+    )?;
+    entry_cache.insert(&key, &mut entry)?;
+    Ok(entry)
+  }
+
   pub async fn open_handle(
     entry: ZipEntrySpecification,
-    mut zip_options: zip::write::FileOptions,
     options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
   ) -> Result<Self, MedusaInputReadError> {
     match entry {
       /* If it's a directory, we don't need any more info. */
       ZipEntrySpecification::Directory(name) => Ok(Self::Directory(name)),
+      /* Symlinks are written directly from their target string. */
+      ZipEntrySpecification::Symlink(symlink) => Ok(Self::Symlink(symlink)),
       /* If it's a file, we're need to extract its contents. */
-      ZipEntrySpecification::File(FileSource { name, source }) => {
+      ZipEntrySpecification::File(mut file) => {
+        /* Count both the source file and the buffer it may be compressed into, so
+         * that neither has to wait for the other once the file is opened. */
+        let mut permit = entry_behavior.open_files.acquire(2).await;
+        /* Get the filesystem metadata for this file, if the crawl didn't already. */
+        let metadata = file.metadata_or_stat().await?;
+        let FileSource { name, source, .. } = file;
         /* Get the file handle */
         let handle = fs::OpenOptions::new()
           .read(true)
           .open(&source)
           .await
           .map_err(|e| MedusaInputReadError::SourceNotFound(source.clone(), e))?;
-        /* Get the filesystem metadata for this file. */
-        let metadata = handle
-          .metadata()
-          .await
-          .map_err(|e| MedusaInputReadError::SourceNotFound(source.clone(), e))?;
-        /* Configure the zip options for this file, such as compression, given the
-         * metadata. */
-        zip_options = options_initializers.set_zip_options_for_file(zip_options, &metadata)?;
-
-        /* Create the spooled temporary zip file. */
-        let mut zip_output: ZipWriter<tempfile::SpooledTempFile> = task::spawn_blocking(|| {
-          let temp_file = tempfile::spooled_tempfile(PER_FILE_SPOOL_THRESHOLD);
-          let zip_wrapper = ZipWriter::new(temp_file);
-          Ok::<_, MedusaInputReadError>(zip_wrapper)
-        })
-        .await??;
+        /* Select the header and compression for this file, given the metadata. */
+        let size = metadata.size;
+        let header = options_initializers.entry_header_for_file(&metadata)?;
+        let strategy = options_initializers.compression_strategy_for_file(&name, size);
+        let encoding = entry_behavior
+          .encoding_selector
+          .encoding_for_entry(strategy, size);
+
+        if entry_behavior.copies_directly(&encoding, size, &tuning) {
+          /* There is no buffer. */
+          OpenFileLimit::release_one(&mut permit);
+          return Ok(Self::StoredFile(StoredFile {
+            record: EntryRecord::stored(&name, header, size),
+            source,
+            handle: handle.into_std().await,
+            permit,
+          }));
+        }
 
         /* We can send a oneshot::Receiver over an mpsc::bounded() channel in order
          * to force our receiving send of this the mpsc::bounded() to await
          * until the oneshot::Receiver is complete. */
-        let (tx, rx) =
-          oneshot::channel::<Result<ZipArchive<tempfile::SpooledTempFile>, MedusaInputReadError>>();
+        let (tx, rx) = oneshot::channel::<Result<BufferedEntry, MedusaInputReadError>>();
 
         let mut handle = handle.into_std().await;
         task::spawn(async move {
-          let completed_single_zip: Result<
-            ZipArchive<tempfile::SpooledTempFile>,
-            MedusaInputReadError,
-          > = task::spawn_blocking(move || {
-            /* In parallel, we will be writing this input file out to a spooled temporary
-             * zip containing just this one entry. */
-            zip_output.start_file(name.into_string(), zip_options)?;
-            std::io::copy(&mut handle, &mut zip_output)
-              .map_err(|e| MedusaInputReadError::SourceNotFound(source.clone(), e))?;
-            let temp_zip = zip_output.finish_into_readable()?;
-            Ok::<ZipArchive<_>, MedusaInputReadError>(temp_zip)
-          })
-          .await
-          .expect("joining should not fail");
-          tx.send(completed_single_zip)
-            .expect("rx should always be open");
+          let compressed_entry: Result<CompressedEntry, MedusaInputReadError> =
+            task::spawn_blocking(move || {
+              /* In parallel, we will be compressing this input file out to a spooled
+               * temporary buffer. */
+              Self::write_single_entry_with_behavior(
+                &name,
+                &source,
+                &mut handle,
+                size,
+                header,
+                strategy,
+                encoding,
+                &entry_behavior,
+                &tuning,
+              )
+            })
+            .await
+            .expect("joining should not fail");
+          /* The source file has been closed, but the buffer remains. */
+          OpenFileLimit::release_one(&mut permit);
+          let buffered_entry = compressed_entry.map(|entry| BufferedEntry { entry, permit });
+          tx.send(buffered_entry).expect("rx should always be open");
         });
         /* NB: not awaiting this spawned task! */
 
@@ -591,196 +1331,963 @@ pub enum Parallelism {
   /// Parallelize creation by splitting up the input into chunks.
   #[default]
   ParallelMerge,
+  /// <PARALLEL-TREE-MERGE>
+  ///
+  /// Like <PARALLEL-MERGE>, but merge adjacent chunks together in parallel
+  /// before copying the few largest into the output zip.
+  ParallelTreeMerge,
+  /// <PER-ENTRY>
+  ///
+  /// Compress each file entry in parallel, then write them in order to a
+  /// single temporary zip which is copied into the output zip.
+  PerEntry,
+}
+
+/// An intermediate zip, along with the permit which counts it against
+/// [`PipelineTuning::max_parallel_intermediates`] until it has been merged.
+struct Intermediate {
+  archive: ZipArchive<tempfile::SpooledTempFile>,
+  permit: Option<OwnedSemaphorePermit>,
+}
+
+type IntermediateReceiver = oneshot::Receiver<Result<Intermediate, MedusaZipError>>;
+
+/// A chunk of entries waiting to be written to an intermediate zip.
+struct PendingChunk {
+  /// Position of this chunk within the output.
+  index: usize,
+  entries: Vec<ZipEntrySpecification>,
+  /// Total size of the source files in this chunk.
+  size: u64,
+  result_tx: oneshot::Sender<Result<Intermediate, MedusaZipError>>,
+}
+
+/// Splits entries into contiguous chunks of roughly
+/// [`PipelineTuning::intermediate_chunk_bytes`] of source data (and at most
+/// [`PipelineTuning::intermediate_chunk_size`] entries). Any file at least as
+/// large as the byte limit is placed into a chunk of its own.
+struct EntryChunker {
+  max_bytes: u64,
+  max_entries: usize,
+  cur_chunk: Vec<ZipEntrySpecification>,
+  cur_bytes: u64,
+}
+
+impl EntryChunker {
+  fn new(tuning: &PipelineTuning) -> Self {
+    Self {
+      max_bytes: tuning.intermediate_chunk_bytes.get(),
+      max_entries: tuning.intermediate_chunk_size.get(),
+      cur_chunk: Vec::new(),
+      cur_bytes: 0,
+    }
+  }
+
+  fn take_chunk(&mut self) -> Option<(Vec<ZipEntrySpecification>, u64)> {
+    if self.cur_chunk.is_empty() {
+      return None;
+    }
+    let chunk = mem::take(&mut self.cur_chunk);
+    Some((chunk, mem::take(&mut self.cur_bytes)))
+  }
+
+  /// Add the next entry, returning any chunks which are now complete along
+  /// with their total source size.
+  fn push(
+    &mut self,
+    entry: ZipEntrySpecification,
+    size: u64,
+  ) -> Vec<(Vec<ZipEntrySpecification>, u64)> {
+    if size >= self.max_bytes {
+      return self
+        .take_chunk()
+        .into_iter()
+        .chain(std::iter::once((vec![entry], size)))
+        .collect();
+    }
+    self.cur_chunk.push(entry);
+    self.cur_bytes += size;
+    if self.cur_bytes >= self.max_bytes || self.cur_chunk.len() >= self.max_entries {
+      return self.take_chunk().into_iter().collect();
+    }
+    Vec::new()
+  }
+
+  /// Return the final chunk, if any entries remain.
+  fn finish(mut self) -> Option<(Vec<ZipEntrySpecification>, u64)> { self.take_chunk() }
 }
 
 #[derive(Clone)]
 pub struct MedusaZip {
   pub input_files: Vec<FileSource>,
+  /// Symlinks to write as symlink entries, alongside [`Self::input_files`].
+  pub input_symlinks: Vec<SymlinkSource>,
   pub zip_options: ZipOutputOptions,
   pub modifications: EntryModifications,
   pub parallelism: Parallelism,
 }
 
-/* TODO: make these configurable!!! */
-const INTERMEDIATE_CHUNK_SIZE: usize = 10_000;
-const MAX_PARALLEL_INTERMEDIATES: usize = 20;
-const PER_INTERMEDIATE_FILE_IO_QUEUE_LENGTH: usize = 50;
-const INTERMEDIATE_OUTPUT_SPOOL_THRESHOLD: usize = 20_000;
+/// Resource limits for the parallel zip and merge pipelines.
+///
+/// The defaults are intended to work reasonably on most machines, but may be
+/// raised for many-core machines or lowered for those with little memory.
+#[derive(Clone, Debug)]
+pub struct PipelineTuning {
+  /// Maximum number of entries to write into each intermediate zip in
+  /// [`Parallelism::ParallelMerge`] mode.
+  pub intermediate_chunk_size: NonZeroUsize,
+  /// Total size in bytes of the source files to write into each intermediate
+  /// zip in [`Parallelism::ParallelMerge`] mode. Files at least this large
+  /// are given an intermediate zip of their own.
+  pub intermediate_chunk_bytes: NonZeroU64,
+  /// Number of intermediate zips which may be written concurrently.
+  pub max_parallel_intermediates: NonZeroUsize,
+  /// Number of input files which may be opened and compressed ahead of being
+  /// merged into their intermediate zip.
+  pub per_intermediate_file_io_queue_length: NonZeroUsize,
+  /// Size in bytes above which an intermediate zip is written to disk instead
+  /// of memory.
+  pub intermediate_output_spool_threshold: usize,
+  /// Size in bytes above which the compressed contents of a single file entry
+  /// are written to disk instead of memory.
+  pub per_file_spool_threshold: usize,
+  /// Number of source zips which may be opened ahead of being merged into the
+  /// output in [`MedusaMerge::merge`](crate::merge::MedusaMerge::merge).
+  pub parallel_merge_entries: NonZeroUsize,
+  /// Number of intermediate zips at which to stop merging pairs together in
+  /// [`Parallelism::ParallelTreeMerge`] mode. These are then copied into the
+  /// output one at a time.
+  pub tree_merge_final_width: NonZeroUsize,
+  /// Directory to create spooled temporary files in. Defaults to
+  /// [`std::env::temp_dir()`].
+  pub temp_dir: Option<PathBuf>,
+  /// Maximum number of files which may be held open at once while zipping.
+  /// This counts each source file while it is compressed, the buffer it is
+  /// compressed into, the temporary file behind each intermediate zip, and the
+  /// spill file of [`MedusaZip::zip_streaming()`].
+  /// [`MedusaCrawl::crawl_zip()`](crate::crawl::MedusaCrawl::crawl_zip) counts
+  /// its crawl against this limit too.
+  ///
+  /// Defaults to [`crate::default_max_open_files()`].
+  pub max_open_files: NonZeroUsize,
+}
+
+impl Default for PipelineTuning {
+  fn default() -> Self {
+    Self {
+      intermediate_chunk_size: NonZeroUsize::new(10_000).unwrap(),
+      intermediate_chunk_bytes: NonZeroU64::new(50_000_000).unwrap(),
+      max_parallel_intermediates: NonZeroUsize::new(20).unwrap(),
+      per_intermediate_file_io_queue_length: NonZeroUsize::new(50).unwrap(),
+      intermediate_output_spool_threshold: 20_000,
+      per_file_spool_threshold: 3_000,
+      parallel_merge_entries: NonZeroUsize::new(10).unwrap(),
+      tree_merge_final_width: NonZeroUsize::new(4).unwrap(),
+      temp_dir: None,
+      max_open_files: crate::default_max_open_files(),
+    }
+  }
+}
+
+impl PipelineTuning {
+  pub fn spooled_tempfile(&self, max_size: usize) -> tempfile::SpooledTempFile {
+    match &self.temp_dir {
+      Some(temp_dir) => tempfile::spooled_tempfile_in(max_size, temp_dir),
+      None => tempfile::spooled_tempfile(max_size),
+    }
+  }
+
+  pub fn tempfile(&self) -> io::Result<std::fs::File> {
+    match &self.temp_dir {
+      Some(temp_dir) => tempfile::tempfile_in(temp_dir),
+      None => tempfile::tempfile(),
+    }
+  }
+
+  /// Whether a stored file of `size` bytes is large enough that it should be
+  /// copied straight into the output (or into a file), instead of into a
+  /// buffer which would be spooled to disk anyway.
+  pub fn stored_copy_fast_path(&self, size: u64) -> bool {
+    size > self.per_file_spool_threshold as u64
+  }
+}
 
 pub struct ZipOptionsInitializers {
-  pub initializers: Vec<Box<dyn InitializeZipOptionsForSpecificFile+Send+Sync>>,
+  pub mtime_behavior: ModifiedTimeBehavior,
+  /// The compression applied to entries which match no rule of
+  /// [`Self::compression_policy`].
+  pub default_compression: CompressionStrategy,
+  pub compression_policy: CompressionPolicy,
 }
 
 impl ZipOptionsInitializers {
-  pub fn set_zip_options_for_file(
+  /// The header fields to write for this file.
+  pub fn entry_header_for_file(
     &self,
-    mut options: zip::write::FileOptions,
-    metadata: &std::fs::Metadata,
-  ) -> Result<zip::write::FileOptions, InitializeZipOptionsError> {
-    let Self { initializers } = self;
-    for initializer in initializers.iter() {
-      options = initializer.set_zip_options_for_file(options, metadata)?;
+    metadata: &EntryMetadata,
+  ) -> Result<EntryHeaderMetadata, InitializeZipOptionsError> {
+    let Self { mtime_behavior, .. } = self;
+    Ok(EntryHeaderMetadata::for_file(
+      mtime_behavior.zip_time_for_modified(metadata.mtime)?,
+      PreservePermsBehavior::permissions(metadata),
+    ))
+  }
+
+  /// The compression to apply to this file.
+  pub fn compression_strategy_for_file(&self, name: &EntryName, size: u64) -> CompressionStrategy {
+    let Self {
+      default_compression,
+      compression_policy,
+      ..
+    } = self;
+    compression_policy
+      .matching_strategy(name, size)
+      .unwrap_or(*default_compression)
+  }
+}
+
+/// The options and behavior shared by every entry written to one output zip.
+struct EntryPipeline {
+  /// The modified time of every directory and symlink entry.
+  last_modified: ZipDateTime,
+  options_initializers: Arc<ZipOptionsInitializers>,
+  entry_behavior: Arc<SingleEntryBehavior>,
+}
+
+impl EntryPipeline {
+  async fn initialize(
+    zip_options: ZipOutputOptions,
+    open_files: OpenFileLimit,
+  ) -> Result<Self, MedusaZipError> {
+    let ZipOutputOptions {
+      mtime_behavior,
+      compression_options,
+      compression_policy,
+      incompressible_detection,
+      parallel_deflate,
+      entry_cache,
+    } = zip_options;
+
+    let last_modified = mtime_behavior.static_zip_time();
+    let options_initializers = Arc::new(MedusaZip::options_initializers(
+      mtime_behavior,
+      compression_options,
+      compression_policy,
+    ));
+    if let Some(entry_cache) = &entry_cache {
+      let entry_cache = entry_cache.clone();
+      task::spawn_blocking(move || entry_cache.ensure_dir()).await??;
     }
-    Ok(options)
+    let entry_behavior = Arc::new(SingleEntryBehavior {
+      incompressible_detection,
+      encoding_selector: EntryEncodingSelector { parallel_deflate },
+      entry_cache,
+      open_files,
+    });
+
+    Ok(Self {
+      last_modified,
+      options_initializers,
+      entry_behavior,
+    })
   }
 }
 
+/// A file or symlink sent to [`MedusaZip::zip_streaming()`] as soon as it has
+/// been found.
+#[derive(Clone, Debug)]
+pub enum StreamedEntry {
+  /// A file, along with its metadata if the sender already knows it.
+  File(FileSource),
+  Symlink(SymlinkSource),
+}
+
 impl MedusaZip {
+  /// Get the size of each source file as soon as possible, so that chunks can
+  /// be balanced by the amount of data rather than the number of entries.
+  /// Files are only stat'd if their metadata wasn't recorded by the crawl, and
+  /// the result is kept for when they are opened.
+  fn stat_entry_sizes(
+    entries: Vec<ZipEntrySpecification>,
+    tuning: &PipelineTuning,
+  ) -> impl futures::stream::Stream<Item=Result<(ZipEntrySpecification, u64), MedusaInputReadError>>
+  {
+    futures::stream::iter(entries)
+      .map(|mut entry| async move {
+        let size = match &mut entry {
+          ZipEntrySpecification::Directory(_) => 0,
+          ZipEntrySpecification::Symlink(SymlinkSource { target, .. }) => target.len() as u64,
+          ZipEntrySpecification::File(file) => file.metadata_or_stat().await?.size,
+        };
+        Ok::<_, MedusaInputReadError>((entry, size))
+      })
+      .buffered(tuning.per_intermediate_file_io_queue_length.get())
+  }
+
   async fn zip_intermediate(
-    entries: &[ZipEntrySpecification],
-    zip_options: zip::write::FileOptions,
+    entries: Vec<ZipEntrySpecification>,
+    last_modified: ZipDateTime,
     options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
   ) -> Result<ZipArchive<tempfile::SpooledTempFile>, MedusaZipError> {
     /* (1) Create unnamed filesystem-backed temp file handle. */
-    let intermediate_output = task::spawn_blocking(|| {
-      let temp_file = tempfile::spooled_tempfile(INTERMEDIATE_OUTPUT_SPOOL_THRESHOLD);
-      let zip_wrapper = ZipWriter::new(temp_file);
-      Ok::<_, MedusaZipError>(zip_wrapper)
-    })
-    .await??;
+    let temp_file = tuning.spooled_tempfile(tuning.intermediate_output_spool_threshold);
+    let intermediate_output = ZipWriter::new(temp_file);
 
-    /* (2) Map to individual file handles and/or in-memory "immediate" zip files. */
+    /* (2) Map to individual file handles and/or in-memory "immediate" compressed
+     * entries. */
     let (handle_tx, handle_rx) =
-      mpsc::channel::<IntermediateSingleEntry>(PER_INTERMEDIATE_FILE_IO_QUEUE_LENGTH);
-    let entries = entries.to_vec();
+      mpsc::channel::<IntermediateSingleEntry>(tuning.per_intermediate_file_io_queue_length.get());
     let handle_stream_task = task::spawn(async move {
       for entry in entries.into_iter() {
-        let handle =
-          IntermediateSingleEntry::open_handle(entry, zip_options, options_initializers.clone())
-            .await?;
+        let handle = IntermediateSingleEntry::open_handle(
+          entry,
+          options_initializers.clone(),
+          entry_behavior.clone(),
+          tuning.clone(),
+        )
+        .await?;
         handle_tx.send(handle).await?;
       }
       Ok::<(), MedusaInputReadError>(())
     });
-    let mut handle_jobs = ReceiverStream::new(handle_rx);
 
     /* (3) Add file entries, in order. */
-    let intermediate_output = Arc::new(Mutex::new(intermediate_output));
-    while let Some(intermediate_entry) = handle_jobs.next().await {
-      let intermediate_output = intermediate_output.clone();
-      match intermediate_entry {
-        IntermediateSingleEntry::Directory(name) => {
-          task::spawn_blocking(move || {
-            let mut intermediate_output = intermediate_output.lock();
-            intermediate_output.add_directory(name.into_string(), zip_options)?;
-            Ok::<(), ZipError>(())
-          })
-          .await??;
-        },
-        IntermediateSingleEntry::File(tmp_merge_archive) => {
-          let tmp_merge_archive: ZipArchive<tempfile::SpooledTempFile> =
-            tmp_merge_archive.await??;
-          task::spawn_blocking(move || {
-            let mut intermediate_output = intermediate_output.lock();
-            intermediate_output.merge_archive(tmp_merge_archive)?;
-            Ok::<(), ZipError>(())
-          })
-          .await??;
-        },
-      }
-    }
+    let mut intermediate_output =
+      Self::zip_handles(handle_rx, intermediate_output, last_modified).await?;
     handle_stream_task.await??;
 
     /* (4) Convert the intermediate write archive into a file-backed read
      * archive. */
-    let temp_for_read = task::spawn_blocking(move || {
-      let mut zip_wrapper = Arc::into_inner(intermediate_output)
-        .expect("no other references should exist to intermediate_output")
-        .into_inner();
-      let temp_file = zip_wrapper.finish_into_readable()?;
-      Ok::<_, ZipError>(temp_file)
-    })
-    .await??;
+    let temp_for_read =
+      task::spawn_blocking(move || intermediate_output.finish_into_readable()).await??;
 
     Ok(temp_for_read)
   }
 
-  fn options_initializers(mtime_behavior: ModifiedTimeBehavior) -> ZipOptionsInitializers {
+  fn options_initializers(
+    mtime_behavior: ModifiedTimeBehavior,
+    compression_options: CompressionStrategy,
+    compression_policy: CompressionPolicy,
+  ) -> ZipOptionsInitializers {
     ZipOptionsInitializers {
-      initializers: vec![
-        Box::new(mtime_behavior),
-        Box::new(PreservePermsBehavior),
-        Box::new(SmallFileBehavior),
-        Box::new(LargeFileBehavior),
-      ],
+      mtime_behavior,
+      default_compression: compression_options,
+      compression_policy,
     }
   }
 
+  /// How many intermediates [`Self::launch_intermediates()`] may hold at once.
+  fn max_intermediates(tuning: &PipelineTuning, merge_width: usize) -> usize {
+    cmp::max(tuning.max_parallel_intermediates.get(), merge_width)
+  }
+
+  /// Split `entries` into chunks and start writing each chunk to an
+  /// intermediate zip in the background, as soon as the size of each entry is
+  /// known.
+  ///
+  /// Each intermediate holds a permit until it has been merged, either into
+  /// the output or into another intermediate. At least `merge_width`
+  /// intermediates may be held at once, since the caller needs that many to
+  /// merge them together.
+  ///
+  /// Returns a receiver for each intermediate zip in the same order as the
+  /// entries, along with the task which launches them.
+  fn launch_intermediates(
+    entries: Vec<ZipEntrySpecification>,
+    last_modified: ZipDateTime,
+    options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
+    merge_width: usize,
+  ) -> (
+    mpsc::UnboundedReceiver<IntermediateReceiver>,
+    task::JoinHandle<Result<(), MedusaInputReadError>>,
+  ) {
+    let (result_rx_tx, result_rx_rx) = mpsc::unbounded_channel::<IntermediateReceiver>();
+    let (chunk_tx, mut chunk_rx) = mpsc::unbounded_channel::<PendingChunk>();
+
+    /* (1) Split into however many subtasks (which may just be one) to do
+     * "normally", handing out the receiver for each in order. */
+    let chunk_tuning = tuning.clone();
+    let chunk_entries = async move {
+      let mut sized_entries = Box::pin(Self::stat_entry_sizes(entries, &chunk_tuning));
+      let mut chunker = EntryChunker::new(&chunk_tuning);
+      let mut index: usize = 0;
+      let mut send_chunk = |(entries, size): (Vec<ZipEntrySpecification>, u64)| {
+        let (result_tx, result_rx) = oneshot::channel::<Result<Intermediate, MedusaZipError>>();
+        /* If either receiver was dropped, the merge has already failed. */
+        let _ = result_rx_tx.send(result_rx);
+        let _ = chunk_tx.send(PendingChunk {
+          index,
+          entries,
+          size,
+          result_tx,
+        });
+        index += 1;
+      };
+      while let Some(sized_entry) = sized_entries.next().await {
+        let (entry, size) = sized_entry?;
+        chunker
+          .push(entry, size)
+          .into_iter()
+          .for_each(&mut send_chunk);
+      }
+      chunker.finish().into_iter().for_each(send_chunk);
+      Ok::<(), MedusaInputReadError>(())
+    };
+
+    /* (2) Start the largest chunks first, so that huge files begin compressing
+     * as early as possible. Ties are broken by position to keep scheduling
+     * deterministic. */
+    let max_intermediates = Self::max_intermediates(&tuning, merge_width);
+    let intermediate_permits = Arc::new(Semaphore::new(max_intermediates));
+    let launch_chunks = async move {
+      let mut window: Vec<PendingChunk> = Vec::new();
+      let mut received: usize = 0;
+      let mut launched: usize = 0;
+      loop {
+        let permit = intermediate_permits
+          .clone()
+          .acquire_owned()
+          .await
+          .expect("semaphore should never be closed");
+        /* Permits are released as intermediates are merged, which happens in
+         * order. So only a chunk within this many of the first unmerged one is
+         * sure to leave a permit for every chunk before it, without which the
+         * merge could never reach it. */
+        let bound = launched + 1 + intermediate_permits.available_permits();
+        while received < bound {
+          match chunk_rx.recv().await {
+            Some(chunk) => {
+              window.push(chunk);
+              received += 1;
+            },
+            None => break,
+          }
+        }
+        let Some(largest) =
+          (0..window.len()).max_by_key(|&i| (window[i].size, cmp::Reverse(window[i].index)))
+        else {
+          break;
+        };
+        let PendingChunk {
+          entries: entry_chunk,
+          result_tx,
+          ..
+        } = window.swap_remove(largest);
+        launched += 1;
+
+        let options_initializers = options_initializers.clone();
+        let entry_behavior = entry_behavior.clone();
+        let tuning = tuning.clone();
+        task::spawn(async move {
+          let intermediate = Self::zip_intermediate(
+            entry_chunk,
+            last_modified,
+            options_initializers,
+            entry_behavior,
+            tuning,
+          )
+          .await
+          .map(|archive| Intermediate {
+            archive,
+            permit: Some(permit),
+          });
+          /* If the receiver was dropped, the merge has already failed. */
+          let _ = result_tx.send(intermediate);
+        });
+      }
+    };
+
+    let intermediate_launch_task = task::spawn(async move {
+      let (chunked, ()) = futures::join!(chunk_entries, launch_chunks);
+      chunked
+    });
+
+    (result_rx_rx, intermediate_launch_task)
+  }
+
   async fn zip_parallel<Output>(
     entries: Vec<ZipEntrySpecification>,
     output_zip: OutputWrapper<ZipWriter<Output>>,
-    zip_options: zip::write::FileOptions,
-    mtime_behavior: ModifiedTimeBehavior,
+    last_modified: ZipDateTime,
+    options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
   ) -> Result<(), MedusaZipError>
   where
     Output: Write+Seek+Send+'static,
   {
-    let options_initializers = Arc::new(Self::options_initializers(mtime_behavior));
+    /* Count the temporary file behind each intermediate which may be held at
+     * once. */
+    let _temp_files = entry_behavior
+      .open_files
+      .reserve(Self::max_intermediates(&tuning, 1))
+      .await;
+    let (mut result_rxs, intermediate_launch_task) = Self::launch_intermediates(
+      entries,
+      last_modified,
+      options_initializers,
+      entry_behavior,
+      tuning,
+      1,
+    );
+
+    /* (3) Merge each intermediate into the output, in order. */
+    while let Some(result_rx) = result_rxs.recv().await {
+      let Intermediate { archive, permit } = result_rx.await??;
+      let output_zip = output_zip.clone();
+      task::spawn_blocking(move || {
+        output_zip.lease().merge_archive(archive)?;
+        mem::drop(permit);
+        Ok::<(), MedusaZipError>(())
+      })
+      .await??;
+    }
+    intermediate_launch_task.await??;
 
-    let (intermediate_tx, intermediate_rx) =
-      mpsc::channel::<ZipArchive<tempfile::SpooledTempFile>>(MAX_PARALLEL_INTERMEDIATES);
-    let mut handle_intermediates = ReceiverStream::new(intermediate_rx);
+    Ok(())
+  }
 
-    /* (1) Split into however many subtasks (which may just be one) to do
-     * "normally". */
-    let intermediate_stream_task = task::spawn(async move {
-      for entry_chunk in entries.chunks(INTERMEDIATE_CHUNK_SIZE) {
-        let archive =
-          Self::zip_intermediate(entry_chunk, zip_options, options_initializers.clone()).await?;
-        intermediate_tx.send(archive).await?;
+  async fn merge_intermediate_pair(
+    left: Intermediate,
+    right: Intermediate,
+    tuning: Arc<PipelineTuning>,
+  ) -> Result<Intermediate, MedusaZipError> {
+    let archive = task::spawn_blocking(move || {
+      let temp_file = tuning.spooled_tempfile(tuning.intermediate_output_spool_threshold);
+      let mut merged = ZipWriter::new(temp_file);
+      merged.merge_archive(left.archive)?;
+      merged.merge_archive(right.archive)?;
+      /* The permits held by both halves are released once they are merged. */
+      merged.finish_into_readable()
+    })
+    .await??;
+    Ok(Intermediate {
+      archive,
+      permit: None,
+    })
+  }
+
+  async fn zip_parallel_tree<Output>(
+    entries: Vec<ZipEntrySpecification>,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    last_modified: ZipDateTime,
+    options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
+  ) -> Result<(), MedusaZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    /* Count the temporary file behind each intermediate which may be held at
+     * once. */
+    let _temp_files = entry_behavior
+      .open_files
+      .reserve(Self::max_intermediates(&tuning, 2))
+      .await;
+    let (mut result_rxs, intermediate_launch_task) = Self::launch_intermediates(
+      entries,
+      last_modified,
+      options_initializers,
+      entry_behavior,
+      tuning.clone(),
+      2,
+    );
+
+    /* (3) Merge adjacent intermediates pairwise, level by level, until only a
+     * few remain. Every merge is spawned up front and begins as soon as both of
+     * its inputs are ready. Pairs are always formed in order, so the entry order
+     * is unchanged. */
+    let mut level: Vec<task::JoinHandle<Result<Intermediate, MedusaZipError>>> = Vec::new();
+    while let Some(result_rx) = result_rxs.recv().await {
+      level.push(task::spawn(async move { result_rx.await? }));
+    }
+    while level.len() > tuning.tree_merge_final_width.get() {
+      let mut next_level = Vec::with_capacity((level.len() + 1) / 2);
+      let mut nodes = level.into_iter();
+      while let Some(left) = nodes.next() {
+        match nodes.next() {
+          /* An odd node out is carried up to the next level as-is. */
+          None => next_level.push(left),
+          Some(right) => {
+            let tuning = tuning.clone();
+            next_level.push(task::spawn(async move {
+              let (left, right) = (left.await??, right.await??);
+              Self::merge_intermediate_pair(left, right, tuning).await
+            }));
+          },
+        }
       }
-      Ok::<(), MedusaZipError>(())
-    });
+      level = next_level;
+    }
 
-    /* (2) ??? */
-    while let Some(intermediate_archive) = handle_intermediates.next().await {
+    /* (4) Merge the remaining intermediates into the output, in order. */
+    for node in level.into_iter() {
+      let Intermediate { archive, permit } = node.await??;
       let output_zip = output_zip.clone();
       task::spawn_blocking(move || {
-        output_zip.lease().merge_archive(intermediate_archive)?;
+        output_zip.lease().merge_archive(archive)?;
+        mem::drop(permit);
         Ok::<(), MedusaZipError>(())
       })
       .await??;
     }
-    intermediate_stream_task.await??;
+    intermediate_launch_task.await??;
+
+    Ok(())
+  }
+
+  async fn zip_per_entry<Output>(
+    entries: Vec<ZipEntrySpecification>,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    last_modified: ZipDateTime,
+    options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
+  ) -> Result<(), MedusaZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    let ordered_behavior = entry_behavior.clone();
+    let ordered_tuning = tuning.clone();
+
+    /* (1) Compress each file into a buffer of raw compressed data, with as many
+     * in flight at once as the queue allows. */
+    let (handle_tx, handle_rx) =
+      mpsc::channel::<IntermediateSingleEntry>(tuning.per_intermediate_file_io_queue_length.get());
+    let handle_stream_task = task::spawn(async move {
+      for entry in entries.into_iter() {
+        let handle = IntermediateSingleEntry::open_handle(
+          entry,
+          options_initializers.clone(),
+          entry_behavior.clone(),
+          tuning.clone(),
+        )
+        .await?;
+        handle_tx.send(handle).await?;
+      }
+      Ok::<(), MedusaInputReadError>(())
+    });
+
+    /* (2) Append each entry to the output, in order. */
+    Self::write_handles_ordered(
+      handle_rx,
+      output_zip,
+      last_modified,
+      &ordered_behavior,
+      &ordered_tuning,
+    )
+    .await?;
+    handle_stream_task.await??;
 
     Ok(())
   }
 
+  /// Append each entry received from `handle_rx` to a temporary archive in
+  /// order, then merge that archive into `output_zip`.
+  ///
+  /// [`ZipWriter`] can only copy compressed data out of another archive, so
+  /// every entry is written to one temporary file without going through it,
+  /// and the whole file is then copied into the output in a single pass.
+  async fn write_handles_ordered<Output>(
+    handle_rx: mpsc::Receiver<IntermediateSingleEntry>,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    last_modified: ZipDateTime,
+    entry_behavior: &SingleEntryBehavior,
+    tuning: &PipelineTuning,
+  ) -> Result<(), MedusaZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    let temp_permit = entry_behavior.open_files.reserve(1).await;
+    let writer = OrderedEntryWriter::new(tuning.tempfile()?);
+    let writer = Self::write_handles(handle_rx, writer, last_modified).await?;
+    task::spawn_blocking(move || {
+      let temp_file = writer.finish()?;
+      let temp_archive = ZipArchive::new(temp_file)?;
+      output_zip.lease().merge_archive(temp_archive)?;
+      Ok::<(), ZipError>(())
+    })
+    .await??;
+    /* The temporary file has been closed. */
+    drop(temp_permit);
+    Ok(())
+  }
+
+  /// Append each entry received from `handle_rx` to `writer`, in order.
+  ///
+  /// If the output is a file, each [`IntermediateSingleEntry::StoredFile`] is
+  /// given space in the output and copied into it in the background while the
+  /// following entries are written. Otherwise it is copied in order.
+  async fn write_handles<W>(
+    handle_rx: mpsc::Receiver<IntermediateSingleEntry>,
+    mut writer: OrderedEntryWriter<W>,
+    last_modified: ZipDateTime,
+  ) -> Result<OrderedEntryWriter<W>, MedusaZipError>
+  where
+    W: Write+Seek+Send+'static,
+  {
+    #[cfg(unix)]
+    let positional_output = writer.positional_output()?.map(Arc::new);
+    #[cfg(unix)]
+    let mut stored_copies: Vec<task::JoinHandle<Result<(usize, u32), MedusaInputReadError>>> =
+      Vec::new();
+
+    let mut handle_jobs = ReceiverStream::new(handle_rx);
+    while let Some(entry) = handle_jobs.next().await {
+      writer = match entry {
+        IntermediateSingleEntry::Directory(name) => {
+          task::spawn_blocking(move || {
+            writer.append_directory(&name, last_modified)?;
+            Ok::<_, ZipError>(writer)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::Symlink(SymlinkSource { name, target }) => {
+          task::spawn_blocking(move || {
+            writer.append_symlink(&name, &target, last_modified)?;
+            Ok::<_, ZipError>(writer)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::File(buffered_entry) => {
+          let BufferedEntry {
+            entry: mut compressed_entry,
+            permit,
+          } = buffered_entry.await??;
+          task::spawn_blocking(move || {
+            writer.append_compressed(&mut compressed_entry)?;
+            /* The buffer has been closed. */
+            drop(compressed_entry);
+            drop(permit);
+            Ok::<_, ZipError>(writer)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::Spilled(spilled_entry) => {
+          let (record, mut data) = spilled_entry.await??;
+          task::spawn_blocking(move || {
+            writer.append_raw(record, &mut data)?;
+            Ok::<_, ZipError>(writer)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::StoredFile(stored_file) => {
+          #[cfg(unix)]
+          if let Some(positional_output) = positional_output.clone() {
+            let StoredFile {
+              record,
+              source,
+              mut handle,
+              permit,
+            } = stored_file;
+            let size = record.info.compressed_size;
+            let (returned_writer, index, data_start) = task::spawn_blocking(move || {
+              let (index, data_start) = writer.reserve_stored(record)?;
+              Ok::<_, io::Error>((writer, index, data_start))
+            })
+            .await??;
+            writer = returned_writer;
+            stored_copies.push(task::spawn_blocking(move || {
+              let crc32 = positional_output
+                .copy_stored_file_at(&mut handle, data_start, size)
+                .map_err(|e| MedusaInputReadError::SourceNotFound(source, e))?;
+              /* The source file has been closed. */
+              drop(handle);
+              drop(permit);
+              Ok((index, crc32))
+            }));
+            continue;
+          }
+          let StoredFile {
+            record,
+            mut handle,
+            permit,
+            ..
+          } = stored_file;
+          task::spawn_blocking(move || {
+            writer.append_from(record, &mut handle, EntryEncoding::Stored)?;
+            /* The source file has been closed. */
+            drop(handle);
+            drop(permit);
+            Ok::<_, ZipError>(writer)
+          })
+          .await??
+        },
+      };
+    }
+
+    /* Fill in the checksum of each file copied in the background. */
+    #[cfg(unix)]
+    {
+      let mut checksums: Vec<(usize, u32)> = Vec::with_capacity(stored_copies.len());
+      for stored_copy in stored_copies.into_iter() {
+        checksums.push(stored_copy.await??);
+      }
+      writer = task::spawn_blocking(move || {
+        for (index, crc32) in checksums.into_iter() {
+          writer.set_crc32(index, crc32)?;
+        }
+        Ok::<_, io::Error>(writer)
+      })
+      .await??;
+    }
+
+    Ok(writer)
+  }
+
+  /// Add each entry received from `handle_rx` to `output_zip`, in order.
+  ///
+  /// Each compressed entry is copied straight out of its buffer, and each
+  /// [`IntermediateSingleEntry::StoredFile`] is copied from its source file.
+  async fn zip_handles<W>(
+    handle_rx: mpsc::Receiver<IntermediateSingleEntry>,
+    mut output_zip: ZipWriter<W>,
+    last_modified: ZipDateTime,
+  ) -> Result<ZipWriter<W>, MedusaZipError>
+  where
+    W: Write+Seek+Send+'static,
+  {
+    let options = ZipLibraryFileOptions::default().last_modified_time(last_modified);
+    let mut handle_jobs = ReceiverStream::new(handle_rx);
+    while let Some(entry) = handle_jobs.next().await {
+      output_zip = match entry {
+        IntermediateSingleEntry::Directory(name) => {
+          task::spawn_blocking(move || {
+            output_zip.add_directory(name.into_string(), options)?;
+            Ok::<_, ZipError>(output_zip)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::Symlink(SymlinkSource { name, target }) => {
+          task::spawn_blocking(move || {
+            output_zip.add_symlink(name.into_string(), target, options)?;
+            Ok::<_, ZipError>(output_zip)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::File(buffered_entry) => {
+          let BufferedEntry {
+            entry: CompressedEntry { record, data },
+            permit,
+          } = buffered_entry.await??;
+          task::spawn_blocking(move || {
+            SingleEntryArchive::new(&record, data)?.copy_into(&mut output_zip)?;
+            /* The buffer has been closed. */
+            drop(permit);
+            Ok::<_, ZipError>(output_zip)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::Spilled(spilled_entry) => {
+          let (record, data) = spilled_entry.await??;
+          task::spawn_blocking(move || {
+            SingleEntryArchive::new(&record, data)?.copy_into(&mut output_zip)?;
+            Ok::<_, ZipError>(output_zip)
+          })
+          .await??
+        },
+        IntermediateSingleEntry::StoredFile(StoredFile {
+          record,
+          mut handle,
+          permit,
+          ..
+        }) => {
+          task::spawn_blocking(move || {
+            let options = zip_library_options(&record.header, record.large_file)
+              .compression_method(ZipCompressionMethod::Stored);
+            output_zip.start_file(record.name, options)?;
+            std::io::copy(&mut handle, &mut output_zip)?;
+            /* The source file has been closed. */
+            drop(handle);
+            drop(permit);
+            Ok::<_, ZipError>(output_zip)
+          })
+          .await??
+        },
+      };
+    }
+
+    Ok(output_zip)
+  }
+
   async fn zip_synchronous<Output>(
     entries: Vec<ZipEntrySpecification>,
     output_zip: OutputWrapper<ZipWriter<Output>>,
-    zip_options: zip::write::FileOptions,
-    mtime_behavior: ModifiedTimeBehavior,
+    last_modified: ZipDateTime,
+    options_initializers: Arc<ZipOptionsInitializers>,
+    entry_behavior: Arc<SingleEntryBehavior>,
+    tuning: Arc<PipelineTuning>,
   ) -> Result<(), MedusaZipError>
   where
     Output: Write+Seek+Send+'static,
   {
-    let options_initializers = Self::options_initializers(mtime_behavior);
+    let options = ZipLibraryFileOptions::default().last_modified_time(last_modified);
     for entry in entries.into_iter() {
       let output_zip = output_zip.clone();
       match entry {
         ZipEntrySpecification::Directory(name) => {
           task::spawn_blocking(move || {
             let mut output_zip = output_zip.lease();
-            output_zip.add_directory(name.into_string(), zip_options)?;
+            output_zip.add_directory(name.into_string(), options)?;
             Ok::<(), ZipError>(())
           })
           .await??;
         },
-        ZipEntrySpecification::File(FileSource { name, source }) => {
+        ZipEntrySpecification::Symlink(SymlinkSource { name, target }) => {
+          task::spawn_blocking(move || {
+            let mut output_zip = output_zip.lease();
+            output_zip.add_symlink(name.into_string(), target, options)?;
+            Ok::<(), ZipError>(())
+          })
+          .await??;
+        },
+        ZipEntrySpecification::File(mut file) => {
+          let metadata = file.metadata_or_stat().await?;
+          let FileSource { name, source, .. } = file;
           let f = fs::OpenOptions::new()
             .read(true)
             .open(&source)
             .await
-            .map_err(|e| MedusaInputReadError::SourceNotFound(source, e))?;
-          let metadata = f.metadata().await?;
-          let zip_options =
-            options_initializers.set_zip_options_for_file(zip_options, &metadata)?;
+            .map_err(|e| MedusaInputReadError::SourceNotFound(source.clone(), e))?;
+          let size = metadata.size;
+          let header = options_initializers.entry_header_for_file(&metadata)?;
+          let strategy = options_initializers.compression_strategy_for_file(&name, size);
+          let encoding = entry_behavior
+            .encoding_selector
+            .encoding_for_entry(strategy, size);
           let mut f = f.into_std().await;
+          let entry_behavior = entry_behavior.clone();
+          let tuning = tuning.clone();
           task::spawn_blocking(move || {
-            let mut output_zip = output_zip.lease();
-            output_zip.start_file(name.into_string(), zip_options)?;
-            std::io::copy(&mut f, &mut *output_zip)?;
+            if entry_behavior.needs_buffer() || matches!(encoding, EntryEncoding::DeflatedBlocks(_))
+            {
+              /* We need to know the compressed size before committing to an entry (or
+               * to compress it ourselves, or to cache it), so compress it into a buffer
+               * first. */
+              let CompressedEntry { record, data } =
+                IntermediateSingleEntry::write_single_entry_with_behavior(
+                  &name,
+                  &source,
+                  &mut f,
+                  size,
+                  header,
+                  strategy,
+                  encoding,
+                  &entry_behavior,
+                  &tuning,
+                )?;
+              SingleEntryArchive::new(&record, data)?.copy_into(&mut output_zip.lease())?;
+            } else {
+              let options = strategy
+                .set_zip_options_static(zip_library_options(&header, size >= ZIP64_BYTES_THR));
+              let mut output_zip = output_zip.lease();
+              output_zip.start_file(name.into_string(), options)?;
+              std::io::copy(&mut f, &mut *output_zip)?;
+            }
             Ok::<(), MedusaZipError>(())
           })
           .await??;
@@ -794,41 +2301,509 @@ impl MedusaZip {
   pub async fn zip<Output>(
     self,
     output_zip: OutputWrapper<ZipWriter<Output>>,
+    tuning: PipelineTuning,
   ) -> Result<OutputWrapper<ZipWriter<Output>>, MedusaZipError>
   where
     Output: Write+Seek+Send+'static,
   {
     let Self {
       input_files,
-      zip_options: ZipOutputOptions {
-        mtime_behavior,
-        compression_options,
-      },
+      input_symlinks,
+      zip_options,
       modifications,
       parallelism,
     } = self;
 
     let EntrySpecificationList(entries) = task::spawn_blocking(move || {
-      EntrySpecificationList::from_file_specs(input_files, modifications)
+      EntrySpecificationList::from_file_specs(input_files, input_symlinks, modifications)
     })
     .await??;
 
-    let static_options_initializers: Vec<Box<dyn DefaultInitializeZipOptions+Send+Sync>> =
-      vec![Box::new(mtime_behavior), Box::new(compression_options)];
-    let mut zip_options = ZipLibraryFileOptions::default();
-    for initializer in static_options_initializers.into_iter() {
-      zip_options = initializer.set_zip_options_static(zip_options);
-    }
-
-    match parallelism {
+    let EntryPipeline {
+      last_modified,
+      options_initializers,
+      entry_behavior,
+    } = EntryPipeline::initialize(zip_options, OpenFileLimit::new(tuning.max_open_files)).await?;
+    let entry_cache = entry_behavior.entry_cache.clone();
+    let tuning = Arc::new(tuning);
+    let zipped = match parallelism {
       Parallelism::Synchronous => {
-        Self::zip_synchronous(entries, output_zip.clone(), zip_options, mtime_behavior).await?;
+        Self::zip_synchronous(
+          entries,
+          output_zip.clone(),
+          last_modified,
+          options_initializers,
+          entry_behavior,
+          tuning,
+        )
+        .await
       },
       Parallelism::ParallelMerge => {
-        Self::zip_parallel(entries, output_zip.clone(), zip_options, mtime_behavior).await?;
+        Self::zip_parallel(
+          entries,
+          output_zip.clone(),
+          last_modified,
+          options_initializers,
+          entry_behavior,
+          tuning,
+        )
+        .await
       },
+      Parallelism::ParallelTreeMerge => {
+        Self::zip_parallel_tree(
+          entries,
+          output_zip.clone(),
+          last_modified,
+          options_initializers,
+          entry_behavior,
+          tuning,
+        )
+        .await
+      },
+      Parallelism::PerEntry => {
+        Self::zip_per_entry(
+          entries,
+          output_zip.clone(),
+          last_modified,
+          options_initializers,
+          entry_behavior,
+          tuning,
+        )
+        .await
+      },
+    };
+    Self::evict_cache(entry_cache, zipped).await?;
+
+    Ok(output_zip)
+  }
+
+  /// Trim the cache back down to size once all of its entries have been read,
+  /// even if the zip which read them has failed. An error from the zip takes
+  /// precedence over one from the cache.
+  async fn evict_cache<T>(
+    entry_cache: Option<EntryCache>,
+    zipped: Result<T, MedusaZipError>,
+  ) -> Result<T, MedusaZipError> {
+    let Some(entry_cache) = entry_cache else {
+      return zipped;
+    };
+    let evicted = task::spawn_blocking(move || entry_cache.evict()).await;
+    let ret = zipped?;
+    evicted??;
+    Ok(ret)
+  }
+
+  /// Move a compressed entry into `spill_file` as soon as it is ready.
+  fn spill_entry(
+    buffered_entry: oneshot::Receiver<Result<BufferedEntry, MedusaInputReadError>>,
+    spill_file: Arc<SpillFile>,
+  ) -> task::JoinHandle<Result<(EntryRecord, SpilledData), MedusaZipError>> {
+    task::spawn(async move {
+      let BufferedEntry {
+        entry: CompressedEntry { record, mut data },
+        permit,
+      } = buffered_entry.await??;
+      let data = task::spawn_blocking(move || spill_file.append(&mut data)).await??;
+      /* The buffer has been closed. */
+      drop(permit);
+      Ok((record, data))
+    })
+  }
+
+  /// Like [`Self::zip()`], but receive the files and symlinks to write from
+  /// `entries`, and start compressing each file as soon as it arrives.
+  ///
+  /// Entries are only sorted and given their parent directory entries once
+  /// `entries` is closed, so the output is identical to that of
+  /// [`Self::zip()`] over the same inputs. There is no [`Parallelism`]
+  /// argument, as every file is compressed independently, as with
+  /// [`Parallelism::PerEntry`].
+  pub async fn zip_streaming<Output>(
+    entries: mpsc::UnboundedReceiver<StreamedEntry>,
+    zip_options: ZipOutputOptions,
+    modifications: EntryModifications,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    tuning: PipelineTuning,
+  ) -> Result<OutputWrapper<ZipWriter<Output>>, MedusaZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    let open_files = OpenFileLimit::new(tuning.max_open_files);
+    Self::zip_streaming_within(
+      entries,
+      zip_options,
+      modifications,
+      output_zip,
+      tuning,
+      open_files,
+    )
+    .await
+  }
+
+  /// Like [`Self::zip_streaming()`], but count open files against
+  /// `open_files`, which may be shared with the crawl sending `entries`.
+  pub(crate) async fn zip_streaming_within<Output>(
+    mut entries: mpsc::UnboundedReceiver<StreamedEntry>,
+    zip_options: ZipOutputOptions,
+    modifications: EntryModifications,
+    output_zip: OutputWrapper<ZipWriter<Output>>,
+    tuning: PipelineTuning,
+    open_files: OpenFileLimit,
+  ) -> Result<OutputWrapper<ZipWriter<Output>>, MedusaZipError>
+  where
+    Output: Write+Seek+Send+'static,
+  {
+    /* The prefix is needed to name each file before it is compressed. */
+    let (cached_prefix, _) = EntrySpecificationList::prefix_directories(modifications.clone())?;
+
+    let EntryPipeline {
+      last_modified,
+      options_initializers,
+      entry_behavior,
+    } = EntryPipeline::initialize(zip_options, open_files).await?;
+    let entry_cache = entry_behavior.entry_cache.clone();
+    let tuning = Arc::new(tuning);
+
+    let zipped = async {
+      /* (1) Start compressing each file as it is received. Each file is moved into
+       * a single spill file on disk as soon as it has been compressed, so that
+       * only its header is kept in memory (and no file is held open for it) until
+       * it is reached in the output. Large files which are written uncompressed
+       * are copied straight into the output once they are reached instead. */
+      let spill_permit = entry_behavior.open_files.reserve(1).await;
+      let spill_file = Arc::new(SpillFile::new(tuning.tempfile()?));
+      let mut files: Vec<FileSource> = Vec::new();
+      let mut symlinks: Vec<SymlinkSource> = Vec::new();
+      let mut started: HashMap<EntryName, IntermediateSingleEntry> = HashMap::new();
+      while let Some(entry) = entries.recv().await {
+        let mut file = match entry {
+          StreamedEntry::Symlink(symlink) => {
+            symlinks.push(symlink);
+            continue;
+          },
+          StreamedEntry::File(file) => file,
+        };
+        let mut name = match modifications.rewrite_name(&file.name) {
+          Ok(rewritten) => rewritten.unwrap_or_else(|| file.name.clone()),
+          Err(_) => {
+            /* This is reported once the entries are sorted. */
+            files.push(file);
+            continue;
+          },
+        };
+        name.add_prefix(&cached_prefix);
+        if started.contains_key(&name) {
+          /* This is reported as a duplicate once the entries are sorted. */
+          files.push(file);
+          continue;
+        }
+        let size = file.metadata_or_stat().await?.size;
+        let strategy = options_initializers.compression_strategy_for_file(&name, size);
+        let encoding = entry_behavior
+          .encoding_selector
+          .encoding_for_entry(strategy, size);
+        if entry_behavior.copies_directly(&encoding, size, &tuning) {
+          files.push(file);
+          continue;
+        }
+        let handle = IntermediateSingleEntry::open_handle(
+          ZipEntrySpecification::File(FileSource {
+            name: name.clone(),
+            ..file.clone()
+          }),
+          options_initializers.clone(),
+          entry_behavior.clone(),
+          tuning.clone(),
+        )
+        .await?;
+        let handle = match handle {
+          IntermediateSingleEntry::File(compressed_entry) => IntermediateSingleEntry::Spilled(
+            Self::spill_entry(compressed_entry, spill_file.clone()),
+          ),
+          handle => handle,
+        };
+        started.insert(name, handle);
+        files.push(file);
+      }
+
+      /* (2) Now that every entry is known, sort them and introduce directories
+       * exactly as the non-streaming pipeline does. */
+      let EntrySpecificationList(specs) = task::spawn_blocking(move || {
+        EntrySpecificationList::from_file_specs(files, symlinks, modifications)
+      })
+      .await??;
+
+      /* (3) Pick up each entry already being compressed, and open the rest. */
+      let ordered_behavior = entry_behavior.clone();
+      let ordered_tuning = tuning.clone();
+      let (handle_tx, handle_rx) = mpsc::channel::<IntermediateSingleEntry>(
+        tuning.per_intermediate_file_io_queue_length.get(),
+      );
+      let handle_stream_task = task::spawn(async move {
+        for spec in specs.into_iter() {
+          let handle = match &spec {
+            ZipEntrySpecification::File(FileSource { name, .. }) => started.remove(name),
+            _ => None,
+          };
+          match handle {
+            Some(handle) => handle_tx.send(handle).await?,
+            None => {
+              let handle = IntermediateSingleEntry::open_handle(
+                spec,
+                options_initializers.clone(),
+                entry_behavior.clone(),
+                tuning.clone(),
+              )
+              .await?;
+              handle_tx.send(handle).await?;
+            },
+          }
+        }
+        Ok::<(), MedusaInputReadError>(())
+      });
+
+      /* (4) Append each entry to the output, in order. */
+      Self::write_handles_ordered(
+        handle_rx,
+        output_zip.clone(),
+        last_modified,
+        &ordered_behavior,
+        &ordered_tuning,
+      )
+      .await?;
+      handle_stream_task.await??;
+      /* The spill file has been closed. */
+      drop(spill_file);
+      drop(spill_permit);
+      Ok::<(), MedusaZipError>(())
     }
+    .await;
+    Self::evict_cache(entry_cache, zipped).await?;
 
     Ok(output_zip)
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  use std::io::{Read, SeekFrom};
+
+  /// Write files of every kind the pipeline treats differently into `root`.
+  fn write_sources(root: &Path) -> Vec<FileSource> {
+    let compressible: Vec<u8> = b"medusa ".iter().copied().cycle().take(50_000).collect();
+    let noisy: Vec<u8> = (0..20_000_u32)
+      .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+      .collect();
+    let sources: [(&str, &[u8]); 5] = [
+      ("z.txt", b"small"),
+      ("a/compressible.txt", &compressible),
+      ("a/b/noisy.txt", &noisy),
+      ("a/b/stored.bin", &noisy),
+      ("c/empty.txt", b""),
+    ];
+    sources
+      .into_iter()
+      .map(|(name, contents)| {
+        let source = root.join(name);
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::write(&source, contents).unwrap();
+        FileSource {
+          name: EntryName::validate(name.to_string()).unwrap(),
+          source,
+          metadata: None,
+        }
+      })
+      .collect()
+  }
+
+  fn symlinks() -> Vec<SymlinkSource> {
+    vec![SymlinkSource {
+      name: EntryName::validate("a/link".to_string()).unwrap(),
+      target: "b/noisy.txt".to_string(),
+    }]
+  }
+
+  fn zip_options() -> ZipOutputOptions {
+    let mut compression_policy = CompressionPolicy::default();
+    compression_policy.rules.push(CompressionRule {
+      name_pattern: Some(EntryNamePattern::glob("*.bin").unwrap()),
+      min_size: None,
+      max_size: None,
+      strategy: CompressionStrategy::Stored,
+    });
+    ZipOutputOptions {
+      compression_policy,
+      incompressible_detection: Some(IncompressibleDetection::new(0.1).unwrap()),
+      ..Default::default()
+    }
+  }
+
+  fn modifications() -> EntryModifications {
+    EntryModifications {
+      own_prefix: Some("out".to_string()),
+      ..Default::default()
+    }
+  }
+
+  fn tuning() -> PipelineTuning {
+    /* Spool anything but the smallest files to disk, and copy the stored file
+     * straight into the output. Only allow a single intermediate at a time
+     * beyond what merging requires. */
+    PipelineTuning {
+      per_file_spool_threshold: 1_000,
+      intermediate_chunk_size: NonZeroUsize::new(2).unwrap(),
+      max_parallel_intermediates: NonZeroUsize::new(1).unwrap(),
+      ..Default::default()
+    }
+  }
+
+  fn output() -> OutputWrapper<ZipWriter<std::fs::File>> {
+    OutputWrapper::wrap(ZipWriter::new(tempfile::tempfile().unwrap()))
+  }
+
+  fn into_bytes(output_zip: OutputWrapper<ZipWriter<std::fs::File>>) -> Vec<u8> {
+    let mut output = output_zip.reclaim().finish().unwrap();
+    output.seek(SeekFrom::Start(0)).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    output.read_to_end(&mut bytes).unwrap();
+    bytes
+  }
+
+  async fn zip_with(files: Vec<FileSource>, parallelism: Parallelism) -> Vec<u8> {
+    let zip = MedusaZip {
+      input_files: files,
+      input_symlinks: symlinks(),
+      zip_options: zip_options(),
+      modifications: modifications(),
+      parallelism,
+    };
+    into_bytes(zip.zip(output(), tuning()).await.unwrap())
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn streaming_matches_every_parallelism() {
+    let root = tempfile::tempdir().unwrap();
+    let files = write_sources(root.path());
+
+    let expected = zip_with(files.clone(), Parallelism::ParallelMerge).await;
+    for parallelism in [
+      Parallelism::Synchronous,
+      Parallelism::ParallelTreeMerge,
+      Parallelism::PerEntry,
+    ] {
+      assert!(
+        zip_with(files.clone(), parallelism).await == expected,
+        "{parallelism} output differs"
+      );
+    }
+
+    /* Entries may arrive in any order, and with or without their metadata. */
+    let (entries_tx, entries_rx) = mpsc::unbounded_channel::<StreamedEntry>();
+    for (i, mut file) in files.into_iter().rev().enumerate() {
+      if i % 2 == 0 {
+        let metadata = file.source.metadata().unwrap();
+        file.metadata = Some(EntryMetadata::from_metadata(EntryKind::File, &metadata).unwrap());
+      }
+      entries_tx.send(StreamedEntry::File(file)).unwrap();
+    }
+    for symlink in symlinks().into_iter() {
+      entries_tx.send(StreamedEntry::Symlink(symlink)).unwrap();
+    }
+    mem::drop(entries_tx);
+    let streamed = MedusaZip::zip_streaming(
+      entries_rx,
+      zip_options(),
+      modifications(),
+      output(),
+      tuning(),
+    )
+    .await
+    .unwrap();
+    assert!(into_bytes(streamed) == expected, "streaming output differs");
+
+    let mut archive = ZipArchive::new(std::io::Cursor::new(expected)).unwrap();
+    let stored = archive.by_name("out/a/b/stored.bin").unwrap();
+    assert_eq!(stored.compression(), ZipCompressionMethod::Stored);
+    mem::drop(stored);
+    let compressible = archive.by_name("out/a/compressible.txt").unwrap();
+    assert_eq!(compressible.compression(), ZipCompressionMethod::Deflated);
+  }
+
+  #[test]
+  fn compression_level_ranges() {
+    assert!(matches!(
+      CompressionStrategy::from_method_and_level(CompressionMethod::Bzip2, Some(0)),
+      Err(ParseCompressionOptionsError::InvalidCompressionLevel(
+        CompressionMethod::Bzip2,
+        0,
+        _
+      ))
+    ));
+    assert!(matches!(
+      CompressionStrategy::from_method_and_level(CompressionMethod::Bzip2, Some(1)),
+      Ok(CompressionStrategy::Bzip2(Some(1)))
+    ));
+    assert!(matches!(
+      CompressionStrategy::from_method_and_level(CompressionMethod::Bzip2, Some(9)),
+      Ok(CompressionStrategy::Bzip2(Some(9)))
+    ));
+    assert!(
+      CompressionStrategy::from_method_and_level(CompressionMethod::Bzip2, Some(10)).is_err()
+    );
+    assert!(matches!(
+      CompressionStrategy::from_method_and_level(CompressionMethod::Deflated, Some(0)),
+      Ok(CompressionStrategy::Deflated(Some(0)))
+    ));
+  }
+
+  #[test]
+  fn entry_cache_recipe_is_stable() {
+    let header = EntryHeaderMetadata::for_file(
+      ZipDateTime::from_date_and_time(2023, 4, 5, 6, 7, 8).unwrap(),
+      Some(0o755),
+    );
+    let recipe = IntermediateSingleEntry::entry_cache_recipe(
+      CompressionStrategy::Deflated(None),
+      EntryEncoding::DeflatedBlocks(DeflateBlocks {
+        level: Compression::new(6),
+        block_size: NonZeroUsize::new(1024).unwrap(),
+      }),
+      header,
+      Some(IncompressibleDetection::new(0.5).unwrap()),
+    );
+    assert_eq!(
+      recipe,
+      "strategy=deflated:default encoding=deflated-blocks:6:1024 date=22149 time=12516 \
+       mode=100755 min-savings=3fe0000000000000"
+    );
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn entry_cache_evicted_after_failure() {
+    let root = tempfile::tempdir().unwrap();
+    let cache_dir = root.path().join("cache");
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    std::fs::write(cache_dir.join("stale.zip"), b"stale").unwrap();
+
+    let mut files = write_sources(root.path());
+    files.push(FileSource {
+      name: EntryName::validate("missing.txt".to_string()).unwrap(),
+      source: root.path().join("missing.txt"),
+      metadata: None,
+    });
+    let zip = MedusaZip {
+      input_files: files,
+      input_symlinks: Vec::new(),
+      zip_options: ZipOutputOptions {
+        entry_cache: Some(EntryCache::new(cache_dir.clone(), 0)),
+        ..zip_options()
+      },
+      modifications: modifications(),
+      parallelism: Parallelism::ParallelMerge,
+    };
+    assert!(zip.zip(output(), tuning()).await.is_err());
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 0);
+  }
+}
@@ -20,3 +20,12 @@ class FileSource:
   def name(self) -> EntryName: ...
   @property
   def source(self) -> Path: ...
+
+
+class SymlinkSource:
+  def __init__(self, name: EntryName, target: str) -> None: ...
+
+  @property
+  def name(self) -> EntryName: ...
+  @property
+  def target(self) -> str: ...
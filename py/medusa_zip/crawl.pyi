@@ -10,11 +10,58 @@
 from pathlib import Path
 from typing import Iterable, Optional, Union
 
-from .zip import EntryModifications, MedusaZip, Parallelism, ZipOutputOptions
+from .destination import ZipFileWriter
+from .zip import (
+  EntryModifications, MedusaZip, Parallelism, PipelineTuning, ZipOutputOptions,
+)
+
+
+class EntryKind:
+  File: 'EntryKind'
+  Symlink: 'EntryKind'
+
+  def __int__(self) -> int: ...
+
+
+class EntryMetadata:
+  def __init__(self, *, kind: EntryKind, size: int, mode: int, mtime_ns: int) -> None:
+    ...
+
+  @property
+  def kind(self) -> EntryKind: ...
+  @property
+  def size(self) -> int: ...
+  @property
+  def mode(self) -> int: ...
+  @property
+  def mtime_ns(self) -> int: ...
+
+
+class DigestAlgorithm:
+  Sha256: 'DigestAlgorithm'
+  Blake3: 'DigestAlgorithm'
+
+  def __int__(self) -> int: ...
+
+
+class ContentDigest:
+  def __init__(self, digest: str) -> None: ...
+
+  @property
+  def algorithm(self) -> DigestAlgorithm: ...
+  @property
+  def hex(self) -> str: ...
 
 
 class ResolvedPath:
-  def __init__(self, *, unresolved_path: Union[str, Path], resolved_path: Union[str, Path]) -> None:
+  def __init__(
+    self,
+    *,
+    unresolved_path: Union[str, Path],
+    resolved_path: Union[str, Path],
+    metadata: Optional[EntryMetadata] = None,
+    digest: Optional[ContentDigest] = None,
+  ) -> None:
     ...
 
   # FIXME: these also appear to be strings at runtime!
@@ -22,14 +69,42 @@ class ResolvedPath:
   def unresolved_path(self) -> Path: ...
   @property
   def resolved_path(self) -> Path: ...
+  @property
+  def metadata(self) -> Optional[EntryMetadata]: ...
+  @property
+  def digest(self) -> Optional[ContentDigest]: ...
+
+
+class ResolvedSymlink:
+  def __init__(
+    self,
+    *,
+    unresolved_path: Union[str, Path],
+    target: Union[str, Path],
+    metadata: Optional[EntryMetadata] = None,
+  ) -> None:
+    ...
+
+  @property
+  def unresolved_path(self) -> Path: ...
+  @property
+  def target(self) -> Path: ...
+  @property
+  def metadata(self) -> Optional[EntryMetadata]: ...
 
 
 class CrawlResult:
-  def __init__(self, real_file_paths: Iterable[ResolvedPath]) -> None:
+  def __init__(
+    self,
+    real_file_paths: Iterable[ResolvedPath],
+    symlinks: Optional[Iterable[ResolvedSymlink]] = None,
+  ) -> None:
     ...
 
   @property
   def real_file_paths(self) -> Iterable[ResolvedPath]: ...
+  @property
+  def symlinks(self) -> Iterable[ResolvedSymlink]: ...
 
   def medusa_zip(
     self,
@@ -39,21 +114,102 @@ class CrawlResult:
   ) -> MedusaZip:
     ...
 
+  def aggregate_digest(self, algorithm: DigestAlgorithm) -> ContentDigest: ...
+
 
 class Ignores:
-  def __init__(self, patterns: Optional[Iterable[str]] = None) -> None:
+  def __init__(
+    self,
+    patterns: Optional[Iterable[str]] = None,
+    ignore_globs: Optional[Iterable[str]] = None,
+    include_globs: Optional[Iterable[str]] = None,
+  ) -> None:
     ...
 
   @classmethod
   def default(cls) -> 'Ignores': ...
 
 
+class FileFilters:
+  def __init__(
+    self,
+    *,
+    max_size: Optional[int] = None,
+    modified_after_ns: Optional[int] = None,
+    modified_before_ns: Optional[int] = None,
+    same_file_system: bool = False,
+  ) -> None:
+    ...
+
+  @classmethod
+  def default(cls) -> 'FileFilters': ...
+
+  @property
+  def max_size(self) -> Optional[int]: ...
+  @property
+  def modified_after_ns(self) -> Optional[int]: ...
+  @property
+  def modified_before_ns(self) -> Optional[int]: ...
+  @property
+  def same_file_system(self) -> bool: ...
+
+
+class SymlinkBehavior:
+  Follow: 'SymlinkBehavior'
+  Preserve: 'SymlinkBehavior'
+
+  def __int__(self) -> int: ...
+
+  @classmethod
+  def default(cls) -> 'SymlinkBehavior': ...
+
+
+class SymlinkCyclePolicy:
+  Error: 'SymlinkCyclePolicy'
+  Skip: 'SymlinkCyclePolicy'
+  FollowOnce: 'SymlinkCyclePolicy'
+
+  def __int__(self) -> int: ...
+
+  @classmethod
+  def default(cls) -> 'SymlinkCyclePolicy': ...
+
+
+class SpecialFilePolicy:
+  Error: 'SpecialFilePolicy'
+  Skip: 'SpecialFilePolicy'
+
+  def __int__(self) -> int: ...
+
+  @classmethod
+  def default(cls) -> 'SpecialFilePolicy': ...
+
+
+class CrawlSource:
+  Filesystem: 'CrawlSource'
+  GitIndex: 'CrawlSource'
+
+  def __int__(self) -> int: ...
+
+  @classmethod
+  def default(cls) -> 'CrawlSource': ...
+
+
 class MedusaCrawl:
   def __init__(
     self,
     paths_to_crawl: Iterable[Union[str, Path]],
     ignores: Optional[Ignores] = None,
     cwd: Optional[Union[str, Path]] = None,
+    symlink_behavior: Optional[SymlinkBehavior] = None,
+    symlink_cycle_policy: Optional[SymlinkCyclePolicy] = None,
+    max_depth: Optional[int] = None,
+    special_file_policy: Optional[SpecialFilePolicy] = None,
+    ignore_file_names: Optional[Iterable[str]] = None,
+    source: Optional[CrawlSource] = None,
+    max_open_files: Optional[int] = None,
+    digest: Optional[DigestAlgorithm] = None,
+    filters: Optional[FileFilters] = None,
   ) -> None:
     ...
 
@@ -66,6 +222,51 @@ class MedusaCrawl:
   @property
   def cwd(self) -> Optional[Path]: ...
 
+  @property
+  def symlink_behavior(self) -> SymlinkBehavior: ...
+
+  @property
+  def symlink_cycle_policy(self) -> SymlinkCyclePolicy: ...
+
+  @property
+  def max_depth(self) -> Optional[int]: ...
+
+  @property
+  def special_file_policy(self) -> SpecialFilePolicy: ...
+
+  @property
+  def ignore_file_names(self) -> Iterable[str]: ...
+
+  @property
+  def source(self) -> CrawlSource: ...
+
+  @property
+  def max_open_files(self) -> int: ...
+
+  @property
+  def digest(self) -> Optional[DigestAlgorithm]: ...
+
+  @property
+  def filters(self) -> FileFilters: ...
+
   async def crawl_paths(self) -> CrawlResult: ...
 
   def crawl_paths_sync(self) -> CrawlResult: ...
+
+  async def crawl_zip(
+    self,
+    output_zip: ZipFileWriter,
+    zip_options: Optional[ZipOutputOptions] = None,
+    modifications: Optional[EntryModifications] = None,
+    tuning: Optional[PipelineTuning] = None,
+  ) -> ZipFileWriter:
+    ...
+
+  def crawl_zip_sync(
+    self,
+    output_zip: ZipFileWriter,
+    zip_options: Optional[ZipOutputOptions] = None,
+    modifications: Optional[EntryModifications] = None,
+    tuning: Optional[PipelineTuning] = None,
+  ) -> ZipFileWriter:
+    ...
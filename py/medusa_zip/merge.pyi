@@ -10,7 +10,7 @@ from typing import Iterable, Optional, Union
 
 from . import EntryName
 from .destination import ZipFileWriter
-from .zip import ModifiedTimeBehavior
+from .zip import ModifiedTimeBehavior, PipelineTuning
 
 
 class MergeGroup:
@@ -38,6 +38,7 @@ class MedusaMerge:
     self,
     mtime_behavior: ModifiedTimeBehavior,
     output_zip: ZipFileWriter,
+    tuning: Optional[PipelineTuning] = None,
   ) -> ZipFileWriter:
     ...
 
@@ -45,5 +46,6 @@ class MedusaMerge:
     self,
     mtime_behavior: ModifiedTimeBehavior,
     output_zip: ZipFileWriter,
+    tuning: Optional[PipelineTuning] = None,
   ) -> ZipFileWriter:
     ...
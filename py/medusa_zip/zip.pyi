@@ -6,9 +6,10 @@
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 from datetime import datetime
-from typing import Iterable, Optional
+from pathlib import Path
+from typing import Iterable, List, Optional, Union
 
-from . import FileSource
+from . import FileSource, SymlinkSource
 from .destination import ZipFileWriter
 
 
@@ -69,6 +70,8 @@ class ModifiedTimeBehavior:
 class CompressionMethod:
   Stored: 'CompressionMethod'
   Deflated: 'CompressionMethod'
+  Bzip2: 'CompressionMethod'
+  Zstd: 'CompressionMethod'
 
   def __int__(self) -> int: ...
 
@@ -77,6 +80,8 @@ class CompressionMethod:
 
 
 class CompressionOptions:
+  # Accepted levels: Stored takes none, Deflated 0..=9, Bzip2 1..=9, and Zstd
+  # -7..=22. Deflated and Zstd also map 0 to their default level.
   def __init__(self, method: CompressionMethod, level: Optional[int]) -> None:
     ...
 
@@ -89,11 +94,40 @@ class CompressionOptions:
   def default(cls) -> 'CompressionOptions': ...
 
 
+class CompressionRule:
+  def __init__(
+    self,
+    compression_options: CompressionOptions,
+    glob: Optional[str] = None,
+    regex: Optional[str] = None,
+    min_size: Optional[int] = None,
+    max_size: Optional[int] = None,
+  ) -> None:
+    ...
+
+  @property
+  def compression_options(self) -> CompressionOptions: ...
+  @property
+  def glob(self) -> Optional[str]: ...
+  @property
+  def regex(self) -> Optional[str]: ...
+  @property
+  def min_size(self) -> Optional[int]: ...
+  @property
+  def max_size(self) -> Optional[int]: ...
+
+
 class ZipOutputOptions:
   def __init__(
     self,
     mtime_behavior: Optional[ModifiedTimeBehavior] = None,
     compression_options: Optional[CompressionOptions] = None,
+    compression_rules: Optional[List[CompressionRule]] = None,
+    incompressible_min_savings: Optional[float] = None,
+    parallel_deflate_min_size: Optional[int] = None,
+    parallel_deflate_block_size: Optional[int] = None,
+    entry_cache_dir: Optional[Union[str, Path]] = None,
+    entry_cache_max_size: Optional[int] = None,
   ) -> None:
     ...
 
@@ -101,16 +135,40 @@ class ZipOutputOptions:
   def mtime_behavior(self) -> ModifiedTimeBehavior: ...
   @property
   def compression_options(self) -> CompressionOptions: ...
+  @property
+  def compression_rules(self) -> List[CompressionRule]: ...
+  @property
+  def incompressible_min_savings(self) -> Optional[float]: ...
+  @property
+  def parallel_deflate_min_size(self) -> Optional[int]: ...
+  @property
+  def parallel_deflate_block_size(self) -> int: ...
+  @property
+  def entry_cache_dir(self) -> Optional[Path]: ...
+  @property
+  def entry_cache_max_size(self) -> int: ...
 
   @classmethod
   def default(cls) -> 'ZipOutputOptions': ...
 
 
+class NameRewrite:
+  @classmethod
+  def strip_prefix(cls, prefix: str) -> 'NameRewrite': ...
+
+  @classmethod
+  def add_prefix(cls, prefix: str) -> 'NameRewrite': ...
+
+  @classmethod
+  def replace(cls, pattern: str, replacement: str) -> 'NameRewrite': ...
+
+
 class EntryModifications:
   def __init__(
     self,
     silent_external_prefix: Optional[str] = None,
     own_prefix: Optional[str] = None,
+    rewrites: Optional[Iterable[NameRewrite]] = None,
   ) -> None:
     ...
 
@@ -118,6 +176,8 @@ class EntryModifications:
   def silent_external_prefix(self) -> Optional[str]: ...
   @property
   def own_prefix(self) -> Optional[str]: ...
+  @property
+  def rewrites(self) -> Iterable[NameRewrite]: ...
 
   @classmethod
   def default(cls) -> 'EntryModifications': ...
@@ -126,6 +186,8 @@ class EntryModifications:
 class Parallelism:
   Synchronous: 'Parallelism'
   ParallelMerge: 'Parallelism'
+  ParallelTreeMerge: 'Parallelism'
+  PerEntry: 'Parallelism'
 
   def __int__(self) -> int: ...
 
@@ -133,6 +195,47 @@ class Parallelism:
   def default(cls) -> 'Parallelism': ...
 
 
+class PipelineTuning:
+  def __init__(
+    self,
+    intermediate_chunk_size: Optional[int] = None,
+    intermediate_chunk_bytes: Optional[int] = None,
+    max_parallel_intermediates: Optional[int] = None,
+    per_intermediate_file_io_queue_length: Optional[int] = None,
+    intermediate_output_spool_threshold: Optional[int] = None,
+    per_file_spool_threshold: Optional[int] = None,
+    parallel_merge_entries: Optional[int] = None,
+    tree_merge_final_width: Optional[int] = None,
+    temp_dir: Optional[Union[str, Path]] = None,
+    max_open_files: Optional[int] = None,
+  ) -> None:
+    ...
+
+  @property
+  def intermediate_chunk_size(self) -> int: ...
+  @property
+  def intermediate_chunk_bytes(self) -> int: ...
+  @property
+  def max_parallel_intermediates(self) -> int: ...
+  @property
+  def per_intermediate_file_io_queue_length(self) -> int: ...
+  @property
+  def intermediate_output_spool_threshold(self) -> int: ...
+  @property
+  def per_file_spool_threshold(self) -> int: ...
+  @property
+  def parallel_merge_entries(self) -> int: ...
+  @property
+  def tree_merge_final_width(self) -> int: ...
+  @property
+  def temp_dir(self) -> Optional[Path]: ...
+  @property
+  def max_open_files(self) -> int: ...
+
+  @classmethod
+  def default(cls) -> 'PipelineTuning': ...
+
+
 class MedusaZip:
   def __init__(
     self,
@@ -140,11 +243,20 @@ class MedusaZip:
     zip_options: Optional[ZipOutputOptions] = None,
     modifications: Optional[EntryModifications] = None,
     parallelism: Optional[Parallelism] = None,
+    input_symlinks: Optional[Iterable[SymlinkSource]] = None,
   ) -> None:
     ...
 
-  async def zip(self, output_zip: ZipFileWriter) -> ZipFileWriter:
+  async def zip(
+    self,
+    output_zip: ZipFileWriter,
+    tuning: Optional[PipelineTuning] = None,
+  ) -> ZipFileWriter:
     ...
 
-  def zip_sync(self, output_zip: ZipFileWriter) -> ZipFileWriter:
+  def zip_sync(
+    self,
+    output_zip: ZipFileWriter,
+    tuning: Optional[PipelineTuning] = None,
+  ) -> ZipFileWriter:
     ...
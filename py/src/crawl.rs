@@ -10,20 +10,198 @@
 //! ???
 
 use crate::{
+  destination::ZipFileWriter,
   util::repr,
-  zip::{EntryModifications, MedusaZip, Parallelism, ZipOutputOptions},
+  zip::{EntryModifications, MedusaZip, Parallelism, PipelineTuning, ZipOutputOptions},
 };
 
-use libmedusa_zip::{crawl as lib_crawl, zip as lib_zip};
+use libmedusa_zip::{crawl as lib_crawl, digest as lib_digest, zip as lib_zip};
 
 use pyo3::{
-  exceptions::{PyException, PyValueError},
+  exceptions::{PyException, PyUserWarning, PyValueError},
   prelude::*,
   types::PyType,
 };
 use regex::RegexSet;
 
-use std::path::PathBuf;
+use std::{
+  num::NonZeroUsize,
+  path::PathBuf,
+  time::{Duration, SystemTime},
+};
+
+#[pyclass]
+#[derive(Copy, Clone)]
+pub enum EntryKind {
+  File,
+  Symlink,
+}
+
+impl From<EntryKind> for lib_crawl::EntryKind {
+  fn from(x: EntryKind) -> Self {
+    match x {
+      EntryKind::File => Self::File,
+      EntryKind::Symlink => Self::Symlink,
+    }
+  }
+}
+
+impl From<lib_crawl::EntryKind> for EntryKind {
+  fn from(x: lib_crawl::EntryKind) -> Self {
+    match x {
+      lib_crawl::EntryKind::File => Self::File,
+      lib_crawl::EntryKind::Symlink => Self::Symlink,
+    }
+  }
+}
+
+#[pyclass]
+#[derive(Copy, Clone)]
+pub struct EntryMetadata {
+  #[pyo3(get)]
+  pub kind: EntryKind,
+  #[pyo3(get)]
+  pub size: u64,
+  #[pyo3(get)]
+  pub mode: u32,
+  /// Nanoseconds since the unix epoch, like `os.stat_result.st_mtime_ns`.
+  #[pyo3(get)]
+  pub mtime_ns: i128,
+}
+
+#[pymethods]
+impl EntryMetadata {
+  #[new]
+  #[pyo3(signature = (*, kind, size, mode, mtime_ns))]
+  fn new(kind: EntryKind, size: u64, mode: u32, mtime_ns: i128) -> Self {
+    Self {
+      kind,
+      size,
+      mode,
+      mtime_ns,
+    }
+  }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let Self {
+      kind,
+      size,
+      mode,
+      mtime_ns,
+    } = self;
+    let kind = repr(py, *kind)?;
+    Ok(format!(
+      "EntryMetadata(kind={}, size={}, mode=0o{:o}, mtime_ns={})",
+      kind, size, mode, mtime_ns
+    ))
+  }
+}
+
+impl EntryMetadata {
+  fn time_to_ns(time: SystemTime) -> i128 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+      Ok(after) => after.as_nanos() as i128,
+      Err(e) => -(e.duration().as_nanos() as i128),
+    }
+  }
+
+  fn ns_to_time(ns: i128) -> SystemTime {
+    let offset = Duration::from_nanos(ns.unsigned_abs() as u64);
+    if ns >= 0 {
+      SystemTime::UNIX_EPOCH + offset
+    } else {
+      SystemTime::UNIX_EPOCH - offset
+    }
+  }
+}
+
+impl From<EntryMetadata> for lib_crawl::EntryMetadata {
+  fn from(x: EntryMetadata) -> Self {
+    let EntryMetadata {
+      kind,
+      size,
+      mode,
+      mtime_ns,
+    } = x;
+    Self {
+      kind: kind.into(),
+      size,
+      mode,
+      mtime: EntryMetadata::ns_to_time(mtime_ns),
+    }
+  }
+}
+
+impl From<lib_crawl::EntryMetadata> for EntryMetadata {
+  fn from(x: lib_crawl::EntryMetadata) -> Self {
+    let lib_crawl::EntryMetadata {
+      kind,
+      size,
+      mode,
+      mtime,
+    } = x;
+    Self {
+      kind: kind.into(),
+      size,
+      mode,
+      mtime_ns: Self::time_to_ns(mtime),
+    }
+  }
+}
+
+#[pyclass]
+#[derive(Copy, Clone)]
+pub enum DigestAlgorithm {
+  Sha256,
+  Blake3,
+}
+
+impl From<DigestAlgorithm> for lib_digest::DigestAlgorithm {
+  fn from(x: DigestAlgorithm) -> Self {
+    match x {
+      DigestAlgorithm::Sha256 => Self::Sha256,
+      DigestAlgorithm::Blake3 => Self::Blake3,
+    }
+  }
+}
+
+impl From<lib_digest::DigestAlgorithm> for DigestAlgorithm {
+  fn from(x: lib_digest::DigestAlgorithm) -> Self {
+    match x {
+      lib_digest::DigestAlgorithm::Sha256 => Self::Sha256,
+      lib_digest::DigestAlgorithm::Blake3 => Self::Blake3,
+    }
+  }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct ContentDigest(lib_digest::ContentDigest);
+
+#[pymethods]
+impl ContentDigest {
+  /// Parse a digest of the form `<algorithm>:<hex digest>`.
+  #[new]
+  fn new(digest: &str) -> PyResult<Self> {
+    /* TODO: better error! */
+    let parsed = digest
+      .parse::<lib_digest::ContentDigest>()
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    Ok(Self(parsed))
+  }
+
+  #[getter]
+  fn algorithm(&self) -> DigestAlgorithm { self.0.algorithm.into() }
+
+  #[getter]
+  fn hex(&self) -> String { self.0.hex() }
+
+  fn __repr__(&self) -> String { format!("ContentDigest({:?})", self.0.to_string()) }
+
+  fn __str__(&self) -> String { self.0.to_string() }
+
+  fn __eq__(&self, other: &Self) -> bool { self.0 == other.0 }
+}
 
 #[pyclass]
 #[derive(Clone)]
@@ -32,25 +210,38 @@ pub struct ResolvedPath {
   pub unresolved_path: PathBuf,
   #[pyo3(get)]
   pub resolved_path: PathBuf,
+  #[pyo3(get)]
+  pub metadata: Option<EntryMetadata>,
+  #[pyo3(get)]
+  pub digest: Option<ContentDigest>,
 }
 
 #[pymethods]
 impl ResolvedPath {
   #[new]
-  #[pyo3(signature = (*, unresolved_path, resolved_path))]
-  fn new(unresolved_path: PathBuf, resolved_path: PathBuf) -> Self {
+  #[pyo3(signature = (*, unresolved_path, resolved_path, metadata=None, digest=None))]
+  fn new(
+    unresolved_path: PathBuf,
+    resolved_path: PathBuf,
+    metadata: Option<EntryMetadata>,
+    digest: Option<ContentDigest>,
+  ) -> Self {
     Self {
       unresolved_path,
       resolved_path,
+      metadata,
+      digest,
     }
   }
 
   /* See https://pyo3.rs/v0.19.1/class/object for more info. */
-  fn __repr__(&self) -> String {
-    format!(
-      "ResolvedPath(unresolved_path={:?}, resolved_path={:?})",
-      &self.unresolved_path, &self.resolved_path
-    )
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let metadata = repr(py, self.metadata)?;
+    let digest = repr(py, self.digest.clone())?;
+    Ok(format!(
+      "ResolvedPath(unresolved_path={:?}, resolved_path={:?}, metadata={}, digest={})",
+      &self.unresolved_path, &self.resolved_path, metadata, digest,
+    ))
   }
 }
 
@@ -59,10 +250,14 @@ impl From<ResolvedPath> for lib_crawl::ResolvedPath {
     let ResolvedPath {
       unresolved_path,
       resolved_path,
+      metadata,
+      digest,
     } = x;
     Self {
       unresolved_path,
       resolved_path,
+      metadata: metadata.map(lib_crawl::EntryMetadata::from),
+      digest: digest.map(|ContentDigest(digest)| digest),
     }
   }
 }
@@ -72,10 +267,76 @@ impl From<lib_crawl::ResolvedPath> for ResolvedPath {
     let lib_crawl::ResolvedPath {
       unresolved_path,
       resolved_path,
+      metadata,
+      digest,
     } = x;
     Self {
       unresolved_path,
       resolved_path,
+      metadata: metadata.map(EntryMetadata::from),
+      digest: digest.map(ContentDigest),
+    }
+  }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct ResolvedSymlink {
+  #[pyo3(get)]
+  pub unresolved_path: PathBuf,
+  #[pyo3(get)]
+  pub target: PathBuf,
+  #[pyo3(get)]
+  pub metadata: Option<EntryMetadata>,
+}
+
+#[pymethods]
+impl ResolvedSymlink {
+  #[new]
+  #[pyo3(signature = (*, unresolved_path, target, metadata=None))]
+  fn new(unresolved_path: PathBuf, target: PathBuf, metadata: Option<EntryMetadata>) -> Self {
+    Self {
+      unresolved_path,
+      target,
+      metadata,
+    }
+  }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let metadata = repr(py, self.metadata)?;
+    Ok(format!(
+      "ResolvedSymlink(unresolved_path={:?}, target={:?}, metadata={})",
+      &self.unresolved_path, &self.target, metadata,
+    ))
+  }
+}
+
+impl From<ResolvedSymlink> for lib_crawl::ResolvedSymlink {
+  fn from(x: ResolvedSymlink) -> Self {
+    let ResolvedSymlink {
+      unresolved_path,
+      target,
+      metadata,
+    } = x;
+    Self {
+      unresolved_path,
+      target,
+      metadata: metadata.map(lib_crawl::EntryMetadata::from),
+    }
+  }
+}
+
+impl From<lib_crawl::ResolvedSymlink> for ResolvedSymlink {
+  fn from(x: lib_crawl::ResolvedSymlink) -> Self {
+    let lib_crawl::ResolvedSymlink {
+      unresolved_path,
+      target,
+      metadata,
+    } = x;
+    Self {
+      unresolved_path,
+      target,
+      metadata: metadata.map(EntryMetadata::from),
     }
   }
 }
@@ -85,22 +346,40 @@ impl From<lib_crawl::ResolvedPath> for ResolvedPath {
 pub struct CrawlResult {
   #[pyo3(get)]
   pub real_file_paths: Vec<ResolvedPath>,
+  #[pyo3(get)]
+  pub symlinks: Vec<ResolvedSymlink>,
 }
 
 #[pymethods]
 impl CrawlResult {
   #[new]
-  fn new(real_file_paths: &PyAny) -> PyResult<Self> {
+  fn new(real_file_paths: &PyAny, symlinks: Option<&PyAny>) -> PyResult<Self> {
     let real_file_paths: Vec<ResolvedPath> = real_file_paths
       .iter()?
       .map(|rp| rp.and_then(PyAny::extract::<ResolvedPath>))
       .collect::<PyResult<_>>()?;
-    Ok(Self { real_file_paths })
+    let symlinks: Vec<ResolvedSymlink> = symlinks
+      .map(|symlinks| {
+        symlinks
+          .iter()?
+          .map(|rs| rs.and_then(PyAny::extract::<ResolvedSymlink>))
+          .collect::<PyResult<_>>()
+      })
+      .transpose()?
+      .unwrap_or_default();
+    Ok(Self {
+      real_file_paths,
+      symlinks,
+    })
   }
 
   fn __repr__(&self, py: Python<'_>) -> String {
     let real_file_paths = self.real_file_paths.clone().into_py(py);
-    format!("CrawlResult(real_file_paths={})", real_file_paths)
+    let symlinks = self.symlinks.clone().into_py(py);
+    format!(
+      "CrawlResult(real_file_paths={}, symlinks={})",
+      real_file_paths, symlinks
+    )
   }
 
   fn medusa_zip(
@@ -109,11 +388,7 @@ impl CrawlResult {
     modifications: Option<EntryModifications>,
     parallelism: Option<Parallelism>,
   ) -> PyResult<MedusaZip> {
-    let zip_options: lib_zip::ZipOutputOptions = zip_options
-      .unwrap_or_default()
-      .try_into()
-      /* TODO: better error! */
-      .map_err(|e| PyException::new_err(format!("{}", e)))?;
+    let zip_options: lib_zip::ZipOutputOptions = zip_options.unwrap_or_default().try_into()?;
     let modifications: lib_zip::EntryModifications = modifications.unwrap_or_default().into();
     let parallelism: lib_zip::Parallelism = parallelism.unwrap_or_default().into();
     let crawl_result: lib_crawl::CrawlResult = self.clone().into();
@@ -124,25 +399,44 @@ impl CrawlResult {
     let medusa_zip: MedusaZip = medusa_zip.into();
     Ok(medusa_zip)
   }
+
+  fn aggregate_digest(&self, algorithm: DigestAlgorithm) -> PyResult<ContentDigest> {
+    let crawl_result: lib_crawl::CrawlResult = self.clone().into();
+    let digest = crawl_result
+      .aggregate_digest(algorithm.into())
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    Ok(ContentDigest(digest))
+  }
 }
 
 impl From<lib_crawl::CrawlResult> for CrawlResult {
   fn from(x: lib_crawl::CrawlResult) -> Self {
-    let lib_crawl::CrawlResult { real_file_paths } = x;
+    let lib_crawl::CrawlResult {
+      real_file_paths,
+      symlinks,
+      ..
+    } = x;
     Self {
       real_file_paths: real_file_paths
         .into_iter()
         .map(ResolvedPath::from)
         .collect(),
+      symlinks: symlinks.into_iter().map(ResolvedSymlink::from).collect(),
     }
   }
 }
 
 impl From<CrawlResult> for lib_crawl::CrawlResult {
   fn from(x: CrawlResult) -> Self {
-    let CrawlResult { real_file_paths } = x;
+    let CrawlResult {
+      real_file_paths,
+      symlinks,
+    } = x;
     Self {
       real_file_paths: real_file_paths.into_iter().map(|rp| rp.into()).collect(),
+      symlinks: symlinks.into_iter().map(|rs| rs.into()).collect(),
+      skipped_files: Vec::new(),
     }
   }
 }
@@ -152,6 +446,8 @@ impl From<CrawlResult> for lib_crawl::CrawlResult {
 #[derive(Clone)]
 pub struct Ignores {
   pub patterns: RegexSet,
+  pub ignore_globs: lib_crawl::GlobRules,
+  pub include_globs: lib_crawl::GlobRules,
 }
 
 impl Default for Ignores {
@@ -161,40 +457,336 @@ impl Default for Ignores {
 #[pymethods]
 impl Ignores {
   #[new]
-  fn new(patterns: Option<&PyAny>) -> PyResult<Self> {
-    let patterns: Vec<&str> = patterns
-      .map(|patterns| {
-        let ret: Vec<&str> = patterns
-          .iter()?
-          .map(|p| p.and_then(PyAny::extract::<&str>))
-          .collect::<PyResult<_>>()?;
-        Ok::<_, PyErr>(ret)
-      })
-      .transpose()?
-      .unwrap_or_default();
+  #[pyo3(signature = (patterns=None, ignore_globs=None, include_globs=None))]
+  fn new(
+    patterns: Option<&PyAny>,
+    ignore_globs: Option<&PyAny>,
+    include_globs: Option<&PyAny>,
+  ) -> PyResult<Self> {
+    let patterns: Vec<&str> = Self::extract_strings(patterns)?;
     /* TODO: better error! */
     let patterns = RegexSet::new(patterns).map_err(|e| PyValueError::new_err(format!("{}", e)))?;
-    Ok(Self { patterns })
+    let ignore_globs = Self::parse_globs(Self::extract_strings(ignore_globs)?)?;
+    let include_globs = Self::parse_globs(Self::extract_strings(include_globs)?)?;
+    Ok(Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    })
   }
 
   #[classmethod]
   #[pyo3(name = "default")]
   fn py_default(_cls: &PyType) -> Self { Self::default() }
 
-  fn __repr__(&self) -> String { format!("Ignores(patterns={:?})", self.patterns.patterns()) }
+  fn __repr__(&self) -> String {
+    let Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    } = self;
+    let glob_strings = |globs: &lib_crawl::GlobRules| -> Vec<String> {
+      globs.rules.iter().map(|g| g.as_str().to_string()).collect()
+    };
+    format!(
+      "Ignores(patterns={:?}, ignore_globs={:?}, include_globs={:?})",
+      patterns.patterns(),
+      glob_strings(ignore_globs),
+      glob_strings(include_globs),
+    )
+  }
+}
+
+impl Ignores {
+  fn extract_strings(strings: Option<&PyAny>) -> PyResult<Vec<&str>> {
+    Ok(
+      strings
+        .map(|strings| {
+          let ret: Vec<&str> = strings
+            .iter()?
+            .map(|p| p.and_then(PyAny::extract::<&str>))
+            .collect::<PyResult<_>>()?;
+          Ok::<_, PyErr>(ret)
+        })
+        .transpose()?
+        .unwrap_or_default(),
+    )
+  }
+
+  fn parse_globs(globs: Vec<&str>) -> PyResult<lib_crawl::GlobRules> {
+    let rules: Vec<lib_crawl::GlobRule> = globs
+      .into_iter()
+      .map(|g| lib_crawl::GlobRule::parse(g).map_err(|e| PyValueError::new_err(format!("{}", e))))
+      .collect::<PyResult<_>>()?;
+    Ok(lib_crawl::GlobRules::new(rules))
+  }
 }
 
 impl From<Ignores> for lib_crawl::Ignores {
   fn from(x: Ignores) -> Self {
-    let Ignores { patterns } = x;
-    Self { patterns }
+    let Ignores {
+      patterns,
+      ignore_globs,
+      include_globs,
+    } = x;
+    Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    }
   }
 }
 
 impl From<lib_crawl::Ignores> for Ignores {
   fn from(x: lib_crawl::Ignores) -> Self {
-    let lib_crawl::Ignores { patterns } = x;
-    Self { patterns }
+    let lib_crawl::Ignores {
+      patterns,
+      ignore_globs,
+      include_globs,
+    } = x;
+    Self {
+      patterns,
+      ignore_globs,
+      include_globs,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Copy, Clone, Default)]
+pub struct FileFilters {
+  #[pyo3(get)]
+  pub max_size: Option<u64>,
+  /// Nanoseconds since the unix epoch, like `os.stat_result.st_mtime_ns`.
+  #[pyo3(get)]
+  pub modified_after_ns: Option<i128>,
+  #[pyo3(get)]
+  pub modified_before_ns: Option<i128>,
+  #[pyo3(get)]
+  pub same_file_system: bool,
+}
+
+#[pymethods]
+impl FileFilters {
+  #[new]
+  #[pyo3(signature = (*, max_size=None, modified_after_ns=None, modified_before_ns=None, same_file_system=false))]
+  fn new(
+    max_size: Option<u64>,
+    modified_after_ns: Option<i128>,
+    modified_before_ns: Option<i128>,
+    same_file_system: bool,
+  ) -> Self {
+    Self {
+      max_size,
+      modified_after_ns,
+      modified_before_ns,
+      same_file_system,
+    }
+  }
+
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let Self {
+      max_size,
+      modified_after_ns,
+      modified_before_ns,
+      same_file_system,
+    } = self;
+    let max_size = repr(py, *max_size)?;
+    let modified_after_ns = repr(py, *modified_after_ns)?;
+    let modified_before_ns = repr(py, *modified_before_ns)?;
+    let same_file_system = repr(py, *same_file_system)?;
+    Ok(format!(
+      "FileFilters(max_size={}, modified_after_ns={}, modified_before_ns={}, same_file_system={})",
+      max_size, modified_after_ns, modified_before_ns, same_file_system,
+    ))
+  }
+}
+
+impl From<FileFilters> for lib_crawl::FileFilters {
+  fn from(x: FileFilters) -> Self {
+    let FileFilters {
+      max_size,
+      modified_after_ns,
+      modified_before_ns,
+      same_file_system,
+    } = x;
+    Self {
+      max_size,
+      modified_after: modified_after_ns.map(EntryMetadata::ns_to_time),
+      modified_before: modified_before_ns.map(EntryMetadata::ns_to_time),
+      same_file_system,
+    }
+  }
+}
+
+impl From<lib_crawl::FileFilters> for FileFilters {
+  fn from(x: lib_crawl::FileFilters) -> Self {
+    let lib_crawl::FileFilters {
+      max_size,
+      modified_after,
+      modified_before,
+      same_file_system,
+    } = x;
+    Self {
+      max_size,
+      modified_after_ns: modified_after.map(EntryMetadata::time_to_ns),
+      modified_before_ns: modified_before.map(EntryMetadata::time_to_ns),
+      same_file_system,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Copy, Clone, Default)]
+pub enum SymlinkBehavior {
+  #[default]
+  Follow,
+  Preserve,
+}
+
+#[pymethods]
+impl SymlinkBehavior {
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+}
+
+impl From<SymlinkBehavior> for lib_crawl::SymlinkBehavior {
+  fn from(x: SymlinkBehavior) -> Self {
+    match x {
+      SymlinkBehavior::Follow => Self::Follow,
+      SymlinkBehavior::Preserve => Self::Preserve,
+    }
+  }
+}
+
+impl From<lib_crawl::SymlinkBehavior> for SymlinkBehavior {
+  fn from(x: lib_crawl::SymlinkBehavior) -> Self {
+    match x {
+      lib_crawl::SymlinkBehavior::Follow => Self::Follow,
+      lib_crawl::SymlinkBehavior::Preserve => Self::Preserve,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Copy, Clone, Default)]
+pub enum SymlinkCyclePolicy {
+  #[default]
+  Error,
+  Skip,
+  FollowOnce,
+}
+
+#[pymethods]
+impl SymlinkCyclePolicy {
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+}
+
+impl From<SymlinkCyclePolicy> for lib_crawl::SymlinkCyclePolicy {
+  fn from(x: SymlinkCyclePolicy) -> Self {
+    match x {
+      SymlinkCyclePolicy::Error => Self::Error,
+      SymlinkCyclePolicy::Skip => Self::Skip,
+      SymlinkCyclePolicy::FollowOnce => Self::FollowOnce,
+    }
+  }
+}
+
+impl From<lib_crawl::SymlinkCyclePolicy> for SymlinkCyclePolicy {
+  fn from(x: lib_crawl::SymlinkCyclePolicy) -> Self {
+    match x {
+      lib_crawl::SymlinkCyclePolicy::Error => Self::Error,
+      lib_crawl::SymlinkCyclePolicy::Skip => Self::Skip,
+      lib_crawl::SymlinkCyclePolicy::FollowOnce => Self::FollowOnce,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Copy, Clone, Default)]
+pub enum SpecialFilePolicy {
+  #[default]
+  Error,
+  Skip,
+}
+
+#[pymethods]
+impl SpecialFilePolicy {
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+}
+
+impl From<SpecialFilePolicy> for lib_crawl::SpecialFilePolicy {
+  fn from(x: SpecialFilePolicy) -> Self {
+    match x {
+      SpecialFilePolicy::Error => Self::Error,
+      SpecialFilePolicy::Skip => Self::Skip,
+    }
+  }
+}
+
+impl From<lib_crawl::SpecialFilePolicy> for SpecialFilePolicy {
+  fn from(x: lib_crawl::SpecialFilePolicy) -> Self {
+    match x {
+      lib_crawl::SpecialFilePolicy::Error => Self::Error,
+      lib_crawl::SpecialFilePolicy::Skip => Self::Skip,
+    }
+  }
+}
+
+/// Emit a python warning for each file left out of the crawl with
+/// [`SpecialFilePolicy::Skip`].
+fn warn_skipped_files(py: Python, skipped_files: &[lib_crawl::SkippedFile]) -> PyResult<()> {
+  for lib_crawl::SkippedFile { path, kind } in skipped_files.iter() {
+    let message = format!("skipping {} at {:?}", kind, path);
+    PyErr::warn(py, py.get_type::<PyUserWarning>(), &message, 0)?;
+  }
+  Ok(())
+}
+
+
+#[pyclass]
+#[derive(Copy, Clone, Default)]
+pub enum CrawlSource {
+  #[default]
+  Filesystem,
+  GitIndex,
+}
+
+#[pymethods]
+impl CrawlSource {
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+}
+
+impl From<CrawlSource> for lib_crawl::CrawlSource {
+  fn from(x: CrawlSource) -> Self {
+    match x {
+      CrawlSource::Filesystem => Self::Filesystem,
+      CrawlSource::GitIndex => Self::GitIndex,
+    }
+  }
+}
+
+impl From<lib_crawl::CrawlSource> for CrawlSource {
+  fn from(x: lib_crawl::CrawlSource) -> Self {
+    match x {
+      lib_crawl::CrawlSource::Filesystem => Self::Filesystem,
+      lib_crawl::CrawlSource::GitIndex => Self::GitIndex,
+    }
   }
 }
 
@@ -208,24 +800,99 @@ pub struct MedusaCrawl {
   #[pyo3(get)]
   pub ignores: Ignores,
   #[pyo3(get)]
+  pub filters: FileFilters,
+  #[pyo3(get)]
   pub cwd: Option<PathBuf>,
+  #[pyo3(get)]
+  pub symlink_behavior: SymlinkBehavior,
+  #[pyo3(get)]
+  pub symlink_cycle_policy: SymlinkCyclePolicy,
+  #[pyo3(get)]
+  pub max_depth: Option<usize>,
+  #[pyo3(get)]
+  pub special_file_policy: SpecialFilePolicy,
+  #[pyo3(get)]
+  pub ignore_file_names: Vec<String>,
+  #[pyo3(get)]
+  pub source: CrawlSource,
+  #[pyo3(get)]
+  pub max_open_files: usize,
+  #[pyo3(get)]
+  pub digest: Option<DigestAlgorithm>,
 }
 
 #[pymethods]
 impl MedusaCrawl {
   #[new]
-  #[pyo3(signature = (paths_to_crawl, ignores = None, cwd = None))]
-  fn new(paths_to_crawl: &PyAny, ignores: Option<Ignores>, cwd: Option<&PyAny>) -> PyResult<Self> {
+  #[pyo3(signature = (
+    paths_to_crawl,
+    ignores = None,
+    cwd = None,
+    symlink_behavior = None,
+    symlink_cycle_policy = None,
+    max_depth = None,
+    special_file_policy = None,
+    ignore_file_names = None,
+    source = None,
+    max_open_files = None,
+    digest = None,
+    filters = None,
+  ))]
+  fn new(
+    paths_to_crawl: &PyAny,
+    ignores: Option<Ignores>,
+    cwd: Option<&PyAny>,
+    symlink_behavior: Option<SymlinkBehavior>,
+    symlink_cycle_policy: Option<SymlinkCyclePolicy>,
+    max_depth: Option<usize>,
+    special_file_policy: Option<SpecialFilePolicy>,
+    ignore_file_names: Option<&PyAny>,
+    source: Option<CrawlSource>,
+    max_open_files: Option<usize>,
+    digest: Option<DigestAlgorithm>,
+    filters: Option<FileFilters>,
+  ) -> PyResult<Self> {
     let ignores = ignores.unwrap_or_default();
+    let filters = filters.unwrap_or_default();
+    let symlink_behavior = symlink_behavior.unwrap_or_default();
+    let symlink_cycle_policy = symlink_cycle_policy.unwrap_or_default();
+    let special_file_policy = special_file_policy.unwrap_or_default();
+    let source = source.unwrap_or_default();
+    let max_open_files = max_open_files
+      .map(|n| {
+        NonZeroUsize::new(n)
+          .ok_or_else(|| PyValueError::new_err("max_open_files must be greater than 0"))
+      })
+      .transpose()?
+      .unwrap_or_else(libmedusa_zip::default_max_open_files)
+      .get();
     let paths_to_crawl: Vec<PathBuf> = paths_to_crawl
       .iter()?
       .map(|p| p.and_then(PyAny::extract::<PathBuf>))
       .collect::<PyResult<_>>()?;
     let cwd = cwd.map(|cwd| PyAny::extract::<PathBuf>(cwd)).transpose()?;
+    let ignore_file_names: Vec<String> = ignore_file_names
+      .map(|names| {
+        names
+          .iter()?
+          .map(|n| n.and_then(PyAny::extract::<String>))
+          .collect::<PyResult<_>>()
+      })
+      .transpose()?
+      .unwrap_or_default();
     Ok(Self {
       paths_to_crawl,
       ignores,
+      filters,
       cwd,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      ignore_file_names,
+      source,
+      max_open_files,
+      digest,
     })
   }
 
@@ -234,13 +901,30 @@ impl MedusaCrawl {
       paths_to_crawl,
       ignores,
       cwd,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      ignore_file_names,
+      source,
+      max_open_files,
+      digest,
+      filters,
     } = self;
     let paths_to_crawl = repr(py, paths_to_crawl.clone())?;
     let ignores = repr(py, ignores.clone())?;
     let cwd = repr(py, cwd.clone())?;
+    let symlink_behavior = repr(py, *symlink_behavior)?;
+    let symlink_cycle_policy = repr(py, *symlink_cycle_policy)?;
+    let max_depth = repr(py, *max_depth)?;
+    let special_file_policy = repr(py, *special_file_policy)?;
+    let ignore_file_names = repr(py, ignore_file_names.clone())?;
+    let source = repr(py, *source)?;
+    let digest = repr(py, *digest)?;
+    let filters = repr(py, *filters)?;
     Ok(format!(
-      "MedusaCrawl(paths_to_crawl={}, ignores={}, cwd={})",
-      paths_to_crawl, ignores, cwd,
+      "MedusaCrawl(paths_to_crawl={}, ignores={}, cwd={}, symlink_behavior={}, symlink_cycle_policy={}, max_depth={}, special_file_policy={}, ignore_file_names={}, source={}, max_open_files={}, digest={}, filters={})",
+      paths_to_crawl, ignores, cwd, symlink_behavior, symlink_cycle_policy, max_depth, special_file_policy, ignore_file_names, source, max_open_files, digest, filters,
     ))
   }
 
@@ -248,13 +932,13 @@ impl MedusaCrawl {
   fn crawl_paths<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
     let crawl: lib_crawl::MedusaCrawl = self.clone().into();
     pyo3_asyncio::tokio::future_into_py(py, async move {
-      let ret: PyResult<CrawlResult> = crawl
+      let crawl_result = crawl
         .crawl_paths()
         .await
         /* TODO: better error! */
-        .map_err(|e| PyException::new_err(format!("{}", e)))
-        .map(|cr| cr.into());
-      ret
+        .map_err(|e| PyException::new_err(format!("{}", e)))?;
+      Python::with_gil(|py| warn_skipped_files(py, &crawl_result.skipped_files))?;
+      Ok::<CrawlResult, PyErr>(crawl_result.into())
     })
   }
 
@@ -262,13 +946,76 @@ impl MedusaCrawl {
   fn crawl_paths_sync(&self, py: Python) -> PyResult<CrawlResult> {
     let handle = crate::TOKIO_RUNTIME.handle();
     let crawl: lib_crawl::MedusaCrawl = self.clone().into();
-    py.allow_threads(move || {
-      let ret: PyResult<CrawlResult> = handle.block_on(crawl
+    let crawl_result = py.allow_threads(move || {
+      handle.block_on(crawl
         .crawl_paths())
         /* TODO: better error! */
         .map_err(|e| PyException::new_err(format!("{}", e)))
-        .map(|cr| cr.into());
-      ret
+    })?;
+    warn_skipped_files(py, &crawl_result.skipped_files)?;
+    Ok(crawl_result.into())
+  }
+
+  #[cfg(feature = "asyncio")]
+  fn crawl_zip<'a>(
+    &self,
+    py: Python<'a>,
+    output_zip: ZipFileWriter,
+    zip_options: Option<ZipOutputOptions>,
+    modifications: Option<EntryModifications>,
+    tuning: Option<PipelineTuning>,
+  ) -> PyResult<&'a PyAny> {
+    let crawl: lib_crawl::MedusaCrawl = self.clone().into();
+    let zip_options: lib_zip::ZipOutputOptions = zip_options.unwrap_or_default().try_into()?;
+    let modifications: lib_zip::EntryModifications = modifications.unwrap_or_default().into();
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
+    let ZipFileWriter {
+      output_path,
+      zip_writer,
+    } = output_zip;
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+      let (zip_writer, skipped_files) = crawl
+        .crawl_zip(zip_options, modifications, zip_writer, tuning)
+        .await
+        /* TODO: better error! */
+        .map_err(|e| PyException::new_err(format!("{}", e)))?;
+      Python::with_gil(|py| warn_skipped_files(py, &skipped_files))?;
+      let output_zip = ZipFileWriter {
+        output_path,
+        zip_writer,
+      };
+      Ok::<_, PyErr>(output_zip)
+    })
+  }
+
+  #[cfg(feature = "sync")]
+  fn crawl_zip_sync(
+    &self,
+    py: Python,
+    output_zip: ZipFileWriter,
+    zip_options: Option<ZipOutputOptions>,
+    modifications: Option<EntryModifications>,
+    tuning: Option<PipelineTuning>,
+  ) -> PyResult<ZipFileWriter> {
+    let handle = crate::TOKIO_RUNTIME.handle();
+    let crawl: lib_crawl::MedusaCrawl = self.clone().into();
+    let zip_options: lib_zip::ZipOutputOptions = zip_options.unwrap_or_default().try_into()?;
+    let modifications: lib_zip::EntryModifications = modifications.unwrap_or_default().into();
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
+    let ZipFileWriter {
+      output_path,
+      zip_writer,
+    } = output_zip;
+    let (zip_writer, skipped_files) = py.allow_threads(move || {
+      handle
+        .block_on(crawl.crawl_zip(zip_options, modifications, zip_writer, tuning))
+        /* TODO: better error! */
+        .map_err(|e| PyException::new_err(format!("{}", e)))
+    })?;
+    warn_skipped_files(py, &skipped_files)?;
+    Ok(ZipFileWriter {
+      output_path,
+      zip_writer,
     })
   }
 }
@@ -279,11 +1026,30 @@ impl From<MedusaCrawl> for lib_crawl::MedusaCrawl {
       paths_to_crawl,
       ignores,
       cwd,
+      symlink_behavior,
+      symlink_cycle_policy,
+      max_depth,
+      special_file_policy,
+      ignore_file_names,
+      source,
+      max_open_files,
+      digest,
+      filters,
     } = x;
     Self {
       paths_to_crawl,
       ignores: ignores.into(),
+      filters: filters.into(),
       cwd,
+      symlink_behavior: symlink_behavior.into(),
+      symlink_cycle_policy: symlink_cycle_policy.into(),
+      max_depth,
+      special_file_policy: special_file_policy.into(),
+      ignore_file_names,
+      source: source.into(),
+      max_open_files: NonZeroUsize::new(max_open_files)
+        .expect("max_open_files was validated in the constructor"),
+      digest: digest.map(lib_digest::DigestAlgorithm::from),
     }
   }
 }
@@ -291,9 +1057,19 @@ impl From<MedusaCrawl> for lib_crawl::MedusaCrawl {
 pub(crate) fn crawl_module(py: Python<'_>) -> PyResult<&PyModule> {
   let crawl = PyModule::new(py, "crawl")?;
 
+  crawl.add_class::<EntryKind>()?;
+  crawl.add_class::<EntryMetadata>()?;
+  crawl.add_class::<DigestAlgorithm>()?;
+  crawl.add_class::<ContentDigest>()?;
   crawl.add_class::<ResolvedPath>()?;
+  crawl.add_class::<ResolvedSymlink>()?;
   crawl.add_class::<CrawlResult>()?;
+  crawl.add_class::<SymlinkBehavior>()?;
+  crawl.add_class::<SymlinkCyclePolicy>()?;
+  crawl.add_class::<SpecialFilePolicy>()?;
+  crawl.add_class::<CrawlSource>()?;
   crawl.add_class::<Ignores>()?;
+  crawl.add_class::<FileFilters>()?;
   crawl.add_class::<MedusaCrawl>()?;
 
   Ok(crawl)
@@ -114,13 +114,17 @@ impl TryFrom<FileSource> for lib::FileSource {
   fn try_from(x: FileSource) -> Result<Self, Self::Error> {
     let FileSource { name, source } = x;
     let name: lib::EntryName = name.try_into()?;
-    Ok(Self { name, source })
+    Ok(Self {
+      name,
+      source,
+      metadata: None,
+    })
   }
 }
 
 impl From<lib::FileSource> for FileSource {
   fn from(x: lib::FileSource) -> Self {
-    let lib::FileSource { name, source } = x;
+    let lib::FileSource { name, source, .. } = x;
     Self {
       name: name.into(),
       source,
@@ -129,6 +133,48 @@ impl From<lib::FileSource> for FileSource {
 }
 
 
+#[pyclass]
+#[derive(Clone)]
+pub struct SymlinkSource {
+  #[pyo3(get)]
+  pub name: EntryName,
+  #[pyo3(get)]
+  pub target: String,
+}
+
+#[pymethods]
+impl SymlinkSource {
+  #[new]
+  fn new(name: EntryName, target: String) -> Self { Self { name, target } }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let Self { name, target } = self;
+    let name = crate::util::repr(py, name.clone())?;
+    Ok(format!("SymlinkSource(name={}, target={:?})", name, target))
+  }
+}
+
+impl TryFrom<SymlinkSource> for lib::SymlinkSource {
+  type Error = lib::MedusaNameFormatError;
+
+  fn try_from(x: SymlinkSource) -> Result<Self, Self::Error> {
+    let SymlinkSource { name, target } = x;
+    let name: lib::EntryName = name.try_into()?;
+    Ok(Self { name, target })
+  }
+}
+
+impl From<lib::SymlinkSource> for SymlinkSource {
+  fn from(x: lib::SymlinkSource) -> Self {
+    let lib::SymlinkSource { name, target } = x;
+    Self {
+      name: name.into(),
+      target,
+    }
+  }
+}
+
+
 /* TODO: consider adding TailTasks as in pants's task_executor subcrate in
  * case we ever end up spawning further background tasks or whatever. */
 #[cfg(feature = "sync")]
@@ -162,6 +208,7 @@ fn pymedusa_zip(py: Python<'_>, medusa_zip: &PyModule) -> PyResult<()> {
 
   medusa_zip.add_class::<EntryName>()?;
   medusa_zip.add_class::<FileSource>()?;
+  medusa_zip.add_class::<SymlinkSource>()?;
 
   Ok(())
 }
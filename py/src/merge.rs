@@ -9,7 +9,12 @@
 
 //! ???
 
-use crate::{destination::ZipFileWriter, util::repr, zip::ModifiedTimeBehavior, EntryName};
+use crate::{
+  destination::ZipFileWriter,
+  util::repr,
+  zip::{ModifiedTimeBehavior, PipelineTuning},
+  EntryName,
+};
 
 use libmedusa_zip::{self as lib, merge as lib_merge, zip as lib_zip};
 
@@ -120,6 +125,7 @@ impl MedusaMerge {
     py: Python<'a>,
     mtime_behavior: ModifiedTimeBehavior,
     output_zip: ZipFileWriter,
+    tuning: Option<PipelineTuning>,
   ) -> PyResult<&'a PyAny> {
     let merge: lib_merge::MedusaMerge = self
       .clone()
@@ -127,13 +133,14 @@ impl MedusaMerge {
       /* TODO: better error! */
       .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
     let mtime_behavior: lib_zip::ModifiedTimeBehavior = mtime_behavior.into();
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
     let ZipFileWriter {
       output_path,
       zip_writer,
     } = output_zip;
     pyo3_asyncio::tokio::future_into_py(py, async move {
       let zip_writer = merge
-        .merge(mtime_behavior, zip_writer)
+        .merge(mtime_behavior, zip_writer, tuning)
         .await
         /* TODO: better error! */
         .map_err(|e| PyException::new_err(format!("{}", e)))?;
@@ -151,6 +158,7 @@ impl MedusaMerge {
     py: Python,
     mtime_behavior: ModifiedTimeBehavior,
     output_zip: ZipFileWriter,
+    tuning: Option<PipelineTuning>,
   ) -> PyResult<ZipFileWriter> {
     let handle = crate::TOKIO_RUNTIME.handle();
     let merge: lib_merge::MedusaMerge = self
@@ -159,12 +167,13 @@ impl MedusaMerge {
       /* TODO: better error! */
       .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
     let mtime_behavior: lib_zip::ModifiedTimeBehavior = mtime_behavior.into();
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
     let ZipFileWriter {
       output_path,
       zip_writer,
     } = output_zip;
     py.allow_threads(move || {
-      let zip_writer = handle.block_on(merge.merge(mtime_behavior, zip_writer))
+      let zip_writer = handle.block_on(merge.merge(mtime_behavior, zip_writer, tuning))
         /* TODO: better error! */
         .map_err(|e| PyException::new_err(format!("{}", e)))?;
       let output_zip = ZipFileWriter {
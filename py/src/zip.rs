@@ -9,9 +9,9 @@
 
 //! ???
 
-use crate::{destination::ZipFileWriter, util::repr, FileSource};
+use crate::{destination::ZipFileWriter, util::repr, FileSource, SymlinkSource};
 
-use libmedusa_zip::{self as lib, zip as lib_zip};
+use libmedusa_zip::{self as lib, cache as lib_cache, zip as lib_zip};
 
 use pyo3::{
   exceptions::{PyException, PyValueError},
@@ -21,6 +21,11 @@ use pyo3::{
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use zip::DateTime as ZipDateTime;
 
+use std::{
+  num::{NonZeroU64, NonZeroUsize},
+  path::PathBuf,
+};
+
 
 #[pyclass]
 #[derive(Copy, Clone, Default)]
@@ -236,6 +241,8 @@ impl From<lib_zip::ModifiedTimeBehavior> for ModifiedTimeBehavior {
 pub enum CompressionMethod {
   Stored,
   Deflated,
+  Bzip2,
+  Zstd,
 }
 
 impl Default for CompressionMethod {
@@ -254,6 +261,8 @@ impl From<CompressionMethod> for lib_zip::CompressionMethod {
     match x {
       CompressionMethod::Stored => Self::Stored,
       CompressionMethod::Deflated => Self::Deflated,
+      CompressionMethod::Bzip2 => Self::Bzip2,
+      CompressionMethod::Zstd => Self::Zstd,
     }
   }
 }
@@ -263,6 +272,8 @@ impl From<lib_zip::CompressionMethod> for CompressionMethod {
     match x {
       lib_zip::CompressionMethod::Stored => Self::Stored,
       lib_zip::CompressionMethod::Deflated => Self::Deflated,
+      lib_zip::CompressionMethod::Bzip2 => Self::Bzip2,
+      lib_zip::CompressionMethod::Zstd => Self::Zstd,
     }
   }
 }
@@ -313,25 +324,154 @@ impl TryFrom<CompressionOptions> for lib_zip::CompressionStrategy {
 
 impl From<lib_zip::CompressionStrategy> for CompressionOptions {
   fn from(x: lib_zip::CompressionStrategy) -> Self {
-    let (method, level) = match x {
-      lib_zip::CompressionStrategy::Stored => (CompressionMethod::Stored, None),
-      /* TODO: avoid unchecked cast here! */
-      lib_zip::CompressionStrategy::Deflated(level) => {
-        (CompressionMethod::Deflated, level.map(|l| l as i8))
-      },
-    };
+    let method: CompressionMethod = x.method().into();
+    let level = x.level();
     Self { method, level }
   }
 }
 
 
 #[pyclass]
-#[derive(Copy, Clone)]
+#[derive(Clone)]
+pub struct CompressionRule {
+  #[pyo3(get)]
+  pub compression_options: CompressionOptions,
+  #[pyo3(get)]
+  pub glob: Option<String>,
+  #[pyo3(get)]
+  pub regex: Option<String>,
+  #[pyo3(get)]
+  pub min_size: Option<u64>,
+  #[pyo3(get)]
+  pub max_size: Option<u64>,
+}
+
+#[pymethods]
+impl CompressionRule {
+  #[new]
+  fn new(
+    compression_options: CompressionOptions,
+    glob: Option<String>,
+    regex: Option<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+  ) -> PyResult<Self> {
+    if glob.is_some() && regex.is_some() {
+      return Err(PyValueError::new_err(format!(
+        "at most one of glob={:?} or regex={:?} may be provided",
+        glob, regex
+      )));
+    }
+    Ok(Self {
+      compression_options,
+      glob,
+      regex,
+      min_size,
+      max_size,
+    })
+  }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let Self {
+      compression_options,
+      glob,
+      regex,
+      min_size,
+      max_size,
+    } = self;
+    let compression_options = repr(py, *compression_options)?;
+    let glob = repr(py, glob.clone())?;
+    let regex = repr(py, regex.clone())?;
+    let min_size = repr(py, *min_size)?;
+    let max_size = repr(py, *max_size)?;
+    Ok(format!(
+      "CompressionRule(compression_options={}, glob={}, regex={}, min_size={}, max_size={})",
+      compression_options, glob, regex, min_size, max_size
+    ))
+  }
+}
+
+impl TryFrom<CompressionRule> for lib_zip::CompressionRule {
+  type Error = PyErr;
+
+  fn try_from(x: CompressionRule) -> Result<Self, Self::Error> {
+    let CompressionRule {
+      compression_options,
+      glob,
+      regex,
+      min_size,
+      max_size,
+    } = x;
+    let strategy: lib_zip::CompressionStrategy = compression_options
+      .try_into()
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let name_pattern = match (glob, regex) {
+      (Some(glob), _) => Some(
+        lib_zip::EntryNamePattern::glob(&glob)
+          /* TODO: better error! */
+          .map_err(|e| PyValueError::new_err(format!("{}", e)))?,
+      ),
+      (None, Some(regex)) => Some(
+        lib_zip::EntryNamePattern::regex(&regex)
+          /* TODO: better error! */
+          .map_err(|e| PyValueError::new_err(format!("{}", e)))?,
+      ),
+      (None, None) => None,
+    };
+    Ok(Self {
+      name_pattern,
+      min_size,
+      max_size,
+      strategy,
+    })
+  }
+}
+
+impl From<lib_zip::CompressionRule> for CompressionRule {
+  fn from(x: lib_zip::CompressionRule) -> Self {
+    let lib_zip::CompressionRule {
+      name_pattern,
+      min_size,
+      max_size,
+      strategy,
+    } = x;
+    let compression_options: CompressionOptions = strategy.into();
+    let (glob, regex) = match name_pattern {
+      Some(lib_zip::EntryNamePattern::Glob(matcher)) => (Some(matcher.glob().to_string()), None),
+      Some(lib_zip::EntryNamePattern::Regex(pattern)) => (None, Some(pattern.as_str().to_string())),
+      None => (None, None),
+    };
+    Self {
+      compression_options,
+      glob,
+      regex,
+      min_size,
+      max_size,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Clone)]
 pub struct ZipOutputOptions {
   #[pyo3(get)]
   pub mtime_behavior: ModifiedTimeBehavior,
   #[pyo3(get)]
   pub compression_options: CompressionOptions,
+  #[pyo3(get)]
+  pub compression_rules: Vec<CompressionRule>,
+  #[pyo3(get)]
+  pub incompressible_min_savings: Option<f64>,
+  #[pyo3(get)]
+  pub parallel_deflate_min_size: Option<u64>,
+  #[pyo3(get)]
+  pub parallel_deflate_block_size: usize,
+  #[pyo3(get)]
+  pub entry_cache_dir: Option<PathBuf>,
+  #[pyo3(get)]
+  pub entry_cache_max_size: u64,
 }
 
 impl Default for ZipOutputOptions {
@@ -344,12 +484,32 @@ impl ZipOutputOptions {
   fn new(
     mtime_behavior: Option<ModifiedTimeBehavior>,
     compression_options: Option<CompressionOptions>,
+    compression_rules: Option<Vec<CompressionRule>>,
+    incompressible_min_savings: Option<f64>,
+    parallel_deflate_min_size: Option<u64>,
+    parallel_deflate_block_size: Option<usize>,
+    entry_cache_dir: Option<PathBuf>,
+    entry_cache_max_size: Option<u64>,
   ) -> Self {
     let mtime_behavior = mtime_behavior.unwrap_or_default();
     let compression_options = compression_options.unwrap_or_default();
+    let compression_rules = compression_rules.unwrap_or_else(|| {
+      let lib_zip::CompressionPolicy { rules } = lib_zip::CompressionPolicy::default();
+      rules.into_iter().map(CompressionRule::from).collect()
+    });
+    let parallel_deflate_block_size = parallel_deflate_block_size
+      .unwrap_or_else(|| lib_zip::ParallelDeflate::default().block_size.get());
+    let entry_cache_max_size =
+      entry_cache_max_size.unwrap_or(lib_cache::EntryCache::DEFAULT_MAX_SIZE);
     Self {
       mtime_behavior,
       compression_options,
+      compression_rules,
+      incompressible_min_savings,
+      parallel_deflate_min_size,
+      parallel_deflate_block_size,
+      entry_cache_dir,
+      entry_cache_max_size,
     }
   }
 
@@ -361,30 +521,73 @@ impl ZipOutputOptions {
     let Self {
       mtime_behavior,
       compression_options,
+      compression_rules,
+      incompressible_min_savings,
+      parallel_deflate_min_size,
+      parallel_deflate_block_size,
+      entry_cache_dir,
+      entry_cache_max_size,
     } = self;
     let mtime_behavior = repr(py, *mtime_behavior)?;
     let compression_options = repr(py, *compression_options)?;
+    let compression_rules = repr(py, compression_rules.clone())?;
+    let incompressible_min_savings = repr(py, *incompressible_min_savings)?;
+    let parallel_deflate_min_size = repr(py, *parallel_deflate_min_size)?;
+    let entry_cache_dir = repr(py, entry_cache_dir.clone())?;
     Ok(format!(
-      "ZipOutputOptions(mtime_behavior={}, compression_options={})",
-      mtime_behavior, compression_options
+      "ZipOutputOptions(mtime_behavior={}, compression_options={}, compression_rules={}, incompressible_min_savings={}, parallel_deflate_min_size={}, parallel_deflate_block_size={}, entry_cache_dir={}, entry_cache_max_size={})",
+      mtime_behavior, compression_options, compression_rules, incompressible_min_savings, parallel_deflate_min_size, parallel_deflate_block_size, entry_cache_dir, entry_cache_max_size
     ))
   }
 }
 
 
 impl TryFrom<ZipOutputOptions> for lib_zip::ZipOutputOptions {
-  type Error = lib_zip::ParseCompressionOptionsError;
+  type Error = PyErr;
 
   fn try_from(x: ZipOutputOptions) -> Result<Self, Self::Error> {
     let ZipOutputOptions {
       mtime_behavior,
       compression_options,
+      compression_rules,
+      incompressible_min_savings,
+      parallel_deflate_min_size,
+      parallel_deflate_block_size,
+      entry_cache_dir,
+      entry_cache_max_size,
     } = x;
     let mtime_behavior: lib_zip::ModifiedTimeBehavior = mtime_behavior.into();
-    let compression_options: lib_zip::CompressionStrategy = compression_options.try_into()?;
+    let compression_options: lib_zip::CompressionStrategy = compression_options
+      .try_into()
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let rules: Vec<lib_zip::CompressionRule> = compression_rules
+      .into_iter()
+      .map(lib_zip::CompressionRule::try_from)
+      .collect::<PyResult<_>>()?;
+    let incompressible_detection = incompressible_min_savings
+      .map(lib_zip::IncompressibleDetection::new)
+      .transpose()
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let parallel_deflate = match parallel_deflate_min_size {
+      None => None,
+      Some(min_size) => Some(lib_zip::ParallelDeflate {
+        min_size,
+        block_size: NonZeroUsize::new(parallel_deflate_block_size).ok_or_else(|| {
+          PyValueError::new_err("parallel_deflate_block_size must be greater than 0")
+        })?,
+      }),
+    };
+    let entry_cache =
+      entry_cache_dir.map(|dir| lib_cache::EntryCache::new(dir, entry_cache_max_size));
     Ok(Self {
       mtime_behavior,
       compression_options,
+      compression_policy: lib_zip::CompressionPolicy { rules },
+      incompressible_detection,
+      parallel_deflate,
+      entry_cache,
     })
   }
 }
@@ -394,17 +597,88 @@ impl From<lib_zip::ZipOutputOptions> for ZipOutputOptions {
     let lib_zip::ZipOutputOptions {
       mtime_behavior,
       compression_options,
+      compression_policy: lib_zip::CompressionPolicy { rules },
+      incompressible_detection,
+      parallel_deflate,
+      entry_cache,
     } = x;
     let mtime_behavior: ModifiedTimeBehavior = mtime_behavior.into();
     let compression_options: CompressionOptions = compression_options.into();
+    let compression_rules: Vec<CompressionRule> =
+      rules.into_iter().map(CompressionRule::from).collect();
+    let incompressible_min_savings = incompressible_detection.map(|d| d.min_savings());
+    let lib_zip::ParallelDeflate {
+      min_size,
+      block_size,
+    } = parallel_deflate.unwrap_or_default();
+    let parallel_deflate_min_size = parallel_deflate.map(|_| min_size);
+    let (entry_cache_dir, entry_cache_max_size) = match entry_cache {
+      Some(entry_cache) => (
+        Some(entry_cache.dir().to_path_buf()),
+        entry_cache.max_size(),
+      ),
+      None => (None, lib_cache::EntryCache::DEFAULT_MAX_SIZE),
+    };
     Self {
       mtime_behavior,
       compression_options,
+      compression_rules,
+      incompressible_min_savings,
+      parallel_deflate_min_size,
+      parallel_deflate_block_size: block_size.get(),
+      entry_cache_dir,
+      entry_cache_max_size,
     }
   }
 }
 
 
+#[pyclass]
+#[derive(Clone)]
+pub struct NameRewrite(pub lib_zip::NameRewrite);
+
+#[pymethods]
+impl NameRewrite {
+  #[classmethod]
+  fn strip_prefix(_cls: &PyType, prefix: String) -> PyResult<Self> {
+    Ok(Self(lib_zip::NameRewrite::StripPrefix(Self::parse_prefix(
+      prefix,
+    )?)))
+  }
+
+  #[classmethod]
+  fn add_prefix(_cls: &PyType, prefix: String) -> PyResult<Self> {
+    Ok(Self(lib_zip::NameRewrite::AddPrefix(Self::parse_prefix(
+      prefix,
+    )?)))
+  }
+
+  #[classmethod]
+  fn replace(_cls: &PyType, pattern: &str, replacement: String) -> PyResult<Self> {
+    let pattern = regex::Regex::new(pattern)
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    Ok(Self(lib_zip::NameRewrite::Replace {
+      pattern,
+      replacement,
+    }))
+  }
+
+  fn __repr__(&self) -> String {
+    let Self(rewrite) = self;
+    format!("NameRewrite({:?})", rewrite.to_string())
+  }
+}
+
+impl NameRewrite {
+  fn parse_prefix(prefix: String) -> PyResult<lib::EntryName> {
+    lib::EntryName::validate(prefix)
+      /* TODO: better error! */
+      .map_err(|e| PyValueError::new_err(format!("{}", e)))
+  }
+}
+
+
 #[pyclass]
 #[derive(Clone)]
 pub struct EntryModifications {
@@ -412,6 +686,8 @@ pub struct EntryModifications {
   pub silent_external_prefix: Option<String>,
   #[pyo3(get)]
   pub own_prefix: Option<String>,
+  #[pyo3(get)]
+  pub rewrites: Vec<NameRewrite>,
 }
 
 impl Default for EntryModifications {
@@ -421,10 +697,16 @@ impl Default for EntryModifications {
 #[pymethods]
 impl EntryModifications {
   #[new]
-  fn new(silent_external_prefix: Option<String>, own_prefix: Option<String>) -> Self {
+  #[pyo3(signature = (silent_external_prefix=None, own_prefix=None, rewrites=None))]
+  fn new(
+    silent_external_prefix: Option<String>,
+    own_prefix: Option<String>,
+    rewrites: Option<Vec<NameRewrite>>,
+  ) -> Self {
     Self {
       silent_external_prefix,
       own_prefix,
+      rewrites: rewrites.unwrap_or_default(),
     }
   }
 
@@ -436,15 +718,19 @@ impl EntryModifications {
     let Self {
       silent_external_prefix,
       own_prefix,
+      rewrites,
     } = self;
     let silent_external_prefix = silent_external_prefix
       .as_ref()
       .map(|s| s.as_str())
       .unwrap_or("None");
     let own_prefix = own_prefix.as_ref().map(|s| s.as_str()).unwrap_or("None");
+    let rewrites: Vec<String> = rewrites.iter().map(|r| r.__repr__()).collect();
     format!(
-      "EntryModifications(silent_external_prefix={}, own_prefix={})",
-      silent_external_prefix, own_prefix
+      "EntryModifications(silent_external_prefix={}, own_prefix={}, rewrites=[{}])",
+      silent_external_prefix,
+      own_prefix,
+      rewrites.join(", ")
     )
   }
 }
@@ -454,10 +740,12 @@ impl From<lib_zip::EntryModifications> for EntryModifications {
     let lib_zip::EntryModifications {
       silent_external_prefix,
       own_prefix,
+      rewrites,
     } = x;
     Self {
       silent_external_prefix,
       own_prefix,
+      rewrites: rewrites.into_iter().map(NameRewrite).collect(),
     }
   }
 }
@@ -467,10 +755,15 @@ impl From<EntryModifications> for lib_zip::EntryModifications {
     let EntryModifications {
       silent_external_prefix,
       own_prefix,
+      rewrites,
     } = x;
     Self {
       silent_external_prefix,
       own_prefix,
+      rewrites: rewrites
+        .into_iter()
+        .map(|NameRewrite(rewrite)| rewrite)
+        .collect(),
     }
   }
 }
@@ -481,6 +774,8 @@ impl From<EntryModifications> for lib_zip::EntryModifications {
 pub enum Parallelism {
   Synchronous,
   ParallelMerge,
+  ParallelTreeMerge,
+  PerEntry,
 }
 
 impl Default for Parallelism {
@@ -499,6 +794,8 @@ impl From<lib_zip::Parallelism> for Parallelism {
     match x {
       lib_zip::Parallelism::Synchronous => Self::Synchronous,
       lib_zip::Parallelism::ParallelMerge => Self::ParallelMerge,
+      lib_zip::Parallelism::ParallelTreeMerge => Self::ParallelTreeMerge,
+      lib_zip::Parallelism::PerEntry => Self::PerEntry,
     }
   }
 }
@@ -508,6 +805,178 @@ impl From<Parallelism> for lib_zip::Parallelism {
     match x {
       Parallelism::Synchronous => Self::Synchronous,
       Parallelism::ParallelMerge => Self::ParallelMerge,
+      Parallelism::ParallelTreeMerge => Self::ParallelTreeMerge,
+      Parallelism::PerEntry => Self::PerEntry,
+    }
+  }
+}
+
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PipelineTuning {
+  #[pyo3(get)]
+  pub intermediate_chunk_size: usize,
+  #[pyo3(get)]
+  pub intermediate_chunk_bytes: u64,
+  #[pyo3(get)]
+  pub max_parallel_intermediates: usize,
+  #[pyo3(get)]
+  pub per_intermediate_file_io_queue_length: usize,
+  #[pyo3(get)]
+  pub intermediate_output_spool_threshold: usize,
+  #[pyo3(get)]
+  pub per_file_spool_threshold: usize,
+  #[pyo3(get)]
+  pub parallel_merge_entries: usize,
+  #[pyo3(get)]
+  pub tree_merge_final_width: usize,
+  #[pyo3(get)]
+  pub temp_dir: Option<PathBuf>,
+  #[pyo3(get)]
+  pub max_open_files: usize,
+}
+
+impl Default for PipelineTuning {
+  fn default() -> Self { lib_zip::PipelineTuning::default().into() }
+}
+
+#[pymethods]
+impl PipelineTuning {
+  #[new]
+  fn new(
+    intermediate_chunk_size: Option<usize>,
+    intermediate_chunk_bytes: Option<u64>,
+    max_parallel_intermediates: Option<usize>,
+    per_intermediate_file_io_queue_length: Option<usize>,
+    intermediate_output_spool_threshold: Option<usize>,
+    per_file_spool_threshold: Option<usize>,
+    parallel_merge_entries: Option<usize>,
+    tree_merge_final_width: Option<usize>,
+    temp_dir: Option<PathBuf>,
+    max_open_files: Option<usize>,
+  ) -> Self {
+    let default = Self::default();
+    Self {
+      intermediate_chunk_size: intermediate_chunk_size.unwrap_or(default.intermediate_chunk_size),
+      intermediate_chunk_bytes: intermediate_chunk_bytes
+        .unwrap_or(default.intermediate_chunk_bytes),
+      max_parallel_intermediates: max_parallel_intermediates
+        .unwrap_or(default.max_parallel_intermediates),
+      per_intermediate_file_io_queue_length: per_intermediate_file_io_queue_length
+        .unwrap_or(default.per_intermediate_file_io_queue_length),
+      intermediate_output_spool_threshold: intermediate_output_spool_threshold
+        .unwrap_or(default.intermediate_output_spool_threshold),
+      per_file_spool_threshold: per_file_spool_threshold
+        .unwrap_or(default.per_file_spool_threshold),
+      parallel_merge_entries: parallel_merge_entries.unwrap_or(default.parallel_merge_entries),
+      tree_merge_final_width: tree_merge_final_width.unwrap_or(default.tree_merge_final_width),
+      temp_dir,
+      max_open_files: max_open_files.unwrap_or(default.max_open_files),
+    }
+  }
+
+  #[classmethod]
+  #[pyo3(name = "default")]
+  fn py_default(_cls: &PyType) -> Self { Self::default() }
+
+  fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+    let Self {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    } = self;
+    let temp_dir = repr(py, temp_dir.clone())?;
+    Ok(format!(
+      "PipelineTuning(intermediate_chunk_size={}, intermediate_chunk_bytes={}, max_parallel_intermediates={}, per_intermediate_file_io_queue_length={}, intermediate_output_spool_threshold={}, per_file_spool_threshold={}, parallel_merge_entries={}, tree_merge_final_width={}, temp_dir={}, max_open_files={})",
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    ))
+  }
+}
+
+impl TryFrom<PipelineTuning> for lib_zip::PipelineTuning {
+  type Error = PyErr;
+
+  fn try_from(x: PipelineTuning) -> Result<Self, Self::Error> {
+    let PipelineTuning {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    } = x;
+    let non_zero = |name: &str, value: usize| {
+      NonZeroUsize::new(value)
+        .ok_or_else(|| PyValueError::new_err(format!("{} must be greater than 0", name)))
+    };
+    Ok(Self {
+      intermediate_chunk_size: non_zero("intermediate_chunk_size", intermediate_chunk_size)?,
+      intermediate_chunk_bytes: NonZeroU64::new(intermediate_chunk_bytes)
+        .ok_or_else(|| PyValueError::new_err("intermediate_chunk_bytes must be greater than 0"))?,
+      max_parallel_intermediates: non_zero(
+        "max_parallel_intermediates",
+        max_parallel_intermediates,
+      )?,
+      per_intermediate_file_io_queue_length: non_zero(
+        "per_intermediate_file_io_queue_length",
+        per_intermediate_file_io_queue_length,
+      )?,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries: non_zero("parallel_merge_entries", parallel_merge_entries)?,
+      tree_merge_final_width: non_zero("tree_merge_final_width", tree_merge_final_width)?,
+      temp_dir,
+      max_open_files: non_zero("max_open_files", max_open_files)?,
+    })
+  }
+}
+
+impl From<lib_zip::PipelineTuning> for PipelineTuning {
+  fn from(x: lib_zip::PipelineTuning) -> Self {
+    let lib_zip::PipelineTuning {
+      intermediate_chunk_size,
+      intermediate_chunk_bytes,
+      max_parallel_intermediates,
+      per_intermediate_file_io_queue_length,
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries,
+      tree_merge_final_width,
+      temp_dir,
+      max_open_files,
+    } = x;
+    Self {
+      intermediate_chunk_size: intermediate_chunk_size.get(),
+      intermediate_chunk_bytes: intermediate_chunk_bytes.get(),
+      max_parallel_intermediates: max_parallel_intermediates.get(),
+      per_intermediate_file_io_queue_length: per_intermediate_file_io_queue_length.get(),
+      intermediate_output_spool_threshold,
+      per_file_spool_threshold,
+      parallel_merge_entries: parallel_merge_entries.get(),
+      tree_merge_final_width: tree_merge_final_width.get(),
+      temp_dir,
+      max_open_files: max_open_files.get(),
     }
   }
 }
@@ -520,6 +989,7 @@ pub struct MedusaZip {
   pub zip_options: ZipOutputOptions,
   pub modifications: EntryModifications,
   pub parallelism: Parallelism,
+  pub input_symlinks: Vec<SymlinkSource>,
 }
 
 #[pymethods]
@@ -530,6 +1000,7 @@ impl MedusaZip {
     zip_options: Option<ZipOutputOptions>,
     modifications: Option<EntryModifications>,
     parallelism: Option<Parallelism>,
+    input_symlinks: Option<&PyAny>,
   ) -> PyResult<Self> {
     let zip_options = zip_options.unwrap_or_default();
     let modifications = modifications.unwrap_or_default();
@@ -538,11 +1009,21 @@ impl MedusaZip {
       .iter()?
       .map(|f| f.and_then(PyAny::extract::<FileSource>))
       .collect::<PyResult<_>>()?;
+    let input_symlinks: Vec<SymlinkSource> = input_symlinks
+      .map(|input_symlinks| {
+        input_symlinks
+          .iter()?
+          .map(|s| s.and_then(PyAny::extract::<SymlinkSource>))
+          .collect::<PyResult<_>>()
+      })
+      .transpose()?
+      .unwrap_or_default();
     Ok(Self {
       input_files,
       zip_options,
       modifications,
       parallelism,
+      input_symlinks,
     })
   }
 
@@ -552,27 +1033,35 @@ impl MedusaZip {
       zip_options,
       modifications,
       parallelism,
+      input_symlinks,
     } = self;
     let input_files = repr(py, input_files.clone())?;
-    let zip_options = repr(py, *zip_options)?;
+    let zip_options = repr(py, zip_options.clone())?;
     let modifications = repr(py, modifications.clone())?;
     let parallelism = repr(py, *parallelism)?;
+    let input_symlinks = repr(py, input_symlinks.clone())?;
     Ok(format!(
-      "MedusaZip(input_files={}, zip_options={}, modifications={}, parallelism={})",
-      input_files, zip_options, modifications, parallelism
+      "MedusaZip(input_files={}, zip_options={}, modifications={}, parallelism={}, input_symlinks={})",
+      input_files, zip_options, modifications, parallelism, input_symlinks
     ))
   }
 
   #[cfg(feature = "asyncio")]
-  fn zip<'a>(&self, py: Python<'a>, output_zip: ZipFileWriter) -> PyResult<&'a PyAny> {
+  fn zip<'a>(
+    &self,
+    py: Python<'a>,
+    output_zip: ZipFileWriter,
+    tuning: Option<PipelineTuning>,
+  ) -> PyResult<&'a PyAny> {
     let zip: lib_zip::MedusaZip = self.clone().try_into()?;
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
     let ZipFileWriter {
       output_path,
       zip_writer,
     } = output_zip;
     pyo3_asyncio::tokio::future_into_py(py, async move {
       /* TODO: make a wrapper for this packing/unpacking of ZipFileWriter! */
-      let zip_writer = zip.zip(zip_writer)
+      let zip_writer = zip.zip(zip_writer, tuning)
         .await
         /* TODO: better error! */
         .map_err(|e| PyException::new_err(format!("{}", e)))?;
@@ -585,15 +1074,21 @@ impl MedusaZip {
   }
 
   #[cfg(feature = "sync")]
-  fn zip_sync(&self, py: Python, output_zip: ZipFileWriter) -> PyResult<ZipFileWriter> {
+  fn zip_sync(
+    &self,
+    py: Python,
+    output_zip: ZipFileWriter,
+    tuning: Option<PipelineTuning>,
+  ) -> PyResult<ZipFileWriter> {
     let handle = crate::TOKIO_RUNTIME.handle();
     let zip: lib_zip::MedusaZip = self.clone().try_into()?;
+    let tuning: lib_zip::PipelineTuning = tuning.unwrap_or_default().try_into()?;
     let ZipFileWriter {
       output_path,
       zip_writer,
     } = output_zip;
     py.allow_threads(move || {
-      let zip_writer = handle.block_on(zip.zip(zip_writer))
+      let zip_writer = handle.block_on(zip.zip(zip_writer, tuning))
         /* TODO: better error! */
         .map_err(|e| PyException::new_err(format!("{}", e)))?;
       let output_zip = ZipFileWriter {
@@ -615,6 +1110,7 @@ impl TryFrom<MedusaZip> for lib_zip::MedusaZip {
       zip_options,
       modifications,
       parallelism,
+      input_symlinks,
     } = x;
     let input_files: Vec<lib::FileSource> = input_files
       .into_iter()
@@ -622,13 +1118,18 @@ impl TryFrom<MedusaZip> for lib_zip::MedusaZip {
       .collect::<Result<Vec<_>, _>>()
       /* TODO: better error! */
       .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
-    let zip_options: lib_zip::ZipOutputOptions = zip_options.try_into()
+    let input_symlinks: Vec<lib::SymlinkSource> = input_symlinks
+      .into_iter()
+      .map(lib::SymlinkSource::try_from)
+      .collect::<Result<Vec<_>, _>>()
       /* TODO: better error! */
       .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let zip_options: lib_zip::ZipOutputOptions = zip_options.try_into()?;
     let modifications: lib_zip::EntryModifications = modifications.into();
     let parallelism: lib_zip::Parallelism = parallelism.into();
     Ok(Self {
       input_files,
+      input_symlinks,
       zip_options,
       modifications,
       parallelism,
@@ -640,11 +1141,14 @@ impl From<lib_zip::MedusaZip> for MedusaZip {
   fn from(x: lib_zip::MedusaZip) -> Self {
     let lib_zip::MedusaZip {
       input_files,
+      input_symlinks,
       zip_options,
       modifications,
       parallelism,
     } = x;
     let input_files: Vec<FileSource> = input_files.into_iter().map(|fs| fs.into()).collect();
+    let input_symlinks: Vec<SymlinkSource> =
+      input_symlinks.into_iter().map(|ss| ss.into()).collect();
     let zip_options: ZipOutputOptions = zip_options.into();
     let modifications: EntryModifications = modifications.into();
     let parallelism: Parallelism = parallelism.into();
@@ -653,6 +1157,7 @@ impl From<lib_zip::MedusaZip> for MedusaZip {
       zip_options,
       modifications,
       parallelism,
+      input_symlinks,
     }
   }
 }
@@ -666,9 +1171,12 @@ pub(crate) fn zip_module(py: Python<'_>) -> PyResult<&PyModule> {
   zip.add_class::<ModifiedTimeBehavior>()?;
   zip.add_class::<CompressionMethod>()?;
   zip.add_class::<CompressionOptions>()?;
+  zip.add_class::<CompressionRule>()?;
   zip.add_class::<ZipOutputOptions>()?;
+  zip.add_class::<NameRewrite>()?;
   zip.add_class::<EntryModifications>()?;
   zip.add_class::<Parallelism>()?;
+  zip.add_class::<PipelineTuning>()?;
   zip.add_class::<MedusaZip>()?;
 
   Ok(zip)